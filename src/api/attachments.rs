@@ -0,0 +1,440 @@
+//! Named artifacts stored alongside a result, such as a chart spec uploaded
+//! by the caller or a metrics CSV generated from the analysis
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::integration_manager::{
+    authorize_integration, Integration, IntegrationAnalysisResult, IntegrationManager, MAX_CACHED_ATTACHMENT_BYTES,
+};
+use super::store::PendingWrite;
+
+/// Longest accepted attachment name
+const MAX_NAME_CHARS: usize = 128;
+
+/// What a result lists about each of its attachments; the content is
+/// fetched separately
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttachmentInfo {
+    pub name: String,
+    pub content_type: String,
+    /// Content length in bytes
+    pub size: usize,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub info: AttachmentInfo,
+    pub data: Vec<u8>,
+}
+
+impl Attachment {
+    pub fn new(name: &str, content_type: &str, data: Vec<u8>) -> Self {
+        Self {
+            info: AttachmentInfo {
+                name: name.to_string(),
+                content_type: content_type.to_string(),
+                size: data.len(),
+                created_at: Utc::now(),
+            },
+            data,
+        }
+    }
+}
+
+/// Attachment content held in memory, keyed by result id and name. With a
+/// byte limit the least recently stored content is dropped past it, to be
+/// read back from the store on its next download.
+#[derive(Debug, Default)]
+pub struct AttachmentCache {
+    entries: HashMap<(String, String), (u64, Attachment)>,
+    bytes: usize,
+    next_stamp: u64,
+}
+
+impl AttachmentCache {
+    pub fn get(&self, result_id: &str, name: &str) -> Option<&Attachment> {
+        self.entries.get(&(result_id.to_string(), name.to_string())).map(|(_, attachment)| attachment)
+    }
+
+    /// Keep `attachment` for `result_id`, replacing any of the same name,
+    /// then drop the oldest other content while over `max_bytes`
+    pub fn insert(&mut self, result_id: &str, attachment: Attachment, max_bytes: Option<usize>) {
+        let key = (result_id.to_string(), attachment.info.name.clone());
+        self.next_stamp += 1;
+        self.bytes += attachment.data.len();
+        if let Some((_, replaced)) = self.entries.insert(key.clone(), (self.next_stamp, attachment)) {
+            self.bytes -= replaced.data.len();
+        }
+
+        let Some(max_bytes) = max_bytes else {
+            return;
+        };
+        while self.bytes > max_bytes {
+            let oldest = self
+                .entries
+                .iter()
+                .filter(|(cached, _)| **cached != key)
+                .min_by_key(|(_, (stamp, _))| *stamp)
+                .map(|(cached, _)| cached.clone());
+            let Some(oldest) = oldest else {
+                break;
+            };
+            if let Some((_, dropped)) = self.entries.remove(&oldest) {
+                self.bytes -= dropped.data.len();
+            }
+        }
+    }
+
+    /// Forget every attachment of the given results
+    pub fn remove_results(&mut self, result_ids: &[String]) {
+        if result_ids.is_empty() {
+            return;
+        }
+        let bytes = &mut self.bytes;
+        self.entries.retain(|(result_id, _), (_, attachment)| {
+            let keep = !result_ids.contains(result_id);
+            if !keep {
+                *bytes -= attachment.data.len();
+            }
+            keep
+        });
+    }
+
+    /// Bytes of content currently held
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+/// Names are used in URLs and file names, so only letters, digits, `.`, `_`
+/// and `-` are allowed, and not a leading `.`
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.chars().count() > MAX_NAME_CHARS {
+        return Err(format!("Attachment name must be 1 to {} characters", MAX_NAME_CHARS));
+    }
+    if name.starts_with('.') || !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')) {
+        return Err(format!(
+            "Invalid attachment name '{}': use letters, digits, '.', '_' and '-', not starting with '.'",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Attachments an integration has generated from every completed analysis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentGenerator {
+    /// `metrics.csv`: one `metric,value` row per entry of the output's
+    /// `metrics`, nested objects flattened to dotted names
+    MetricsCsv,
+}
+
+impl AttachmentGenerator {
+    /// The attachment for `output`, or `None` when it has nothing to put in one
+    pub fn generate(&self, output: &Value) -> Option<Attachment> {
+        match self {
+            AttachmentGenerator::MetricsCsv => {
+                let metrics = output.get("metrics")?.as_object()?;
+                let mut rows = Vec::new();
+                flatten_metrics("", metrics, &mut rows);
+                if rows.is_empty() {
+                    return None;
+                }
+                let mut csv = String::from("metric,value\n");
+                for (name, value) in rows {
+                    csv.push_str(&format!("{},{}\n", csv_field(&name), csv_field(&value)));
+                }
+                Some(Attachment::new("metrics.csv", "text/csv", csv.into_bytes()))
+            }
+        }
+    }
+}
+
+fn flatten_metrics(prefix: &str, metrics: &serde_json::Map<String, Value>, rows: &mut Vec<(String, String)>) {
+    for (key, value) in metrics {
+        let name = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        match value {
+            Value::Object(nested) => flatten_metrics(&name, nested, rows),
+            Value::String(text) => rows.push((name, text.clone())),
+            Value::Null => rows.push((name, String::new())),
+            other => rows.push((name, other.to_string())),
+        }
+    }
+}
+
+/// Quote a CSV field when it holds a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl IntegrationManager {
+    /// Store `attachment` with a result, replacing any of the same name.
+    /// `None` when the integration has no such result.
+    pub async fn add_attachment(&self, integration_id: &str, result_id: &str, attachment: Attachment) -> Option<AttachmentInfo> {
+        let updated = {
+            let mut results = self.analysis_results.write().await;
+            let result = results.get_mut(integration_id)?.iter_mut().find(|r| r.id == result_id)?;
+            result.attachments.retain(|info| info.name != attachment.info.name);
+            result.attachments.push(attachment.info.clone());
+            self.storage_usage.update(result);
+            result.clone()
+        };
+        let info = attachment.info.clone();
+        self.save_attachment_content(result_id, attachment).await;
+        self.persist(PendingWrite::Result(Box::new(updated))).await;
+        Some(info)
+    }
+
+    /// A result's attachment with its content, from the cache or else the
+    /// store. Errors only when the store cannot be read.
+    pub async fn get_attachment(&self, integration_id: &str, result_id: &str, name: &str) -> Result<Option<Attachment>, String> {
+        let listed = self
+            .analysis_results
+            .read()
+            .await
+            .get(integration_id)
+            .and_then(|results| results.iter().find(|r| r.id == result_id))
+            .is_some_and(|result| result.attachments.iter().any(|info| info.name == name));
+        if !listed {
+            return Ok(None);
+        }
+
+        if let Some(attachment) = self.attachments.read().await.get(result_id, name) {
+            return Ok(Some(attachment.clone()));
+        }
+        let Some(store) = &self.store else {
+            return Ok(None);
+        };
+        let loaded = store.load_attachment(result_id, name).await?;
+        if let Some(attachment) = &loaded {
+            self.attachments.write().await.insert(result_id, attachment.clone(), Some(MAX_CACHED_ATTACHMENT_BYTES));
+        }
+        Ok(loaded)
+    }
+
+    /// Run the integration's attachment generators over a completed result
+    pub(super) async fn generate_attachments(&self, integration: &Integration, result: &mut IntegrationAnalysisResult) {
+        for generator in &integration.configuration.attachments {
+            let Some(attachment) = generator.generate(&result.analysis_result) else {
+                continue;
+            };
+            result.attachments.retain(|info| info.name != attachment.info.name);
+            result.attachments.push(attachment.info.clone());
+            self.save_attachment_content(&result.id, attachment).await;
+        }
+    }
+
+    pub(super) async fn save_attachment_content(&self, result_id: &str, attachment: Attachment) {
+        // Without a store the cache is the only copy, so nothing is dropped
+        let max_bytes = self.store.is_some().then_some(MAX_CACHED_ATTACHMENT_BYTES);
+        self.attachments.write().await.insert(result_id, attachment.clone(), max_bytes);
+        self.persist(PendingWrite::Attachment {
+            result_id: result_id.to_string(),
+            attachment: Box::new(attachment),
+        })
+        .await;
+    }
+}
+
+/// The attachments of a result; needs the integration's API key or the
+/// admin token
+pub(super) async fn list_result_attachments(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path((integration_id, result_id)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<AttachmentInfo>>, StatusCode> {
+    authorize_integration(&manager, &headers, &integration_id).await?;
+    manager
+        .get_analysis_results(&integration_id, None)
+        .await
+        .into_iter()
+        .find(|r| r.id == result_id)
+        .map(|r| Json(r.attachments))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Store the request body as an attachment, typed by its `Content-Type`;
+/// needs the integration's API key or the admin token
+pub(super) async fn upload_result_attachment(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path((integration_id, result_id, name)): Path<(String, String, String)>,
+    headers: axum::http::HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<(StatusCode, Json<AttachmentInfo>), (StatusCode, String)> {
+    authorize_integration(&manager, &headers, &integration_id)
+        .await
+        .map_err(|status| (status, String::new()))?;
+    validate_name(&name).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/octet-stream");
+
+    let attachment = Attachment::new(&name, content_type, body.to_vec());
+    match manager.add_attachment(&integration_id, &result_id, attachment).await {
+        Some(info) => Ok((StatusCode::CREATED, Json(info))),
+        None => Err((StatusCode::NOT_FOUND, format!("Result {} not found", result_id))),
+    }
+}
+
+/// Serve an attachment's content; needs the integration's API key or the
+/// admin token
+pub(super) async fn download_result_attachment(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path((integration_id, result_id, name)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    authorize_integration(&manager, &headers, &integration_id)
+        .await
+        .map_err(|status| (status, String::new()))?;
+    let attachment = manager
+        .get_attachment(&integration_id, &result_id, &name)
+        .await
+        .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, e))?
+        .ok_or((StatusCode::NOT_FOUND, format!("Attachment {} not found", name)))?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, attachment.info.content_type),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", attachment.info.name)),
+            // The content type is the uploader's, so browsers must not guess a more dangerous one
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        attachment.data,
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::integration_manager::create_integration_routes;
+    use crate::test_support::*;
+    use axum::body::Body;
+    use tower::ServiceExt;
+    use serde_json::json;
+
+    #[test]
+    fn test_metrics_csv_flattens_nested_metrics() {
+        let output = json!({
+            "summary": "ok",
+            "metrics": {"rows": 120, "latency": {"p50": 12.5, "p99": 80}, "note": "peak, then dip"}
+        });
+        let attachment = AttachmentGenerator::MetricsCsv.generate(&output).unwrap();
+
+        assert_eq!((attachment.info.name.as_str(), attachment.info.content_type.as_str()), ("metrics.csv", "text/csv"));
+        assert_eq!(
+            String::from_utf8(attachment.data).unwrap(),
+            "metric,value\nlatency.p50,12.5\nlatency.p99,80\nnote,\"peak, then dip\"\nrows,120\n"
+        );
+        assert!(AttachmentGenerator::MetricsCsv.generate(&json!({"summary": "no metrics"})).is_none());
+    }
+
+    #[test]
+    fn test_cache_drops_the_oldest_content_past_its_limit() {
+        let mut cache = AttachmentCache::default();
+        cache.insert("r1", Attachment::new("a.txt", "text/plain", vec![0; 40]), Some(100));
+        cache.insert("r1", Attachment::new("b.txt", "text/plain", vec![0; 40]), Some(100));
+        cache.insert("r2", Attachment::new("c.txt", "text/plain", vec![0; 40]), Some(100));
+        assert!(cache.get("r1", "a.txt").is_none());
+        assert!(cache.get("r1", "b.txt").is_some() && cache.get("r2", "c.txt").is_some());
+        assert_eq!(cache.bytes(), 80);
+
+        // Content larger than the limit is still kept until the next insert
+        cache.insert("r2", Attachment::new("c.txt", "text/plain", vec![0; 150]), Some(100));
+        assert_eq!(cache.bytes(), 150);
+
+        cache.remove_results(&["r2".to_string()]);
+        assert_eq!(cache.bytes(), 0);
+        assert!(cache.get("r2", "c.txt").is_none());
+    }
+
+    #[test]
+    fn test_attachment_names_are_restricted() {
+        assert!(validate_name("chart-spec_v2.json").is_ok());
+        assert!(validate_name("../secrets").is_err());
+        assert!(validate_name(".hidden").is_err());
+        assert!(validate_name("a b").is_err());
+        assert!(validate_name("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_attachments_are_uploaded_generated_and_served_after_restart() {
+        let (providers, _) = mock_ollama(r#"{"summary": "Sales grew", "metrics": {"orders": 42, "revenue": 980.5}}"#).await;
+        let store = Arc::new(MemoryStore::default());
+        let manager = Arc::new(IntegrationManager::new().with_store(store.clone()));
+        let mut request = sample_request("charts");
+        request.configuration.attachments = vec![AttachmentGenerator::MetricsCsv];
+        let integration = manager.create_integration(request).await.unwrap();
+        let result = manager
+            .process_analysis_request(analysis_request(&integration, serde_json::json!({"orders": [1, 2]})), &providers)
+            .await
+            .unwrap();
+        assert_eq!(result.attachments.len(), 1);
+
+        let app = create_integration_routes(offline_providers()).with_state(manager.clone());
+        let base = format!("/integrations/{}/results/{}/attachments", integration.id, result.id);
+        let spec = r#"{"mark": "bar", "encoding": {"x": {"field": "month"}}}"#;
+        let upload = |name: &str, api_key: &str| {
+            app.clone().oneshot(
+                axum::http::Request::put(format!("{}/{}", base, name))
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header(header::AUTHORIZATION, format!("Bearer {}", api_key))
+                    .body(Body::from(spec))
+                    .unwrap(),
+            )
+        };
+        assert_eq!(upload("chart.vl.json", "json_oracle_nobody").await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        let response = upload("chart.vl.json", &integration.api_key).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let info: AttachmentInfo = serde_json::from_str(&body_string(response).await).unwrap();
+        assert_eq!((info.name.as_str(), info.size), ("chart.vl.json", spec.len()));
+
+        assert_eq!(upload("..%2Fescape", &integration.api_key).await.unwrap().status(), StatusCode::BAD_REQUEST);
+
+        let get = |uri: String| app.clone().oneshot(with_key(axum::http::Request::get(uri), &integration).body(Body::empty()).unwrap());
+        let anonymous = |uri: String| app.clone().oneshot(axum::http::Request::get(uri).body(Body::empty()).unwrap());
+        assert_eq!(anonymous(base.clone()).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(anonymous(format!("{}/chart.vl.json", base)).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        let listed: Vec<AttachmentInfo> = serde_json::from_str(&body_string(get(base.clone()).await.unwrap()).await).unwrap();
+        let names: Vec<&str> = listed.iter().map(|info| info.name.as_str()).collect();
+        assert_eq!(names, ["metrics.csv", "chart.vl.json"]);
+        assert_eq!(get(format!("{}/missing.txt", base)).await.unwrap().status(), StatusCode::NOT_FOUND);
+
+        // Content comes back from the store once the cache is gone
+        let restarted = Arc::new(IntegrationManager::new().with_store(store.clone()));
+        restarted.load_from_store().await.unwrap();
+        let app = create_integration_routes(offline_providers()).with_state(restarted);
+        let get = |uri: String| app.clone().oneshot(with_key(axum::http::Request::get(uri), &integration).body(Body::empty()).unwrap());
+        let response = get(format!("{}/chart.vl.json", base)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(response.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(response.headers()[header::CONTENT_DISPOSITION], "attachment; filename=\"chart.vl.json\"");
+        assert_eq!(body_string(response).await, spec);
+
+        let response = get(format!("{}/metrics.csv", base)).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/csv");
+        assert_eq!(body_string(response).await, "metric,value\norders,42\nrevenue,980.5\n");
+
+        // Deleting the result drops its cached content
+        assert!(manager.attachments.read().await.bytes() > 0);
+        assert_eq!(manager.delete_analysis_results(&integration.id, None, None).await, 1);
+        assert_eq!(manager.attachments.read().await.bytes(), 0);
+    }
+}
@@ -0,0 +1,89 @@
+//! In-process work queue depth tracking and load shedding
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Header carrying the current queue depth on every response
+pub const QUEUE_DEPTH_HEADER: &str = "x-queue-depth";
+
+/// Seconds clients are asked to wait before retrying a shed request
+const RETRY_AFTER_SECONDS: u64 = 5;
+
+/// Counts analysis jobs that are queued or running
+#[derive(Debug)]
+pub struct WorkQueue {
+    depth: AtomicUsize,
+    high_water_mark: usize,
+}
+
+/// Keeps a job counted in the queue until dropped
+#[derive(Debug)]
+pub struct QueueGuard {
+    queue: Arc<WorkQueue>,
+}
+
+impl WorkQueue {
+    pub fn new(high_water_mark: usize) -> Self {
+        Self {
+            depth: AtomicUsize::new(0),
+            high_water_mark,
+        }
+    }
+
+    /// Count a job until the returned guard is dropped
+    pub fn enter(self: &Arc<Self>) -> QueueGuard {
+        self.depth.fetch_add(1, Ordering::SeqCst);
+        QueueGuard { queue: self.clone() }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::SeqCst)
+    }
+
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water_mark
+    }
+
+    /// Whether new work should be refused
+    pub fn is_overloaded(&self) -> bool {
+        self.depth() > self.high_water_mark
+    }
+}
+
+impl Drop for QueueGuard {
+    fn drop(&mut self) {
+        self.queue.depth.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Middleware adding `X-Queue-Depth` to responses and shedding load above the
+/// high-water mark. `/health` is always served so load balancers can see the state.
+pub async fn backpressure(State(queue): State<Arc<WorkQueue>>, request: Request, next: Next) -> Response {
+    let mut response = if queue.is_overloaded() && request.uri().path() != "/health" {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, RETRY_AFTER_SECONDS.to_string())],
+            Json(json!({
+                "status": "error",
+                "error": "Server is overloaded, retry later",
+                "queue_depth": queue.depth()
+            })),
+        )
+            .into_response()
+    } else {
+        next.run(request).await
+    };
+
+    response
+        .headers_mut()
+        .insert(QUEUE_DEPTH_HEADER, HeaderValue::from(queue.depth()));
+    response
+}
+
@@ -0,0 +1,105 @@
+//! Per-destination circuit breaker for outgoing notifications
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Tracks consecutive failures per destination and pauses deliveries to
+/// destinations that keep failing
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    destinations: Mutex<HashMap<String, DestinationState>>,
+}
+
+#[derive(Debug, Default)]
+struct DestinationState {
+    consecutive_failures: u32,
+    /// Set while the circuit is open
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            destinations: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether a delivery to `destination` may be attempted. Once the cooldown
+    /// has passed a single trial delivery is let through; its outcome closes or
+    /// re-opens the circuit.
+    pub fn allow(&self, destination: &str) -> bool {
+        let mut destinations = self.destinations.lock().unwrap();
+        let Some(state) = destinations.get_mut(destination) else {
+            return true;
+        };
+
+        match state.opened_at {
+            Some(opened_at) if opened_at.elapsed() < self.cooldown => false,
+            Some(_) => {
+                // Restart the cooldown so only this trial goes through
+                state.opened_at = Some(Instant::now());
+                true
+            }
+            None => true,
+        }
+    }
+
+    pub fn record_success(&self, destination: &str) {
+        self.destinations.lock().unwrap().remove(destination);
+    }
+
+    pub fn record_failure(&self, destination: &str) {
+        let mut destinations = self.destinations.lock().unwrap();
+        let state = destinations.entry(destination.to_string()).or_default();
+        state.consecutive_failures += 1;
+
+        if state.consecutive_failures >= self.failure_threshold {
+            if state.opened_at.is_none() {
+                log::warn!(
+                    "Opening circuit for {} after {} consecutive failures",
+                    destination,
+                    state.consecutive_failures
+                );
+            }
+            state.opened_at = Some(Instant::now());
+        }
+    }
+
+    pub fn is_open(&self, destination: &str) -> bool {
+        self.destinations
+            .lock()
+            .unwrap()
+            .get(destination)
+            .is_some_and(|state| state.opened_at.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_opens_after_threshold_and_recovers() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(50));
+
+        breaker.record_failure("https://a");
+        assert!(breaker.allow("https://a"));
+        breaker.record_failure("https://a");
+        assert!(breaker.is_open("https://a"));
+        assert!(!breaker.allow("https://a"));
+        assert!(breaker.allow("https://b"));
+
+        // After the cooldown one trial goes through; success closes the circuit
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow("https://a"));
+        assert!(!breaker.allow("https://a"));
+        breaker.record_success("https://a");
+        assert!(breaker.allow("https://a"));
+        assert!(!breaker.is_open("https://a"));
+    }
+}
@@ -1,6 +1,7 @@
 use axum::{
-    extract::{Path, State, WebSocketUpgrade},
-    http::StatusCode,
+    extract::{Path, Query, State, WebSocketUpgrade},
+    http::{HeaderMap, StatusCode},
+    middleware,
     response::{Json, Response},
     routing::{get, post},
     Router,
@@ -13,14 +14,32 @@ use tokio::task::spawn_blocking;
 
 use futures_util::{SinkExt, StreamExt};
 
-use super::file_streaming::JsonStreamManager;
+use super::auth::{require_admin, JwksCache};
+use super::backpressure::{backpressure, WorkQueue};
+use super::domains::Domain;
+use super::extract::JsonBody;
+use super::file_streaming::{JsonStreamManager, WatchMode};
+use super::input::{read_input_file_as, InputFormat};
+use super::integration_manager::{create_integration_routes, IntegrationManager};
+use super::prompt_ab::{PromptAbTestReport, PromptAbTestRequest};
+use super::reembedding::ReembedProgress;
 use crate::ollama::OllamaClient;
 use crate::ollama::Config;
+use crate::ollama::ModelMetadataTable;
+use crate::ollama::OllamaHostPool;
+use crate::ollama::ProviderRegistry;
 
 /// API state shared across handlers
 #[derive(Clone)]
 pub struct ApiState {
     pub json_manager: Arc<JsonStreamManager>,
+    /// Analysis jobs currently queued or running
+    pub work_queue: Arc<WorkQueue>,
+    pub integration_manager: Arc<IntegrationManager>,
+    /// Ollama hosts with their last observed health
+    pub ollama_hosts: Arc<OllamaHostPool>,
+    /// Clerk's signing keys, shared so they are fetched once per TTL
+    pub jwks: JwksCache,
 }
 
 /// Start watching a JSON file
@@ -41,13 +60,14 @@ pub async fn start_watching(
     
     log::info!("File exists, attempting to start watch...");
     
-    match state.json_manager.watch_file(&file_path).await {
+    match state.json_manager.watch_file_with_mode(&file_path, payload.mode).await {
         Ok(_) => {
             log::info!("Successfully started watching: {}", file_path);
             Ok(Json(json!({
                 "status": "success",
                 "message": format!("Started watching file: {}", file_path),
-                "file_path": file_path
+                "file_path": file_path,
+                "mode": payload.mode
             })))
         }
         Err(e) => {
@@ -149,7 +169,7 @@ async fn handle_socket(
                 return;
             }
         };
-        if let Err(e) = sender.send(axum::extract::ws::Message::Text(message_text.into())).await {
+        if let Err(e) = sender.send(axum::extract::ws::Message::Text(message_text)).await {
             log::error!("Failed to send initial content: {}", e);
             return;
         }
@@ -174,7 +194,7 @@ async fn handle_socket(
                         break;
                     }
                 };
-                if let Err(e) = sender.send(axum::extract::ws::Message::Text(message_text.into())).await {
+                if let Err(e) = sender.send(axum::extract::ws::Message::Text(message_text)).await {
                     log::error!("Failed to send update: {}", e);
                     break;
                 }
@@ -187,25 +207,23 @@ async fn handle_socket(
                         log::info!("WebSocket closed for file: {}", file_path);
                         break;
                     }
-                    Ok(axum::extract::ws::Message::Text(text)) => {
-                        // Handle client messages (e.g., ping/pong)
-                        if text == "ping" {
-                            let pong = json!({
-                                "type": "pong",
-                                "timestamp": chrono::Utc::now().to_rfc3339()
-                            });
-                            
-                            let pong_text = match serde_json::to_string(&pong) {
-                                Ok(text) => text,
-                                Err(e) => {
-                                    log::error!("Failed to serialize pong message: {}", e);
-                                    break;
-                                }
-                            };
-                            if let Err(e) = sender.send(axum::extract::ws::Message::Text(pong_text.into())).await {
-                                log::error!("Failed to send pong: {}", e);
+                    // Handle client messages (e.g., ping/pong)
+                    Ok(axum::extract::ws::Message::Text(text)) if text == "ping" => {
+                        let pong = json!({
+                            "type": "pong",
+                            "timestamp": chrono::Utc::now().to_rfc3339()
+                        });
+                        
+                        let pong_text = match serde_json::to_string(&pong) {
+                            Ok(text) => text,
+                            Err(e) => {
+                                log::error!("Failed to serialize pong message: {}", e);
                                 break;
                             }
+                        };
+                        if let Err(e) = sender.send(axum::extract::ws::Message::Text(pong_text)).await {
+                            log::error!("Failed to send pong: {}", e);
+                            break;
                         }
                     }
                     Err(e) => {
@@ -225,18 +243,51 @@ async fn handle_socket(
 }
 
 /// Health check endpoint
-pub async fn health_check() -> Json<Value> {
+pub async fn health_check(State(state): State<ApiState>) -> Json<Value> {
     Json(json!({
         "status": "healthy",
         "timestamp": chrono::Utc::now().to_rfc3339(),
-        "service": "ai-json-analysis-api"
+        "service": "ai-json-analysis-api",
+        "queue_depth": state.work_queue.depth(),
+        "queue_high_water_mark": state.work_queue.high_water_mark(),
+        "backpressure": state.work_queue.is_overloaded(),
+        "store_degraded": state.integration_manager.store_degraded(),
+        "ollama_hosts": state.ollama_hosts.status()
     }))
 }
 
-/// Create the API router
+/// Whether this instance should receive traffic: `503` when a dependency
+/// it cannot work without (currently the persistence store) is failing
+pub async fn readiness_check(State(state): State<ApiState>) -> (StatusCode, Json<Value>) {
+    let manager = &state.integration_manager;
+    let store = if manager.config().readiness_check_store {
+        match manager.check_store().await {
+            Ok(()) => json!({"ready": true}),
+            Err(e) => json!({"ready": false, "error": e}),
+        }
+    } else {
+        json!({"ready": true, "skipped": true})
+    };
+
+    let ready = store["ready"] == true;
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (
+        status,
+        Json(json!({
+            "status": if ready { "ready" } else { "not_ready" },
+            "checks": {"store": store}
+        })),
+    )
+}
+
+/// Create the API router, with the integration routes mounted alongside
 pub fn create_router(state: ApiState) -> Router {
+    let providers = ProviderRegistry::with_ollama_hosts(state.integration_manager.config(), state.ollama_hosts.clone());
+    let integration_routes = create_integration_routes(providers).with_state(state.integration_manager.clone());
+
     Router::new()
         .route("/health", get(health_check))
+        .route("/readyz", get(readiness_check))
         .route("/api/watch", post(start_watching))
         .route("/api/watch/{file_path}", get(stop_watching))
         .route("/api/files", get(get_watched_files))
@@ -245,13 +296,27 @@ pub fn create_router(state: ApiState) -> Router {
         .route("/api/ollama/process", post(ollama_process_json))
         .route("/api/ollama/conversation", post(multi_model_conversation))
         .route("/api/available-files", get(list_available_files))
-        .with_state(state)
+        .route("/api/models", get(list_models))
+        .route("/api/domains", get(list_domains))
+        .route("/api/domains/:domain/analysis-types", get(list_analysis_types))
+        .route("/admin/config", get(get_effective_config))
+        .route("/admin/selftest", post(run_self_test))
+        .route("/admin/reembed", post(start_reembedding))
+        .route("/admin/reembed", get(get_reembedding_progress))
+        .route("/admin/errors", get(list_recent_errors))
+        .route("/api/prompts/ab-test", post(prompt_ab_test))
+        .with_state(state.clone())
+        .merge(integration_routes)
+        .layer(middleware::from_fn_with_state(state.work_queue, backpressure))
 }
 
 /// Request payload for starting file watching
 #[derive(serde::Deserialize)]
 pub struct StartWatchingRequest {
     pub file_path: String,
+    /// `schema_change` publishes only writes that change the data's structure
+    #[serde(default)]
+    pub mode: WatchMode,
 }
 
 /// Request payload for Ollama to process JSON file with prompt
@@ -260,13 +325,17 @@ pub struct OllamaProcessRequest {
     pub file_path: String,
     pub prompt: String,
     pub model: Option<String>,
+    /// Syntax of the file; defaults to its extension, else JSON
+    #[serde(default)]
+    pub input_format: Option<InputFormat>,
 }
 
 /// Process JSON file with Ollama AI (default: ultra-threading)
 pub async fn ollama_process_json(
-    State(_state): State<ApiState>,
-    Json(payload): Json<OllamaProcessRequest>,
+    State(state): State<ApiState>,
+    JsonBody(payload): JsonBody<OllamaProcessRequest>,
 ) -> Result<Json<Value>, StatusCode> {
+    let _job = state.work_queue.enter();
     let start_time = Instant::now();
     
     // Normalize the file path
@@ -285,27 +354,34 @@ pub async fn ollama_process_json(
     let file_path_str = file_path.to_string_lossy().to_string();
     let file_path_str_clone = file_path_str.clone(); // Clone for closure
     
-    // Get file content and config in parallel using ultra-fast threading
-      let (file_content_result, config_result): (Result<Result<String, std::io::Error>, _>, Result<Result<Config, _>, _>) = tokio::join!(
-        spawn_blocking(move || std::fs::read_to_string(&file_path_str_clone)),     
-        spawn_blocking(|| Config::from_env())
-    );
-    
-    let file_content = match file_content_result.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? {
-        Ok(content) => content,
+    // Load config first: it carries the input size limit
+    let config = match spawn_blocking(Config::from_env).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? {
+        Ok(config) => config,
         Err(e) => {
-            log::error!("Failed to read file {}: {}", file_path_str, e);
-            return Err(StatusCode::NOT_FOUND);
+            log::error!("Failed to load config: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     };
     
-    let config = match config_result.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? {
-        Ok(config) => config,
+    let max_input_bytes = config.max_input_bytes;
+    let parquet_sample_rows = config.parquet_sample_rows;
+    let input_format = payload.input_format.unwrap_or_else(|| InputFormat::from_path(&file_path));
+    let file_content = match spawn_blocking(move || {
+        read_input_file_as(std::path::Path::new(&file_path_str_clone), input_format, max_input_bytes, parquet_sample_rows)
+    })
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        Ok(content) => content,
         Err(e) => {
-            log::error!("Failed to load config: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            log::error!("Failed to read file {}: {}", file_path_str, e);
+            return Err(input_error_status(&e));
         }
     };
+    let file_content = input_format.to_json_text(file_content).map_err(|e| {
+        log::error!("Failed to convert {}: {}", file_path_str, e);
+        StatusCode::BAD_REQUEST
+    })?;
     
     let file_read_time = start_time.elapsed();
     
@@ -444,6 +520,9 @@ pub struct MultiModelConversationRequest {
     pub models: Vec<String>,
     pub conversation_rounds: Option<u8>,
     pub conversation_type: Option<String>, // "debate", "collaboration", "review"
+    /// Syntax of the file; defaults to its extension, else JSON
+    #[serde(default)]
+    pub input_format: Option<InputFormat>,
 }
 
 /// Multi-model conversation response
@@ -457,9 +536,10 @@ pub struct ModelResponse {
 
 /// Multi-model conversation handler
 pub async fn multi_model_conversation(
-    State(_state): State<ApiState>,
-    Json(payload): Json<MultiModelConversationRequest>,
+    State(state): State<ApiState>,
+    JsonBody(payload): JsonBody<MultiModelConversationRequest>,
 ) -> Result<Json<Value>, StatusCode> {
+    let _job = state.work_queue.enter();
     let start_time = Instant::now();
     let conversation_rounds = payload.conversation_rounds.unwrap_or(3);
     let conversation_type = payload.conversation_type.as_deref().unwrap_or("collaboration");
@@ -480,27 +560,34 @@ pub async fn multi_model_conversation(
     let file_path_str = file_path.to_string_lossy().to_string();
     let file_path_str_clone = file_path_str.clone(); // Clone for closure
     
-    // Get file content and config in parallel
-    let (file_content_result, config_result): (Result<Result<String, std::io::Error>, _>, Result<Result<Config, _>, _>) = tokio::join!(
-        spawn_blocking(move || std::fs::read_to_string(&file_path_str_clone)),
-        spawn_blocking(|| Config::from_env())
-    );
-    
-    let file_content = match file_content_result.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? {
-        Ok(content) => content,
+    // Load config first: it carries the input size limit
+    let config = match spawn_blocking(Config::from_env).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? {
+        Ok(config) => config,
         Err(e) => {
-            log::error!("Failed to read file {}: {}", file_path_str, e);
-            return Err(StatusCode::NOT_FOUND);
+            log::error!("Failed to load config: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     };
     
-    let config = match config_result.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? {
-        Ok(config) => config,
+    let max_input_bytes = config.max_input_bytes;
+    let parquet_sample_rows = config.parquet_sample_rows;
+    let input_format = payload.input_format.unwrap_or_else(|| InputFormat::from_path(&file_path));
+    let file_content = match spawn_blocking(move || {
+        read_input_file_as(std::path::Path::new(&file_path_str_clone), input_format, max_input_bytes, parquet_sample_rows)
+    })
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        Ok(content) => content,
         Err(e) => {
-            log::error!("Failed to load config: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            log::error!("Failed to read file {}: {}", file_path_str, e);
+            return Err(input_error_status(&e));
         }
     };
+    let file_content = input_format.to_json_text(file_content).map_err(|e| {
+        log::error!("Failed to convert {}: {}", file_path_str, e);
+        StatusCode::BAD_REQUEST
+    })?;
     
     let file_read_time = start_time.elapsed();
     
@@ -540,7 +627,6 @@ pub async fn multi_model_conversation(
             let model_name_for_push = model_name.clone(); // Clone for pushing to vector
             let current_context = current_context.clone();
             let conversation_type = conversation_type.to_string();
-            let round = round;
             let _model_index = model_index; // Prefix with underscore to suppress warning
             
             // Clone config values for this iteration
@@ -686,6 +772,164 @@ pub async fn multi_model_conversation(
     })))
 }
 
+/// Return the configuration the server actually loaded, with secrets
+/// masked; admin only, as it still reveals hosts and limits
+pub async fn get_effective_config(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    require_admin(&headers, state.integration_manager.config().admin_token.as_deref())
+        .map_err(|status| (status, Json(json!({"status": "error", "error": "Admin token required"}))))?;
+    match spawn_blocking(Config::from_env).await {
+        Ok(Ok(config)) => Ok(Json(config.masked())),
+        Ok(Err(e)) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"status": "error", "error": format!("Failed to load config: {}", e)})),
+        )),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"status": "error", "error": "Config loading task failed"})),
+        )),
+    }
+}
+
+/// Run a canned analysis end to end against the configured model; admin only
+pub async fn run_self_test(State(state): State<ApiState>, headers: HeaderMap) -> (StatusCode, Json<Value>) {
+    let manager = &state.integration_manager;
+    if let Err(status) = require_admin(&headers, manager.config().admin_token.as_deref()) {
+        return (status, Json(json!({"status": "error", "error": "Admin token required"})));
+    }
+    let providers = ProviderRegistry::with_ollama_hosts(manager.config(), state.ollama_hosts.clone());
+    let report = manager.self_test(&providers).await;
+
+    let status = if report["status"] == "pass" {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report))
+}
+
+/// Re-embed stored results with the current embedding model in the
+/// background; poll `GET /admin/reembed` for progress. Admin only.
+pub async fn start_reembedding(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Json<ReembedProgress>), (StatusCode, String)> {
+    let manager = &state.integration_manager;
+    require_admin(&headers, manager.config().admin_token.as_deref()).map_err(|status| (status, String::new()))?;
+    let providers = ProviderRegistry::with_ollama_hosts(manager.config(), state.ollama_hosts.clone());
+    manager
+        .start_reembedding(&providers)
+        .await
+        .map(|progress| (StatusCode::ACCEPTED, Json(progress)))
+        .map_err(|e| (StatusCode::CONFLICT, e))
+}
+
+/// Progress of the latest re-embedding run; admin only
+pub async fn get_reembedding_progress(State(state): State<ApiState>, headers: HeaderMap) -> Result<Json<ReembedProgress>, StatusCode> {
+    require_admin(&headers, state.integration_manager.config().admin_token.as_deref())?;
+    state.integration_manager.reembedding_progress().map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Query parameters of `GET /admin/errors`
+#[derive(serde::Deserialize)]
+pub struct RecentErrorsQuery {
+    /// Oldest failure to include; defaults to the last 24 hours
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Failed analyses across every integration since `since`, grouped by
+/// integration and error type; admin only
+pub async fn list_recent_errors(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Query(query): Query<RecentErrorsQuery>,
+) -> Result<Json<Value>, StatusCode> {
+    let manager = &state.integration_manager;
+    require_admin(&headers, manager.config().admin_token.as_deref())?;
+
+    let since = query.since.unwrap_or_else(|| chrono::Utc::now() - chrono::Duration::hours(24));
+    let groups = manager.recent_errors(since).await;
+    Ok(Json(json!({
+        "status": "success",
+        "since": since,
+        "total": groups.iter().map(|group| group.count).sum::<usize>(),
+        "groups": groups
+    })))
+}
+
+/// Compare two prompt templates on the same data; admin only
+pub async fn prompt_ab_test(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    JsonBody(request): JsonBody<PromptAbTestRequest>,
+) -> Result<Json<PromptAbTestReport>, (StatusCode, String)> {
+    let manager = &state.integration_manager;
+    require_admin(&headers, manager.config().admin_token.as_deref()).map_err(|status| (status, String::new()))?;
+
+    let providers = ProviderRegistry::with_ollama_hosts(manager.config(), state.ollama_hosts.clone());
+    manager
+        .compare_prompt_templates(request, &providers)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))
+}
+
+/// Status for a failed input file read
+pub(crate) fn input_error_status(error: &std::io::Error) -> StatusCode {
+    match error.kind() {
+        std::io::ErrorKind::FileTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        std::io::ErrorKind::InvalidData => StatusCode::UNPROCESSABLE_ENTITY,
+        _ => StatusCode::NOT_FOUND,
+    }
+}
+
+/// List the analysis domains this deployment exposes
+pub async fn list_domains(State(state): State<ApiState>) -> Json<Value> {
+    let domains: Vec<&str> = state
+        .integration_manager
+        .enabled_domains()
+        .iter()
+        .map(|d| d.as_str())
+        .collect();
+
+    Json(json!({
+        "status": "success",
+        "domains": domains
+    }))
+}
+
+/// List the analysis types with a native prompt for one domain
+pub async fn list_analysis_types(
+    State(state): State<ApiState>,
+    Path(domain): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let analysis_types = Domain::from_str(&domain)
+        .and_then(|d| state.integration_manager.domain_registry().supported_analysis_types(&d))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let analysis_types: Vec<&str> = analysis_types.iter().map(|t| t.as_str()).collect();
+
+    Ok(Json(json!({
+        "status": "success",
+        "domain": domain.to_lowercase(),
+        "analysis_types": analysis_types
+    })))
+}
+
+/// List known model families and the context windows used for prompt budgeting
+pub async fn list_models() -> Json<Value> {
+    Json(models_response(&ModelMetadataTable::from_env()))
+}
+
+fn models_response(metadata: &ModelMetadataTable) -> Value {
+    json!({
+        "status": "success",
+        "default_context_window": metadata.default_context_window(),
+        "models": metadata.entries()
+    })
+}
+
 /// Get list of available JSON files in current directory
 pub async fn list_available_files() -> Json<Value> {
     let current_dir = match std::env::current_dir() {
@@ -701,13 +945,11 @@ pub async fn list_available_files() -> Json<Value> {
     let mut json_files = Vec::new();
     
     if let Ok(entries) = std::fs::read_dir(&current_dir) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if let Some(extension) = path.extension() {
-                    if extension == "json" {
-                        json_files.push(path.to_string_lossy().to_string());
-                    }
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if let Some(extension) = path.extension() {
+                if extension == "json" {
+                    json_files.push(path.to_string_lossy().to_string());
                 }
             }
         }
@@ -724,24 +966,313 @@ pub async fn list_available_files() -> Json<Value> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::api::backpressure::QUEUE_DEPTH_HEADER;
+    use axum::body::Body;
+    use axum::http::header;
+    use crate::test_support::{mock_ollama_server, FlakyStore};
+    use tower::ServiceExt;
+
+    fn test_state(high_water_mark: usize) -> ApiState {
+        ApiState {
+            json_manager: Arc::new(JsonStreamManager::new()),
+            work_queue: Arc::new(WorkQueue::new(high_water_mark)),
+            integration_manager: Arc::new(IntegrationManager::new()),
+            ollama_hosts: Arc::new(OllamaHostPool::from_config(&Config::default())),
+            jwks: JwksCache::from_config(&Config::default()),
+        }
+    }
 
+    async fn get_path(state: &ApiState, path: &str) -> Response {
+        create_router(state.clone())
+            .oneshot(axum::http::Request::get(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
 
     #[tokio::test]
     async fn test_health_check() {
-        let response = health_check().await;
+        let response = health_check(State(test_state(4))).await;
         let body = response.0;
         
         assert_eq!(body["status"], "healthy");
         assert!(body["timestamp"].is_string());
         assert_eq!(body["service"], "ai-json-analysis-api");
+        assert_eq!(body["backpressure"], false);
+        assert_eq!(body["store_degraded"], false);
+        assert_eq!(body["ollama_hosts"][0]["url"], "http://localhost:11434");
+        assert_eq!(body["ollama_hosts"][0]["healthy"], true);
+    }
+
+    #[tokio::test]
+    async fn test_integration_routes_are_mounted() {
+        let state = test_state(4);
+
+        let response = get_path(&state, "/presets").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body, json!([]));
+
+        // Listing integrations is admin only, and no admin token is configured
+        let response = get_path(&state, "/integrations").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_queue_depth_header_reflects_queued_jobs() {
+        let state = test_state(2);
+        let _first = state.work_queue.enter();
+        let _second = state.work_queue.enter();
+
+        let response = get_path(&state, "/api/files").await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[QUEUE_DEPTH_HEADER], "2");
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_shed_above_high_water_mark() {
+        let state = test_state(1);
+        let _jobs: Vec<_> = (0..2).map(|_| state.work_queue.enter()).collect();
+
+        let response = get_path(&state, "/api/files").await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+        assert_eq!(response.headers()[QUEUE_DEPTH_HEADER], "2");
+
+        // Health stays reachable and reports the backpressure
+        let response = get_path(&state, "/health").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["backpressure"], true);
+        assert_eq!(body["queue_depth"], 2);
+    }
+
+    #[test]
+    fn test_models_response_lists_context_windows() {
+        let body = models_response(&ModelMetadataTable::new().with_context_window("custom", 1024));
+        let models = body["models"].as_array().unwrap();
+
+        assert_eq!(body["default_context_window"], 4096);
+        assert!(models.iter().any(|m| m["family"] == "custom" && m["context_window"] == 1024));
+        assert!(models.iter().any(|m| m["family"] == "llama3.1" && m["context_window"] == 131072));
+    }
+
+    #[tokio::test]
+    async fn test_analysis_types_lists_only_native_prompts() {
+        let state = test_state(4);
+
+        let response = get_path(&state, "/api/domains/finance/analysis-types").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["analysis_types"], json!(["prediction", "risk_assessment"]));
+
+        let response = get_path(&state, "/api/domains/astrology/analysis-types").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_analysis_types_are_not_listed() {
+        let config = Config {
+            disabled_analysis_types: vec![crate::api::domains::AnalysisType::Prediction],
+            ..Config::default()
+        };
+        let state = ApiState {
+            integration_manager: Arc::new(IntegrationManager::with_config(config)),
+            ..test_state(4)
+        };
+
+        let response = get_path(&state, "/api/domains/finance/analysis-types").await;
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["analysis_types"], json!(["risk_assessment"]));
+    }
+
+    fn self_test_state(ollama_base_url: String) -> ApiState {
+        let config = Config {
+            ollama_base_url,
+            ollama_model: "llama3".to_string(),
+            max_timeout_seconds: 5,
+            admin_token: Some("s3cret".to_string()),
+            ..Config::default()
+        };
+        ApiState {
+            ollama_hosts: Arc::new(OllamaHostPool::from_config(&config)),
+            integration_manager: Arc::new(IntegrationManager::with_config(config)),
+            ..test_state(4)
+        }
+    }
+
+    /// POST to `path` as the admin of `self_test_state`
+    async fn post_path(state: &ApiState, path: &str) -> (StatusCode, Value) {
+        let request = axum::http::Request::post(path).header(header::AUTHORIZATION, "Bearer s3cret");
+        let response = create_router(state.clone())
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_self_test_passes_against_mock_ollama() {
+        let state = self_test_state(mock_ollama_server("Latency spiked to 950ms with 4 errors.").await.0);
+
+        let (status, body) = post_path(&state, "/admin/selftest").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["status"], "pass");
+        assert_eq!(body["model"], "llama3");
+        assert!(body["elapsed_ms"].is_number());
+
+        // Nothing is persisted
+        assert_eq!(state.integration_manager.get_dashboard_stats().await["total_analyses"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_self_test_fails_when_ollama_is_down() {
+        let state = self_test_state("http://127.0.0.1:9".to_string());
+
+        let response = create_router(state.clone())
+            .oneshot(axum::http::Request::post("/admin/selftest").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let (status, body) = post_path(&state, "/admin/selftest").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "fail");
+        assert!(body["error"].is_string());
     }
 
     #[tokio::test]
     async fn test_start_watching_request() {
         let request = StartWatchingRequest {
             file_path: "/test/file.json".to_string(),
+            mode: WatchMode::default(),
         };
         
         assert_eq!(request.file_path, "/test/file.json");
     }
-} 
\ No newline at end of file
+
+    async fn readiness(store: FlakyStore, config: Config) -> (StatusCode, Value) {
+        let state = ApiState {
+            integration_manager: Arc::new(IntegrationManager::with_config(config).with_store(Arc::new(store))),
+            ..test_state(4)
+        };
+        let response = get_path(&state, "/readyz").await;
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_readiness_fails_when_store_round_trip_errors() {
+        let (status, body) = readiness(FlakyStore::default(), Config::default()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ready");
+
+        let broken = FlakyStore { failing: true.into(), ..FlakyStore::default() };
+        let (status, body) = readiness(broken, Config::default()).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "not_ready");
+        assert_eq!(body["checks"]["store"]["error"], "disk unavailable");
+
+        // The store check can be switched off
+        let broken = FlakyStore { failing: true.into(), ..FlakyStore::default() };
+        let config = Config { readiness_check_store: false, ..Config::default() };
+        let (status, _) = readiness(broken, config).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_reembedding_is_admin_only_and_needs_an_embedding_model() {
+        let mut state = test_state(4);
+        let config = Config { admin_token: Some("s3cret".to_string()), ..Config::default() };
+        state.integration_manager = Arc::new(IntegrationManager::with_config(config));
+        let reembed = |method: &str, token: &str| {
+            let request = axum::http::Request::builder()
+                .method(method)
+                .uri("/admin/reembed")
+                .header(header::AUTHORIZATION, format!("Bearer {}", token));
+            create_router(state.clone()).oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        assert_eq!(reembed("POST", "guess").await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(reembed("GET", "guess").await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(reembed("GET", "s3cret").await.unwrap().status(), StatusCode::NOT_FOUND);
+        assert_eq!(reembed("POST", "s3cret").await.unwrap().status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_prompt_ab_test_is_admin_only() {
+        let mut state = self_test_state(mock_ollama_server(r#"{"summary": "Revenue is up"}"#).await.0);
+        let mut config = state.integration_manager.config().clone();
+        config.admin_token = Some("s3cret".to_string());
+        state.integration_manager = Arc::new(IntegrationManager::with_config(config));
+
+        let body = json!({
+            "a": {"text": "Summarize {domain} revenue."},
+            "b": {"text": "List {domain} risks."},
+            "data": {"revenue": [120, 160]}
+        });
+        let ab_test = |token: Option<&str>| {
+            let mut request = axum::http::Request::post("/api/prompts/ab-test")
+                .header(axum::http::header::CONTENT_TYPE, "application/json");
+            if let Some(token) = token {
+                request = request.header(axum::http::header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            create_router(state.clone()).oneshot(request.body(Body::from(body.to_string())).unwrap())
+        };
+
+        assert_eq!(ab_test(None).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(ab_test(Some("guess")).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+        let response = ab_test(Some("s3cret")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let report: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(report["a"]["template"], "Summarize generic revenue.");
+        assert_eq!(report["b"]["output"]["summary"], "Revenue is up");
+        assert_eq!(report["diff"]["unchanged"], json!(["summary"]));
+        assert!(report["latency"]["faster"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_effective_config_is_admin_only() {
+        let mut state = test_state(4);
+        assert_eq!(get_path(&state, "/admin/config").await.status(), StatusCode::FORBIDDEN);
+
+        let config = Config { admin_token: Some("s3cret".to_string()), ..Config::default() };
+        state.integration_manager = Arc::new(IntegrationManager::with_config(config));
+        assert_eq!(get_path(&state, "/admin/config").await.status(), StatusCode::UNAUTHORIZED);
+
+        let request = axum::http::Request::get("/admin/config").header(header::AUTHORIZATION, "Bearer s3cret");
+        let response = create_router(state.clone()).oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+        // Past the admin check; the config itself is loaded from the process environment
+        assert!(!matches!(response.status(), StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN));
+    }
+
+    #[tokio::test]
+    async fn test_recent_errors_are_admin_only() {
+        let mut state = test_state(4);
+        let config = Config { admin_token: Some("s3cret".to_string()), ..Config::default() };
+        state.integration_manager = Arc::new(IntegrationManager::with_config(config));
+        let errors = |token: &str, path: &str| {
+            let request = axum::http::Request::get(path).header(header::AUTHORIZATION, format!("Bearer {}", token));
+            create_router(state.clone()).oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        assert_eq!(errors("guess", "/admin/errors").await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+        let response = errors("s3cret", "/admin/errors?since=2026-01-01T00:00:00Z").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["since"], "2026-01-01T00:00:00Z");
+        assert_eq!(body["total"], 0);
+        assert_eq!(body["groups"], json!([]));
+    }
+}
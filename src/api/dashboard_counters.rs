@@ -0,0 +1,156 @@
+//! Running dashboard totals, kept in step with the stored results so reading
+//! them never scans every result
+
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use super::integration_manager::{AnalysisStatus, IntegrationAnalysisResult};
+
+/// Hours of creation-time buckets the recent count looks back over
+const RECENT_HOURS: i64 = 24;
+
+/// Totals over every stored result
+#[derive(Debug, Default)]
+pub struct DashboardCounters {
+    total: AtomicUsize,
+    successful: AtomicUsize,
+    by_domain: Mutex<BTreeMap<String, usize>>,
+    /// Results created in each hour, keyed by hours since the epoch
+    by_hour: Mutex<BTreeMap<i64, usize>>,
+}
+
+/// A consistent-enough read of the counters for the dashboard
+#[derive(Debug, Clone, PartialEq)]
+pub struct DashboardTotals {
+    pub total: usize,
+    pub successful: usize,
+    pub by_domain: BTreeMap<String, usize>,
+    /// Results created in the last 24 hours, to the hour
+    pub recent: usize,
+}
+
+impl DashboardCounters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a stored result changing from `old` to `new`; `None` on
+    /// either side is an insert or a removal
+    pub fn replace(&self, old: Option<&IntegrationAnalysisResult>, new: Option<&IntegrationAnalysisResult>) {
+        if let Some(old) = old {
+            self.adjust(old, false);
+        }
+        if let Some(new) = new {
+            self.adjust(new, true);
+        }
+    }
+
+    /// Reset the counters to exactly `results`
+    pub fn rebuild<'a>(&self, results: impl IntoIterator<Item = &'a IntegrationAnalysisResult>) {
+        self.total.store(0, Ordering::SeqCst);
+        self.successful.store(0, Ordering::SeqCst);
+        self.by_domain.lock().unwrap().clear();
+        self.by_hour.lock().unwrap().clear();
+        for result in results {
+            self.adjust(result, true);
+        }
+    }
+
+    pub fn totals(&self, now: DateTime<Utc>) -> DashboardTotals {
+        let since = hour_of(now) - (RECENT_HOURS - 1);
+        DashboardTotals {
+            total: self.total.load(Ordering::SeqCst),
+            successful: self.successful.load(Ordering::SeqCst),
+            by_domain: self.by_domain.lock().unwrap().clone(),
+            recent: self.by_hour.lock().unwrap().range(since..).map(|(_, count)| count).sum(),
+        }
+    }
+
+    fn adjust(&self, result: &IntegrationAnalysisResult, add: bool) {
+        step(&self.total, add);
+        if result.status == AnalysisStatus::Completed {
+            step(&self.successful, add);
+        }
+        if let Some(domain) = &result.domain {
+            step_key(&mut self.by_domain.lock().unwrap(), domain.clone(), add);
+        }
+
+        let mut by_hour = self.by_hour.lock().unwrap();
+        step_key(&mut by_hour, hour_of(result.created_at), add);
+        // Buckets older than the window are never read again
+        let oldest = hour_of(Utc::now()) - RECENT_HOURS;
+        while by_hour.first_key_value().is_some_and(|(hour, _)| *hour < oldest) {
+            by_hour.pop_first();
+        }
+    }
+}
+
+fn hour_of(time: DateTime<Utc>) -> i64 {
+    time.timestamp().div_euclid(3600)
+}
+
+/// Count one up or down, never below zero
+fn step(counter: &AtomicUsize, add: bool) {
+    let _ = counter.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+        Some(if add { count + 1 } else { count.saturating_sub(1) })
+    });
+}
+
+/// Like `step` for a keyed count, dropping keys that reach zero
+fn step_key<K: Ord>(counts: &mut BTreeMap<K, usize>, key: K, add: bool) {
+    if add {
+        *counts.entry(key).or_insert(0) += 1;
+    } else if let Some(count) = counts.get_mut(&key) {
+        *count -= 1;
+        if *count == 0 {
+            counts.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(status: AnalysisStatus, domain: &str, hours_ago: i64) -> IntegrationAnalysisResult {
+        serde_json::from_value(serde_json::json!({
+            "id": format!("{}-{}", domain, hours_ago),
+            "integration_id": "int-1",
+            "system_name": "test",
+            "data_source": "external_system",
+            "analysis_result": null,
+            "status": status,
+            "created_at": Utc::now() - chrono::Duration::hours(hours_ago),
+            "processing_time": 0.0,
+            "insights_count": 0,
+            "recommendations_count": 0,
+            "domain": domain
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_status_changes_and_removals_keep_totals_exact() {
+        let counters = DashboardCounters::new();
+        let processing = result(AnalysisStatus::Processing, "finance", 0);
+        let completed = IntegrationAnalysisResult { status: AnalysisStatus::Completed, ..processing.clone() };
+        let old = result(AnalysisStatus::Completed, "healthcare", 30);
+
+        counters.replace(None, Some(&processing));
+        counters.replace(None, Some(&old));
+        counters.replace(Some(&processing), Some(&completed));
+        let totals = counters.totals(Utc::now());
+        assert_eq!((totals.total, totals.successful, totals.recent), (2, 2, 1));
+        assert_eq!(totals.by_domain, BTreeMap::from([("finance".to_string(), 1), ("healthcare".to_string(), 1)]));
+
+        counters.replace(Some(&old), None);
+        let totals = counters.totals(Utc::now());
+        assert_eq!((totals.total, totals.successful), (1, 1));
+        assert_eq!(totals.by_domain, BTreeMap::from([("finance".to_string(), 1)]));
+
+        counters.rebuild([&old, &old]);
+        assert_eq!(counters.totals(Utc::now()).by_domain, BTreeMap::from([("healthcare".to_string(), 2)]));
+    }
+}
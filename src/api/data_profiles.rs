@@ -0,0 +1,300 @@
+//! Named data profiles: redaction and sampling settings shared by every
+//! integration that references them
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::auth::require_admin;
+use super::extract::JsonBody;
+use super::integration_manager::{Integration, IntegrationConfig, IntegrationManager};
+use super::sampling::SamplingStrategy;
+use super::store::PendingWrite;
+
+fn default_true() -> bool {
+    true
+}
+
+/// How an integration's input is protected and sampled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataProfile {
+    pub name: String,
+    /// Redact the values of `filters` before data reaches the model
+    #[serde(default = "default_true")]
+    pub redaction: bool,
+    /// Field names (case-insensitive, at any depth) to redact
+    #[serde(default)]
+    pub filters: Vec<String>,
+    /// How input rows are sampled into stored results; requests may still
+    /// override it
+    #[serde(default)]
+    pub sampling: Option<SamplingStrategy>,
+    #[serde(default = "Utc::now")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl DataProfile {
+    /// Fold this profile into an integration's configuration: its filters
+    /// join the integration's own and its sampling replaces the integration's
+    pub fn apply_to(&self, config: &mut IntegrationConfig) {
+        if self.redaction {
+            for filter in &self.filters {
+                if !config.redact_fields.iter().any(|existing| existing.eq_ignore_ascii_case(filter)) {
+                    config.redact_fields.push(filter.clone());
+                }
+            }
+        }
+        if let Some(sampling) = &self.sampling {
+            config.sampling = Some(sampling.clone());
+        }
+    }
+}
+
+/// Profile names are used in file names, so only letters, digits, `.`, `_`
+/// and `-` are allowed, and not a leading `.`
+pub fn validate_profile_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > 128 {
+        return Err("Data profile name must be 1 to 128 characters".to_string());
+    }
+    if name.starts_with('.') || !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')) {
+        return Err(format!(
+            "Invalid data profile name '{}': use letters, digits, '.', '_' and '-', not starting with '.'",
+            name
+        ));
+    }
+    Ok(())
+}
+
+impl IntegrationManager {
+    /// Save a new data profile, failing if the name is taken
+    pub async fn create_data_profile(&self, mut profile: DataProfile) -> Result<DataProfile, String> {
+        let mut profiles = self.data_profiles.write().await;
+        if profiles.contains_key(&profile.name) {
+            return Err(format!("Data profile '{}' already exists", profile.name));
+        }
+
+        profile.created_at = Utc::now();
+        profile.updated_at = profile.created_at;
+        profiles.insert(profile.name.clone(), profile.clone());
+        drop(profiles);
+        self.persist(PendingWrite::DataProfile(Box::new(profile.clone()))).await;
+        Ok(profile)
+    }
+
+    /// Replace an existing data profile's settings; integrations referencing
+    /// it pick up the change on their next analysis
+    pub async fn update_data_profile(&self, name: &str, mut profile: DataProfile) -> Option<DataProfile> {
+        {
+            let mut profiles = self.data_profiles.write().await;
+            let existing = profiles.get_mut(name)?;
+
+            profile.name = name.to_string();
+            profile.created_at = existing.created_at;
+            profile.updated_at = Utc::now();
+            *existing = profile.clone();
+        }
+        self.persist(PendingWrite::DataProfile(Box::new(profile.clone()))).await;
+        Some(profile)
+    }
+
+    pub async fn get_data_profile(&self, name: &str) -> Option<DataProfile> {
+        self.data_profiles.read().await.get(name).cloned()
+    }
+
+    /// All data profiles, sorted by name
+    pub async fn list_data_profiles(&self) -> Vec<DataProfile> {
+        let mut profiles: Vec<DataProfile> = self.data_profiles.read().await.values().cloned().collect();
+        profiles.sort_by(|a, b| a.name.cmp(&b.name));
+        profiles
+    }
+
+    pub async fn delete_data_profile(&self, name: &str) -> bool {
+        let removed = self.data_profiles.write().await.remove(name).is_some();
+        if removed {
+            self.persist(PendingWrite::DeleteDataProfile(name.to_string())).await;
+        }
+        removed
+    }
+
+    /// Fold the integration's data profile into its configuration. A profile
+    /// that no longer exists fails the analysis rather than letting data
+    /// through unredacted.
+    pub(super) async fn apply_data_profile(&self, integration: &mut Integration) -> Result<(), String> {
+        if let Some(name) = &integration.configuration.data_profile {
+            let profile = self
+                .get_data_profile(name)
+                .await
+                .ok_or_else(|| format!("Unknown data profile: {}", name))?;
+            profile.apply_to(&mut integration.configuration);
+        }
+        Ok(())
+    }
+}
+
+pub(super) async fn create_data_profile(
+    State(manager): State<Arc<IntegrationManager>>,
+    headers: HeaderMap,
+    JsonBody(profile): JsonBody<DataProfile>,
+) -> Result<(StatusCode, Json<DataProfile>), (StatusCode, String)> {
+    require_admin(&headers, manager.config().admin_token.as_deref()).map_err(|status| (status, String::new()))?;
+    validate_profile_name(&profile.name).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    manager
+        .create_data_profile(profile)
+        .await
+        .map(|profile| (StatusCode::CREATED, Json(profile)))
+        .map_err(|e| (StatusCode::CONFLICT, e))
+}
+
+pub(super) async fn list_data_profiles(
+    State(manager): State<Arc<IntegrationManager>>,
+) -> Json<Vec<DataProfile>> {
+    Json(manager.list_data_profiles().await)
+}
+
+pub(super) async fn get_data_profile(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path(name): Path<String>,
+) -> Result<Json<DataProfile>, StatusCode> {
+    manager.get_data_profile(&name).await.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+pub(super) async fn update_data_profile(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    JsonBody(profile): JsonBody<DataProfile>,
+) -> Result<Json<DataProfile>, StatusCode> {
+    require_admin(&headers, manager.config().admin_token.as_deref())?;
+    manager.update_data_profile(&name, profile).await.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+pub(super) async fn delete_data_profile(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> StatusCode {
+    if let Err(status) = require_admin(&headers, manager.config().admin_token.as_deref()) {
+        return status;
+    }
+    if manager.delete_data_profile(&name).await {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::integration_manager::{create_integration_routes, CreateIntegrationError, REDACTED};
+    use crate::ollama::Config;
+    use crate::test_support::*;
+    use axum::{body::Body, http::header, response::IntoResponse};
+    use tower::ServiceExt;
+    use serde_json::json;
+
+    fn profile(body: serde_json::Value) -> DataProfile {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn test_profile_filters_join_and_sampling_replaces_the_integrations() {
+        let mut config = IntegrationConfig {
+            redact_fields: vec!["SSN".to_string()],
+            sampling: Some(SamplingStrategy::Head),
+            ..IntegrationConfig::default()
+        };
+        profile(json!({"name": "hipaa", "filters": ["ssn", "dob"], "sampling": {"strategy": "tail"}}))
+            .apply_to(&mut config);
+        assert_eq!(config.redact_fields, ["SSN", "dob"]);
+        assert_eq!(config.sampling, Some(SamplingStrategy::Tail));
+
+        let mut config = IntegrationConfig::default();
+        profile(json!({"name": "sampling-only", "redaction": false, "filters": ["ssn"]})).apply_to(&mut config);
+        assert!(config.redact_fields.is_empty());
+        assert_eq!(config.sampling, None);
+    }
+
+    #[tokio::test]
+    async fn test_data_profile_redaction_and_sampling_apply_to_analysis() {
+        let (providers, calls) = mock_ollama("Visits are rising").await;
+        let store = Arc::new(MemoryStore::default());
+        let config = Config { admin_token: Some("s3cret".to_string()), ..Config::default() };
+        let manager = Arc::new(IntegrationManager::with_config(config.clone()).with_store(store.clone()));
+        let app = create_integration_routes(offline_providers()).with_state(manager.clone());
+
+        let profile = serde_json::json!({"name": "hipaa", "filters": ["ssn"], "sampling": {"strategy": "tail"}});
+        let create_profile = |token: &str| {
+            app.clone().oneshot(
+                axum::http::Request::post("/data-profiles")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                    .body(Body::from(profile.to_string()))
+                    .unwrap(),
+            )
+        };
+        assert_eq!(create_profile("guess").await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(create_profile("s3cret").await.unwrap().status(), StatusCode::CREATED);
+
+        // Profiles survive a restart, and an integration cannot name one that does not exist
+        let restarted = IntegrationManager::with_config(config).with_store(store.clone());
+        restarted.load_from_store().await.unwrap();
+        assert_eq!(restarted.get_data_profile("hipaa").await.unwrap().filters, ["ssn"]);
+        let mut create = sample_request("unprofiled");
+        create.configuration.data_profile = Some("gdpr".to_string());
+        let error = manager.create_integration(create).await.unwrap_err();
+        assert!(matches!(&error, CreateIntegrationError::UnknownDataProfile(name) if name == "gdpr"));
+        assert_eq!(error.into_response().status(), StatusCode::BAD_REQUEST);
+
+        let mut create = sample_request("profiled");
+        create.configuration.data_profile = Some("hipaa".to_string());
+        let integration = manager.create_integration(create).await.unwrap();
+
+        let rows: Vec<serde_json::Value> = (0..6)
+            .map(|i| serde_json::json!({"patient": i, "ssn": format!("123-45-000{}", i), "visits": i * 2}))
+            .collect();
+        let mut request = analysis_request(&integration, serde_json::json!(rows));
+        request.domain = Some("healthcare".to_string());
+        let result = manager.process_analysis_request(request, &providers).await.unwrap();
+
+        // The profile's filter redacted the data the model saw
+        let sent = calls.lock().unwrap()[0].clone();
+        let prompt = sent["prompt"].as_str().unwrap();
+        assert!(prompt.contains(REDACTED));
+        assert!(!prompt.contains("123-45"));
+
+        // and its tail sampling chose the stored rows
+        let sampled: Vec<&serde_json::Value> = result.analysis_result["original_data_sample"]["sample"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| &row["patient"])
+            .collect();
+        assert_eq!(sampled, [3, 4, 5]);
+
+        // Deleting the profile fails later analyses instead of skipping redaction
+        let response = app
+            .oneshot(
+                axum::http::Request::delete("/data-profiles/hipaa")
+                    .header(header::AUTHORIZATION, "Bearer s3cret")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(store.data_profiles.lock().unwrap().is_empty());
+        let error = manager
+            .process_analysis_request(analysis_request(&integration, serde_json::json!(rows)), &providers)
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Unknown data profile: hipaa");
+    }
+}
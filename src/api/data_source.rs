@@ -0,0 +1,246 @@
+//! Pulling input data from an integration's REST API source
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use url::Url;
+
+/// Timeout for a single pull from a data source
+const SOURCE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HttpMethod {
+    #[default]
+    Get,
+    Post,
+    Put,
+}
+
+/// Where a RestApi integration pulls its data from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestApiSource {
+    pub url: String,
+    #[serde(default)]
+    pub method: HttpMethod,
+    /// Request body with `{{name}}` placeholders, e.g. a GraphQL query
+    #[serde(default)]
+    pub body_template: Option<String>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+/// Replace each `{{name}}` in `template` with its percent-encoded value, so
+/// a value cannot break out of the quoting around it; unknown names are
+/// left as-is
+pub fn render_template(template: &str, variables: &HashMap<&str, String>) -> String {
+    variables.iter().fold(template.to_string(), |rendered, (name, value)| {
+        rendered.replace(&format!("{{{{{}}}}}", name), &percent_encode(value))
+    })
+}
+
+/// `value` with everything but RFC 3986 unreserved characters percent-encoded
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// A URL that passed `check_destination`, with the addresses its host
+/// resolved to at the time
+#[derive(Debug, Clone)]
+pub struct Destination {
+    pub url: Url,
+    /// Empty when the host is an IP literal or private hosts are allowed
+    addresses: Vec<SocketAddr>,
+}
+
+impl Destination {
+    /// A client that connects only to the checked addresses and never
+    /// follows redirects, so neither a DNS answer that changes after the
+    /// check nor a redirect can reach an internal host
+    pub fn client(&self) -> Result<reqwest::Client, String> {
+        let mut builder = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none());
+        if let Some(domain) = self.url.domain().filter(|_| !self.addresses.is_empty()) {
+            builder = builder.resolve_to_addrs(domain, &self.addresses);
+        }
+        builder.build().map_err(|e| format!("Failed to build HTTP client: {}", e))
+    }
+}
+
+/// Reject URLs that are not http(s) or that resolve to loopback, private,
+/// link-local or otherwise internal addresses, unless `allow_private` is set
+pub async fn check_destination(url: &str, allow_private: bool) -> Result<Destination, String> {
+    let parsed = Url::parse(url).map_err(|e| format!("Invalid URL: {}", e))?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err("URL must use http or https".to_string());
+    }
+    let host = parsed.host_str().ok_or("URL must have a host")?;
+    if allow_private {
+        return Ok(Destination { url: parsed, addresses: Vec::new() });
+    }
+
+    let port = parsed.port_or_known_default().unwrap_or(80);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| format!("Could not resolve host {}: {}", host, e))?
+        .collect();
+
+    if addresses.iter().any(|address| is_internal(&address.ip())) {
+        return Err(format!("Host {} resolves to an internal address", host));
+    }
+    Ok(Destination { url: parsed, addresses })
+}
+
+fn is_internal(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified() || ip.is_broadcast()
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || (segments[0] & 0xfe00) == 0xfc00 // unique local
+                || (segments[0] & 0xffc0) == 0xfe80 // link local
+                || ip.to_ipv4_mapped().is_some_and(|v4| is_internal(&IpAddr::V4(v4)))
+        }
+    }
+}
+
+/// Issue the source's request and parse the response body, at most
+/// `max_bytes` long, as JSON
+pub async fn fetch(
+    source: &RestApiSource,
+    variables: &HashMap<&str, String>,
+    allow_private: bool,
+    max_bytes: u64,
+) -> Result<serde_json::Value, String> {
+    let destination = check_destination(&source.url, allow_private).await?;
+    let client = destination.client()?;
+
+    let url = destination.url;
+    let mut request = match source.method {
+        HttpMethod::Get => client.get(url),
+        HttpMethod::Post => client.post(url),
+        HttpMethod::Put => client.put(url),
+    }
+    .timeout(SOURCE_TIMEOUT);
+
+    for (name, value) in &source.headers {
+        request = request.header(name.as_str(), value.as_str());
+    }
+    if let Some(template) = &source.body_template {
+        let has_content_type = source.headers.keys().any(|name| name.eq_ignore_ascii_case("content-type"));
+        if !has_content_type {
+            request = request.header(reqwest::header::CONTENT_TYPE, "application/json");
+        }
+        request = request.body(render_template(template, variables));
+    }
+
+    let mut response = request
+        .send()
+        .await
+        .map_err(|e| format!("Data source request failed: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Data source responded with {}", response.status()));
+    }
+
+    let too_large = || format!("Data source response exceeds {} bytes", max_bytes);
+    if response.content_length().is_some_and(|length| length > max_bytes) {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Data source request failed: {}", e))?
+    {
+        if (body.len() + chunk.len()) as u64 > max_bytes {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    serde_json::from_slice(&body).map_err(|e| format!("Data source returned invalid JSON: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::serve;
+
+    #[test]
+    fn test_render_template_substitutes_variables() {
+        let variables = HashMap::from([("integration_id", "abc".to_string())]);
+        let rendered = render_template(r#"{"query": "{ orders(tag: \"{{integration_id}}\") }", "x": "{{other}}"}"#, &variables);
+        assert_eq!(rendered, r#"{"query": "{ orders(tag: \"abc\") }", "x": "{{other}}"}"#);
+    }
+
+    #[test]
+    fn test_render_template_encodes_values() {
+        let variables = HashMap::from([("integration_name", r#"x", "admin": true, "y": "é"#.to_string())]);
+        let rendered = render_template(r#"{"name": "{{integration_name}}"}"#, &variables);
+        assert_eq!(rendered, r#"{"name": "x%22%2C%20%22admin%22%3A%20true%2C%20%22y%22%3A%20%22%C3%A9"}"#);
+    }
+
+    fn get_source(url: String) -> RestApiSource {
+        RestApiSource { url, method: HttpMethod::Get, body_template: None, headers: HashMap::new() }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_does_not_follow_redirects() {
+        use axum::routing::get;
+
+        let base = serve(axum::Router::new()
+            .route("/data", get(|| async { axum::response::Redirect::temporary("/internal") }))
+            .route("/internal", get(|| async { axum::Json(serde_json::json!({"secret": true})) })))
+        .await;
+
+        let error = fetch(&get_source(format!("{}/data", base)), &HashMap::new(), true, 1024).await.unwrap_err();
+        assert!(error.contains("307"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_caps_the_response_size() {
+        use axum::routing::get;
+
+        let base = serve(axum::Router::new().route("/data", get(|| async { axum::Json(vec![0u8; 4096]) }))).await;
+        let source = get_source(format!("{}/data", base));
+
+        let error = fetch(&source, &HashMap::new(), true, 1024).await.unwrap_err();
+        assert!(error.contains("exceeds 1024 bytes"), "{}", error);
+        assert_eq!(fetch(&source, &HashMap::new(), true, 64 * 1024).await.unwrap().as_array().unwrap().len(), 4096);
+    }
+
+    #[tokio::test]
+    async fn test_checked_destinations_are_pinned_to_their_addresses() {
+        use axum::routing::get;
+
+        assert!(check_destination("http://localhost:8080/data", false).await.is_err());
+
+        // The client connects to the checked address whatever the name resolves to now
+        let base = serve(axum::Router::new().route("/data", get(|| async { "pinned" }))).await;
+        let address: SocketAddr = base.trim_start_matches("http://").parse().unwrap();
+        let destination = Destination {
+            url: Url::parse(&format!("http://source.invalid:{}/data", address.port())).unwrap(),
+            addresses: vec![address],
+        };
+        let response = destination.client().unwrap().get(destination.url.clone()).send().await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "pinned");
+    }
+
+    #[tokio::test]
+    async fn test_internal_destinations_are_rejected() {
+        assert!(check_destination("http://127.0.0.1:8080/data", false).await.is_err());
+        assert!(check_destination("http://10.0.0.5/data", false).await.is_err());
+        assert!(check_destination("http://[::1]/data", false).await.is_err());
+        assert!(check_destination("file:///etc/passwd", true).await.is_err());
+        assert!(check_destination("http://127.0.0.1:8080/data", true).await.is_ok());
+    }
+}
@@ -0,0 +1,115 @@
+//! Deterministic statistics about input data, computed without the model
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Objective facts about the rows of an input
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DataStats {
+    pub row_count: usize,
+    pub fields: BTreeMap<String, FieldStats>,
+}
+
+/// Coverage and range of one top-level field across the rows
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldStats {
+    /// Rows where the field is present, including explicit nulls
+    pub present: usize,
+    pub null_count: usize,
+    /// Share of rows holding a non-null value
+    pub coverage: f64,
+    /// Share of rows where the field is missing or null
+    pub null_rate: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean: Option<f64>,
+}
+
+/// Compute stats over `data`: an array is treated as rows, anything else as a single row.
+/// Only object rows contribute fields.
+pub fn compute_stats(data: &Value) -> DataStats {
+    let rows: Vec<&Value> = match data {
+        Value::Array(items) => items.iter().collect(),
+        other => vec![other],
+    };
+    let row_count = rows.len();
+
+    #[derive(Default)]
+    struct Tally {
+        present: usize,
+        nulls: usize,
+        numbers: Vec<f64>,
+    }
+    let mut tallies: BTreeMap<String, Tally> = BTreeMap::new();
+    for row in &rows {
+        let Some(object) = row.as_object() else {
+            continue;
+        };
+        for (name, value) in object {
+            let tally = tallies.entry(name.clone()).or_default();
+            tally.present += 1;
+            match value {
+                Value::Null => tally.nulls += 1,
+                Value::Number(n) => tally.numbers.extend(n.as_f64()),
+                _ => {}
+            }
+        }
+    }
+
+    let share = |count: usize| if row_count == 0 { 0.0 } else { count as f64 / row_count as f64 };
+    let fields = tallies
+        .into_iter()
+        .map(|(name, tally)| {
+            let numeric = !tally.numbers.is_empty();
+            let stats = FieldStats {
+                present: tally.present,
+                null_count: tally.nulls,
+                coverage: share(tally.present - tally.nulls),
+                null_rate: share(row_count - (tally.present - tally.nulls)),
+                min: numeric.then(|| tally.numbers.iter().copied().fold(f64::INFINITY, f64::min)),
+                max: numeric.then(|| tally.numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max)),
+                mean: numeric.then(|| tally.numbers.iter().sum::<f64>() / tally.numbers.len() as f64),
+            };
+            (name, stats)
+        })
+        .collect();
+
+    DataStats { row_count, fields }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_null_rates_and_numeric_ranges() {
+        let data = json!([
+            {"sku": "A1", "price": 12.5, "discount": null},
+            {"sku": "B2", "price": 4, "discount": 0.1},
+            {"sku": "C3", "price": 30},
+            {"sku": null, "price": -2, "discount": 0.3}
+        ]);
+        let stats = compute_stats(&data);
+
+        assert_eq!(stats.row_count, 4);
+        let price = &stats.fields["price"];
+        assert_eq!((price.min, price.max), (Some(-2.0), Some(30.0)));
+        assert_eq!(price.mean, Some(11.125));
+        assert_eq!(price.null_rate, 0.0);
+
+        let discount = &stats.fields["discount"];
+        assert_eq!(discount.present, 3);
+        assert_eq!(discount.null_count, 1);
+        assert_eq!(discount.null_rate, 0.5);
+        assert_eq!(discount.coverage, 0.5);
+
+        let sku = &stats.fields["sku"];
+        assert_eq!(sku.null_rate, 0.25);
+        assert!(sku.min.is_none());
+    }
+}
@@ -0,0 +1,105 @@
+//! Collapsing insights and recommendations that say the same thing in
+//! slightly different words
+
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Remove near-duplicate `items`, returning how many were removed.
+///
+/// Two items are near-duplicates when the Jaccard similarity of their
+/// lowercased word sets is at least `threshold`. Of each group the variant
+/// with the highest `confidence` is kept, at the position of the group's
+/// first item.
+pub fn dedupe_items(items: &mut Vec<Value>, threshold: f64) -> usize {
+    struct Group {
+        kept: Value,
+        word_sets: Vec<HashSet<String>>,
+    }
+
+    let original = items.len();
+    let mut groups: Vec<Group> = Vec::new();
+    for item in items.drain(..) {
+        let words = words(&item_text(&item));
+        let matching = groups.iter_mut().find(|group| {
+            !words.is_empty() && group.word_sets.iter().any(|other| jaccard(&words, other) >= threshold)
+        });
+        match matching {
+            Some(group) => {
+                if confidence(&item) > confidence(&group.kept) {
+                    group.kept = item;
+                }
+                group.word_sets.push(words);
+            }
+            None => groups.push(Group {
+                kept: item,
+                word_sets: vec![words],
+            }),
+        }
+    }
+
+    items.extend(groups.into_iter().map(|group| group.kept));
+    original - items.len()
+}
+
+/// The prose of an insight or recommendation: a plain string, or an object's
+/// title and description
+fn item_text(item: &Value) -> String {
+    match item {
+        Value::String(text) => text.clone(),
+        Value::Object(fields) => ["title", "description", "text", "recommendation"]
+            .iter()
+            .filter_map(|field| fields.get(*field).and_then(Value::as_str))
+            .collect::<Vec<_>>()
+            .join(" "),
+        other => other.to_string(),
+    }
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+fn confidence(item: &Value) -> f64 {
+    item.get("confidence").and_then(Value::as_f64).unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_near_identical_recommendations_collapse_to_one() {
+        let mut items = vec![
+            json!("Reduce inventory holding costs for slow-moving SKUs"),
+            json!("Restock the top sellers before the holiday peak"),
+            json!("Reduce the inventory holding costs for slow moving SKUs."),
+        ];
+        assert_eq!(dedupe_items(&mut items, 0.8), 1);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0], "Reduce inventory holding costs for slow-moving SKUs");
+    }
+
+    #[test]
+    fn test_the_most_confident_variant_is_kept() {
+        let mut items = vec![
+            json!({"title": "Churn rising", "description": "Monthly churn rose in EMEA", "confidence": 0.6}),
+            json!({"title": "Churn rising", "description": "Monthly churn rose in APAC", "confidence": 0.7}),
+            json!({"title": "Churn is rising", "description": "Monthly churn rose in EMEA", "confidence": 0.9}),
+        ];
+        assert_eq!(dedupe_items(&mut items, 0.8), 1);
+        assert_eq!(items[0]["confidence"], 0.9);
+        assert_eq!(items[1]["description"], "Monthly churn rose in APAC");
+    }
+}
@@ -0,0 +1,566 @@
+//! Background export of an integration's whole result history to a gzipped
+//! NDJSON file, downloaded through a signed, expiring URL
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use super::integration_manager::{authorize_integration, IntegrationAnalysisResult, IntegrationManager};
+
+/// Rows buffered between the store reader and a streaming export response
+const EXPORT_CHANNEL_CAPACITY: usize = 16;
+
+/// Bytes read from an export file per chunk of a download response
+const EXPORT_DOWNLOAD_CHUNK: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+/// Progress of one export job, updated as rows are written
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportJob {
+    pub id: String,
+    pub integration_id: String,
+    pub status: ExportStatus,
+    /// Results written so far
+    pub written: usize,
+    /// Size of the finished file
+    pub bytes: Option<u64>,
+    /// Where to fetch the file once completed
+    pub download_url: Option<String>,
+    pub download_expires_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl ExportJob {
+    pub fn start(integration_id: &str) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            integration_id: integration_id.to_string(),
+            status: ExportStatus::Running,
+            written: 0,
+            bytes: None,
+            download_url: None,
+            download_expires_at: None,
+            error: None,
+            started_at: Utc::now(),
+            finished_at: None,
+        }
+    }
+
+    pub fn fail(&mut self, error: String) {
+        self.status = ExportStatus::Failed;
+        self.error = Some(error);
+        self.finished_at = Some(Utc::now());
+    }
+}
+
+/// The file an export is written to
+pub fn export_path(directory: &std::path::Path, export_id: &str) -> PathBuf {
+    directory.join(format!("{}.ndjson.gz", export_id))
+}
+
+/// HMAC-SHA256, keyed by `key`, of `"{export_id}.{expires}"`
+fn download_mac(key: &str, export_id: &str, expires: i64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(export_id.as_bytes());
+    mac.update(b".");
+    mac.update(expires.to_string().as_bytes());
+    mac
+}
+
+/// The `signature` query parameter of a download URL, as hex
+pub fn sign_download(key: &str, export_id: &str, expires: i64) -> String {
+    hex::encode(download_mac(key, export_id, expires).finalize().into_bytes())
+}
+
+/// Whether `signature` was issued for `export_id` and `expires` has not passed at `now`
+pub fn verify_download(key: &str, export_id: &str, expires: i64, signature: &str, now: DateTime<Utc>) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    expires > now.timestamp() && download_mac(key, export_id, expires).verify_slice(&signature).is_ok()
+}
+
+/// Write every result received on `rows` to `path` as gzipped NDJSON,
+/// calling `progress` with the count written after each row. Blocks, so
+/// run it on a blocking thread. Returns the size of the finished file.
+pub fn write_export(
+    path: &std::path::Path,
+    mut rows: mpsc::Receiver<IntegrationAnalysisResult>,
+    mut progress: impl FnMut(usize),
+) -> Result<u64, String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let file = std::fs::File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut encoder = GzEncoder::new(std::io::BufWriter::new(file), Compression::default());
+
+    let mut written = 0;
+    while let Some(result) = rows.blocking_recv() {
+        serde_json::to_writer(&mut encoder, &result).map_err(|e| format!("Failed to serialize result {}: {}", result.id, e))?;
+        encoder.write_all(b"\n").map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        written += 1;
+        progress(written);
+    }
+
+    encoder
+        .finish()
+        .and_then(|mut writer| writer.flush())
+        .map_err(|e| format!("Failed to finish {}: {}", path.display(), e))?;
+    std::fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+impl IntegrationManager {
+    /// Start writing every result of an integration to a gzipped NDJSON file
+    /// in the background; poll `export_job` until it completes for the
+    /// download URL. `None` when the integration does not exist.
+    pub async fn start_export(self: &Arc<Self>, integration_id: &str) -> Option<ExportJob> {
+        self.get_integration(integration_id).await?;
+        let job = ExportJob::start(integration_id);
+        self.exports.lock().unwrap().insert(job.id.clone(), job.clone());
+
+        let rows = self.stream_analysis_results(integration_id);
+        let path = export_path(std::path::Path::new(&self.config.export_directory), &job.id);
+        let manager = self.clone();
+        let export_id = job.id.clone();
+        tokio::spawn(async move {
+            let progress = (manager.clone(), export_id.clone());
+            let finished = tokio::task::spawn_blocking(move || {
+                write_export(&path, rows, |written| progress.0.update_export(&progress.1, |job| job.written = written))
+            })
+            .await
+            .unwrap_or_else(|e| Err(format!("Export task failed: {}", e)));
+
+            match finished {
+                Ok(bytes) => {
+                    let expires_at = Utc::now() + chrono::Duration::seconds(manager.config.export_url_ttl_seconds as i64);
+                    let url = manager.export_download_url(&export_id, expires_at.timestamp());
+                    manager.update_export(&export_id, |job| {
+                        job.status = ExportStatus::Completed;
+                        job.bytes = Some(bytes);
+                        job.download_url = Some(url);
+                        job.download_expires_at = Some(expires_at);
+                        job.finished_at = Some(Utc::now());
+                    });
+                }
+                Err(e) => {
+                    log::warn!("Export {} failed: {}", export_id, e);
+                    manager.update_export(&export_id, |job| job.fail(e));
+                }
+            }
+        });
+        Some(job)
+    }
+
+    /// An export job of `integration_id`, running or finished
+    pub fn export_job(&self, integration_id: &str, export_id: &str) -> Option<ExportJob> {
+        self.exports
+            .lock()
+            .unwrap()
+            .get(export_id)
+            .filter(|job| job.integration_id == integration_id)
+            .cloned()
+    }
+
+    /// The file of a completed export, if `signature` and `expires` are the
+    /// ones its download URL was issued with and it has not expired. URLs
+    /// are only issued once an export completes, so the signature alone is
+    /// enough and the URL keeps working across restarts.
+    pub fn export_file(&self, export_id: &str, expires: i64, signature: &str) -> Result<std::path::PathBuf, StatusCode> {
+        if !verify_download(&self.export_signing_key, export_id, expires, signature, Utc::now()) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        Ok(export_path(std::path::Path::new(&self.config.export_directory), export_id))
+    }
+
+    /// Forget finished export jobs and delete export files once their
+    /// download URL has expired, including files left by an earlier run.
+    /// Returns how many files were deleted.
+    pub fn prune_exports(&self, now: DateTime<Utc>) -> usize {
+        let ttl = chrono::Duration::seconds(self.config.export_url_ttl_seconds as i64);
+        let running: std::collections::HashSet<String> = {
+            let mut exports = self.exports.lock().unwrap();
+            exports.retain(|_, job| match job.status {
+                ExportStatus::Running => true,
+                _ => job.finished_at.is_some_and(|finished| finished + ttl > now),
+            });
+            exports
+                .values()
+                .filter(|job| job.status == ExportStatus::Running)
+                .map(|job| job.id.clone())
+                .collect()
+        };
+        let Ok(entries) = std::fs::read_dir(&self.config.export_directory) else {
+            return 0;
+        };
+        let mut deleted = 0;
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some(export_id) = name.strip_suffix(".ndjson.gz") else {
+                continue;
+            };
+            let expired = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| DateTime::<Utc>::from(modified) + ttl <= now);
+            if expired && !running.contains(export_id) && std::fs::remove_file(entry.path()).is_ok() {
+                deleted += 1;
+            }
+        }
+        deleted
+    }
+
+    pub(super) fn export_download_url(&self, export_id: &str, expires: i64) -> String {
+        format!(
+            "/exports/{}/download?expires={}&signature={}",
+            export_id,
+            expires,
+            sign_download(&self.export_signing_key, export_id, expires)
+        )
+    }
+
+    pub(super) fn update_export(&self, export_id: &str, update: impl FnOnce(&mut ExportJob)) {
+        if let Some(job) = self.exports.lock().unwrap().get_mut(export_id) {
+            update(job);
+        }
+    }
+
+    /// Stream an integration's results in stored order, one row at a time.
+    ///
+    /// Rows are read under a short-lived lock and pushed through a bounded
+    /// channel, so a slow consumer pauses the reader instead of buffering the
+    /// whole result set. The stream covers the results stored when it starts;
+    /// it resumes after the last sequence sent, so rows deleted or evicted
+    /// meanwhile never make it skip others.
+    pub fn stream_analysis_results(&self, integration_id: &str) -> mpsc::Receiver<IntegrationAnalysisResult> {
+        let (tx, rx) = mpsc::channel(EXPORT_CHANNEL_CAPACITY);
+        let results = self.analysis_results.clone();
+        let integration_id = integration_id.to_string();
+
+        tokio::spawn(async move {
+            let last = {
+                let results = results.read().await;
+                results.get(&integration_id).and_then(|r| r.last()).map_or(0, |r| r.sequence)
+            };
+            let mut sent = 0;
+            loop {
+                let next = {
+                    let results = results.read().await;
+                    results.get(&integration_id).and_then(|r| {
+                        // Stored in sequence order
+                        r.get(r.partition_point(|result| result.sequence <= sent)).cloned()
+                    })
+                };
+
+                match next {
+                    Some(result) if result.sequence <= last => {
+                        sent = result.sequence;
+                        if tx.send(result).await.is_err() {
+                            break; // Client went away
+                        }
+                    }
+                    _ => break,
+                }
+            }
+        });
+
+        rx
+    }
+}
+
+/// Every result of the integration, with `stream=true` as NDJSON; needs
+/// its API key or the admin token
+pub(super) async fn export_integration_results(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, StatusCode> {
+    authorize_integration(&manager, &headers, &id).await?;
+
+    let stream = params.get("stream").map(|s| s == "true" || s == "1").unwrap_or(false);
+    if !stream {
+        return Ok(Json(manager.get_analysis_results(&id, None).await).into_response());
+    }
+
+    // Emit one JSON object per line as rows are read from the store
+    let rows = futures_util::stream::unfold(manager.stream_analysis_results(&id), |mut rx| async move {
+        let result = rx.recv().await?;
+        let line = serde_json::to_string(&result)
+            .map(|json| format!("{}\n", json))
+            .map_err(std::io::Error::other);
+        Some((line, rx))
+    });
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(rows),
+    ).into_response())
+}
+
+/// Start a gzipped NDJSON export of every result in the background; poll
+/// the returned job for progress and the signed download URL. Both need
+/// the integration's API key or the admin token.
+pub(super) async fn start_export_job(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Json<ExportJob>), StatusCode> {
+    authorize_integration(&manager, &headers, &id).await?;
+    let job = manager.start_export(&id).await.ok_or(StatusCode::NOT_FOUND)?;
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+pub(super) async fn get_export_job(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path((integration_id, export_id)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Json<ExportJob>, StatusCode> {
+    authorize_integration(&manager, &headers, &integration_id).await?;
+    manager.export_job(&integration_id, &export_id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Serve a completed export's file; the URL's signature stands in for the
+/// caller's credentials, so it can be handed to a download tool as is
+pub(super) async fn download_export(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path(export_id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, StatusCode> {
+    let expires = params.get("expires").and_then(|v| v.parse().ok()).ok_or(StatusCode::FORBIDDEN)?;
+    let signature = params.get("signature").ok_or(StatusCode::FORBIDDEN)?;
+    let path = manager.export_file(&export_id, expires, signature)?;
+    let file = tokio::fs::File::open(&path).await.map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let chunks = futures_util::stream::unfold(file, |mut file| async move {
+        use tokio::io::AsyncReadExt;
+        let mut buffer = vec![0; EXPORT_DOWNLOAD_CHUNK];
+        match file.read(&mut buffer).await {
+            Ok(0) => None,
+            Ok(read) => {
+                buffer.truncate(read);
+                Some((Ok(buffer), file))
+            }
+            Err(e) => Some((Err(e), file)),
+        }
+    });
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/gzip".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}.ndjson.gz\"", export_id)),
+        ],
+        Body::from_stream(chunks),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::integration_manager::{create_integration_routes, AnalysisStatus};
+    use crate::ollama::Config;
+    use crate::test_support::*;
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_download_signature_is_bound_to_export_and_expiry() {
+        let now = Utc::now();
+        let expires = now.timestamp() + 60;
+        let signature = sign_download("key", "export-1", expires);
+
+        assert!(verify_download("key", "export-1", expires, &signature, now));
+        assert!(!verify_download("key", "export-2", expires, &signature, now));
+        assert!(!verify_download("key", "export-1", expires + 1, &signature, now));
+        assert!(!verify_download("other", "export-1", expires, &signature, now));
+        assert!(!verify_download("key", "export-1", expires, "not-hex", now));
+        // Past its expiry the URL stops working even with a valid signature
+        assert!(!verify_download("key", "export-1", expires, &signature, now + chrono::Duration::seconds(61)));
+    }
+
+    #[tokio::test]
+    async fn test_results_stream_skips_nothing_when_earlier_rows_are_deleted() {
+        let manager = IntegrationManager::new();
+        let integration = manager.create_integration(sample_request("streamed")).await.unwrap();
+        let start = Utc::now() - chrono::Duration::hours(1);
+        let mut ids = Vec::new();
+        for minute in 0..40 {
+            let mut result = sample_result(&integration.id, AnalysisStatus::Completed);
+            result.created_at = start + chrono::Duration::minutes(minute);
+            manager.store_result(&mut result).await;
+            ids.push(result.id);
+        }
+
+        let mut rows = manager.stream_analysis_results(&integration.id);
+        let mut streamed = vec![rows.recv().await.unwrap().id];
+        // Let the reader fill the channel, then delete rows it has already read
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(manager.delete_analysis_results(&integration.id, Some(start + chrono::Duration::minutes(10)), None).await, 10);
+        while let Some(row) = rows.recv().await {
+            streamed.push(row.id);
+        }
+        assert_eq!(streamed, ids);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_export_yields_one_object_per_line() {
+        let manager = Arc::new(IntegrationManager::new());
+        let integration = manager.create_integration(sample_request("export")).await.unwrap();
+        seed_results(&manager, &integration.id, vec![
+            sample_result(&integration.id, AnalysisStatus::Completed),
+            sample_result(&integration.id, AnalysisStatus::Failed),
+            sample_result(&integration.id, AnalysisStatus::Completed),
+        ]).await;
+
+        let app = create_integration_routes(offline_providers()).with_state(manager);
+        let path = format!("/integrations/{}/results/export?stream=true", integration.id);
+        let response = app
+            .clone()
+            .oneshot(axum::http::Request::get(&path).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = app
+            .oneshot(with_key(axum::http::Request::get(&path), &integration).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/x-ndjson");
+
+        let body = body_string(response).await;
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), 3);
+        for line in lines {
+            let row: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert!(row.is_object());
+            assert_eq!(row["integration_id"], integration.id);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export_job_writes_every_result_to_a_signed_gzip_download() {
+        use std::io::BufRead;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            export_directory: dir.path().to_string_lossy().into_owned(),
+            export_signing_key: Some("export-key".to_string()),
+            ..Config::default()
+        };
+        let manager = Arc::new(IntegrationManager::with_config(config.clone()));
+        let integration = manager.create_integration(sample_request("archive")).await.unwrap();
+        let seeded: Vec<_> = (0..40)
+            .map(|i| sample_result(&integration.id, if i % 4 == 0 { AnalysisStatus::Failed } else { AnalysisStatus::Completed }))
+            .collect();
+        let seeded_ids: Vec<String> = seeded.iter().map(|r| r.id.clone()).collect();
+        seed_results(&manager, &integration.id, seeded).await;
+
+        let app = create_integration_routes(offline_providers()).with_state(manager.clone());
+        let start_url = format!("/integrations/{}/results/export-jobs", integration.id);
+        let response = app
+            .clone()
+            .oneshot(axum::http::Request::post(&start_url).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let bearer = format!("Bearer {}", integration.api_key);
+        let response = app
+            .clone()
+            .oneshot(
+                axum::http::Request::post(&start_url)
+                    .header(header::AUTHORIZATION, &bearer)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let mut job: serde_json::Value = serde_json::from_str(&body_string(response).await).unwrap();
+        let job_url = format!("/integrations/{}/results/export-jobs/{}", integration.id, job["id"].as_str().unwrap());
+        for _ in 0..100 {
+            let response = app
+                .clone()
+                .oneshot(
+                    axum::http::Request::get(&job_url)
+                        .header(header::AUTHORIZATION, &bearer)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            job = serde_json::from_str(&body_string(response).await).unwrap();
+            if job["status"] != "running" {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(job["status"], "completed");
+        assert_eq!(job["written"], 40);
+
+        let download_url = job["download_url"].as_str().unwrap().to_string();
+        let response = app
+            .clone()
+            .oneshot(axum::http::Request::get(&download_url).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/gzip");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let exported: Vec<String> = std::io::BufReader::new(flate2::read::GzDecoder::new(bytes.as_ref()))
+            .lines()
+            .map(|line| {
+                let row: serde_json::Value = serde_json::from_str(&line.unwrap()).unwrap();
+                row["id"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(exported, seeded_ids);
+
+        // Any change to the signed URL is refused
+        let tampered = download_url.replace("expires=", "expires=1");
+        let response = app
+            .oneshot(axum::http::Request::get(&tampered).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // The URL survives a restart, and the file goes once it has expired
+        let restarted = Arc::new(IntegrationManager::with_config(config));
+        let app = create_integration_routes(offline_providers()).with_state(restarted.clone());
+        let response = app
+            .clone()
+            .oneshot(axum::http::Request::get(&download_url).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert_eq!(restarted.prune_exports(Utc::now()), 0);
+        assert_eq!(restarted.prune_exports(Utc::now() + chrono::Duration::hours(2)), 1);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,206 @@
+//! JSON body extraction that reports which field of a malformed body is wrong
+
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRef, FromRequest, Request},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+
+use super::core_handlers::ApiState;
+use super::integration_manager::IntegrationManager;
+
+/// Like `axum::Json`, but a body that does not deserialize is rejected with a
+/// `400` naming the offending field and what was expected there, instead of a
+/// bare `422`. In strict mode fields the target type does not know are
+/// rejected too, rather than silently ignored.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonBody<T>(pub T);
+
+/// Whether `JsonBody` rejects unknown fields, taken from the router state's
+/// `strict_request_fields` setting
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StrictFields(pub bool);
+
+impl FromRef<Arc<IntegrationManager>> for StrictFields {
+    fn from_ref(manager: &Arc<IntegrationManager>) -> Self {
+        StrictFields(manager.config().strict_request_fields)
+    }
+}
+
+impl FromRef<ApiState> for StrictFields {
+    fn from_ref(state: &ApiState) -> Self {
+        StrictFields::from_ref(&state.integration_manager)
+    }
+}
+
+impl FromRef<Arc<ApiState>> for StrictFields {
+    fn from_ref(state: &Arc<ApiState>) -> Self {
+        StrictFields::from_ref(&state.integration_manager)
+    }
+}
+
+/// Why a request body was rejected
+#[derive(Debug)]
+pub struct BodyError {
+    pub status: StatusCode,
+    /// Path to the offending field, e.g. `configuration.sampling.strategy`
+    pub field: Option<String>,
+    pub detail: String,
+    /// Paths of fields rejected by strict mode
+    pub unknown_fields: Vec<String>,
+}
+
+impl IntoResponse for BodyError {
+    fn into_response(self) -> Response {
+        let mut body = serde_json::json!({
+            "status": "error",
+            "error": "Invalid request body",
+            "detail": self.detail
+        });
+        if let Some(field) = self.field {
+            body["field"] = serde_json::Value::String(field);
+        }
+        if !self.unknown_fields.is_empty() {
+            body["unknown_fields"] = serde_json::json!(self.unknown_fields);
+        }
+        (self.status, Json(body)).into_response()
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+    StrictFields: FromRef<S>,
+{
+    type Rejection = BodyError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if !is_json(req.headers()) {
+            return Err(BodyError {
+                status: StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                field: None,
+                detail: "Expected request with `Content-Type: application/json`".to_string(),
+                unknown_fields: Vec::new(),
+            });
+        }
+
+        let bytes = Bytes::from_request(req, state).await.map_err(|rejection| BodyError {
+            status: rejection.status(),
+            field: None,
+            detail: rejection.body_text(),
+            unknown_fields: Vec::new(),
+        })?;
+        let StrictFields(strict) = StrictFields::from_ref(state);
+        parse_body(&bytes, strict).map(JsonBody)
+    }
+}
+
+/// Deserialize `bytes`, tracking the path to the field that failed. With
+/// `strict`, fields `T` does not know fail it as well; unknown fields beside
+/// a `#[serde(flatten)]` field go unnoticed, so request types avoid those.
+pub fn parse_body<T: DeserializeOwned>(bytes: &[u8], strict: bool) -> Result<T, BodyError> {
+    let mut unknown_fields = Vec::new();
+    let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+    let mut record_unknown = |path: serde_ignored::Path| unknown_fields.push(path.to_string());
+    let tracked = serde_ignored::Deserializer::new(&mut deserializer, &mut record_unknown);
+    let value = serde_path_to_error::deserialize(tracked).map_err(|error| {
+        let path = error.path().to_string();
+        let detail = error.into_inner().to_string();
+        BodyError {
+            status: StatusCode::BAD_REQUEST,
+            field: field_name(&path, &detail),
+            detail,
+            unknown_fields: Vec::new(),
+        }
+    })?;
+    deserializer.end().map_err(|error| BodyError {
+        status: StatusCode::BAD_REQUEST,
+        field: None,
+        detail: error.to_string(),
+        unknown_fields: Vec::new(),
+    })?;
+    if strict && !unknown_fields.is_empty() {
+        let listed: Vec<String> = unknown_fields.iter().map(|field| format!("`{}`", field)).collect();
+        return Err(BodyError {
+            status: StatusCode::BAD_REQUEST,
+            field: None,
+            detail: format!("unknown field(s) {}", listed.join(", ")),
+            unknown_fields,
+        });
+    }
+    Ok(value)
+}
+
+/// The failing field: the error's path, extended with the name from a
+/// "missing field" message since serde reports those against the parent
+fn field_name(path: &str, detail: &str) -> Option<String> {
+    let missing = detail
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split('`').next());
+    match (path, missing) {
+        (".", Some(name)) => Some(name.to_string()),
+        (".", None) => None,
+        (parent, Some(name)) => Some(format!("{}.{}", parent, name)),
+        (path, None) => Some(path.to_string()),
+    }
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let essence = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+    essence == "application/json" || (essence.starts_with("application/") && essence.ends_with("+json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct Outer {
+        #[allow(dead_code)]
+        inner: Inner,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Inner {
+        #[allow(dead_code)]
+        limit: u64,
+    }
+
+    #[test]
+    fn test_errors_name_nested_and_missing_fields() {
+        let error = parse_body::<Outer>(br#"{"inner": {"limit": "ten"}}"#, false).unwrap_err();
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+        assert_eq!(error.field.as_deref(), Some("inner.limit"));
+        assert!(error.detail.contains("expected u64"), "{}", error.detail);
+
+        let error = parse_body::<Outer>(br#"{"inner": {}}"#, false).unwrap_err();
+        assert_eq!(error.field.as_deref(), Some("inner.limit"));
+
+        let error = parse_body::<Outer>(b"not json", false).unwrap_err();
+        assert_eq!(error.field, None);
+    }
+
+    #[test]
+    fn test_strict_mode_lists_unknown_fields_at_any_depth() {
+        let body = br#"{"inner": {"limit": 3, "limt": 4}, "domian": "finance"}"#;
+        assert!(parse_body::<Outer>(body, false).is_ok());
+
+        let error = parse_body::<Outer>(body, true).unwrap_err();
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+        assert_eq!(error.unknown_fields, vec!["inner.limt", "domian"]);
+        assert!(error.detail.contains("`domian`"), "{}", error.detail);
+
+        assert!(parse_body::<Outer>(br#"{"inner": {"limit": 3}}"#, true).is_ok());
+    }
+}
@@ -1,15 +1,18 @@
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tokio::sync::broadcast;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use notify::{Watcher, RecursiveMode, RecommendedWatcher};
-use tokio::fs::File;
-use tokio::io::{AsyncReadExt, BufReader};
 use anyhow::Result;
 use log::{info, warn};
 
+use super::input::{read_input_file_as, InputFormat, DEFAULT_MAX_INPUT_BYTES, DEFAULT_PARQUET_SAMPLE_ROWS};
+
 /// Manages JSON file streaming with real-time updates
 pub struct JsonStreamManager {
     /// Active file watchers
@@ -23,6 +26,66 @@ struct FileWatcher {
     _watcher: notify::RecommendedWatcher,
 }
 
+/// Which writes to a watched file are published to subscribers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchMode {
+    /// Every write
+    #[default]
+    EveryChange,
+    /// Only writes that change the data's structure (keys and value types),
+    /// ignoring edits to values alone
+    SchemaChange,
+}
+
+/// Decides whether a new version of a watched file is published
+#[derive(Debug)]
+struct ChangeFilter {
+    mode: WatchMode,
+    last_fingerprint: Option<u64>,
+}
+
+impl ChangeFilter {
+    fn new(mode: WatchMode) -> Self {
+        Self { mode, last_fingerprint: None }
+    }
+
+    fn should_publish(&mut self, content: &Value) -> bool {
+        let fingerprint = schema_fingerprint(content);
+        let changed = self.last_fingerprint != Some(fingerprint);
+        self.last_fingerprint = Some(fingerprint);
+        self.mode == WatchMode::EveryChange || changed
+    }
+}
+
+/// Hash of the structure of `value`: object keys and the types at every
+/// path, with array elements merged so that adding rows of the same shape
+/// does not count as a change
+pub fn schema_fingerprint(value: &Value) -> u64 {
+    fn shape(value: &Value) -> String {
+        match value {
+            Value::Null => "null".to_string(),
+            Value::Bool(_) => "bool".to_string(),
+            Value::Number(_) => "number".to_string(),
+            Value::String(_) => "string".to_string(),
+            Value::Array(items) => {
+                let element_shapes: BTreeSet<String> = items.iter().map(shape).collect();
+                format!("[{}]", element_shapes.into_iter().collect::<Vec<_>>().join("|"))
+            }
+            Value::Object(fields) => {
+                let mut keys: Vec<(&String, &Value)> = fields.iter().collect();
+                keys.sort_by_key(|(key, _)| *key);
+                let members: Vec<String> = keys.into_iter().map(|(key, v)| format!("{:?}:{}", key, shape(v))).collect();
+                format!("{{{}}}", members.join(","))
+            }
+        }
+    }
+
+    let mut hasher = DefaultHasher::new();
+    shape(value).hash(&mut hasher);
+    hasher.finish()
+}
+
 impl JsonStreamManager {
     /// Create a new JSON stream manager
     pub fn new() -> Self {
@@ -34,6 +97,12 @@ impl JsonStreamManager {
 
     /// Start watching a JSON file for changes
     pub async fn watch_file(&self, file_path: &str) -> Result<broadcast::Receiver<Value>> {
+        self.watch_file_with_mode(file_path, WatchMode::EveryChange).await
+    }
+
+    /// Start watching a JSON file, publishing the writes selected by `mode`.
+    /// A file that is already watched keeps the mode it was first watched with.
+    pub async fn watch_file_with_mode(&self, file_path: &str, mode: WatchMode) -> Result<broadcast::Receiver<Value>> {
         let path = PathBuf::from(file_path);
         
         log::info!("JsonStreamManager: Attempting to watch file: {}", file_path);
@@ -70,7 +139,7 @@ impl JsonStreamManager {
         log::info!("JsonStreamManager: Starting file watcher...");
         
         // Start file watcher
-        self.start_file_watcher(file_path.to_string(), path, tx).await?;
+        self.start_file_watcher(file_path.to_string(), path, tx, ChangeFilter::new(mode)).await?;
         
         log::info!("JsonStreamManager: Successfully started watching file: {}", file_path);
         Ok(rx)
@@ -82,6 +151,7 @@ impl JsonStreamManager {
         file_path: String,
         path: PathBuf,
         tx: broadcast::Sender<Value>,
+        mut filter: ChangeFilter,
     ) -> Result<()> {
         log::info!("JsonStreamManager: start_file_watcher called for: {}", file_path);
         
@@ -109,6 +179,7 @@ impl JsonStreamManager {
         log::info!("JsonStreamManager: Reading initial file content...");
         if let Ok(content) = Self::read_json_file(&path).await {
             log::info!("JsonStreamManager: Successfully read initial content, sending...");
+            filter.should_publish(&content);
             if let Err(e) = tx.send(content) {
                 warn!("Failed to send initial content for {}: {}", file_path, e);
             } else {
@@ -124,7 +195,7 @@ impl JsonStreamManager {
         let path_clone = path.clone();
         log::info!("JsonStreamManager: Spawning background task for file changes...");
         tokio::spawn(async move {
-            Self::handle_file_changes(file_path_clone, path_clone, tx_clone, notify_rx).await;
+            Self::handle_file_changes(file_path_clone, path_clone, tx_clone, notify_rx, filter).await;
         });
 
         info!("Started watching file: {}", file_path);
@@ -137,27 +208,29 @@ impl JsonStreamManager {
         path: PathBuf,
         tx: broadcast::Sender<Value>,
         rx: std::sync::mpsc::Receiver<Result<notify::Event, notify::Error>>,
+        mut filter: ChangeFilter,
     ) {
         for event_result in rx {
             match event_result {
                 Ok(event) => {
-                    match event {
-                        notify::Event {
-                            kind: notify::EventKind::Modify(notify::event::ModifyKind::Data(_)),
-                            paths,
-                            ..
-                        } => {
-                            if paths.contains(&path) {
-                                if let Ok(content) = Self::read_json_file(&path).await {
-                                    if let Err(e) = tx.send(content) {
-                                        warn!("Failed to broadcast update for {}: {}", file_path, e);
-                                    } else {
-                                        info!("Broadcasted update for file: {}", file_path);
-                                    }
+                    if let notify::Event {
+                        kind: notify::EventKind::Modify(notify::event::ModifyKind::Data(_)),
+                        paths,
+                        ..
+                    } = event {
+                        if paths.contains(&path) {
+                            if let Ok(content) = Self::read_json_file(&path).await {
+                                if !filter.should_publish(&content) {
+                                    log::debug!("Skipping value-only change to {}", file_path);
+                                    continue;
+                                }
+                                if let Err(e) = tx.send(content) {
+                                    warn!("Failed to broadcast update for {}: {}", file_path, e);
+                                } else {
+                                    info!("Broadcasted update for file: {}", file_path);
                                 }
                             }
                         }
-                        _ => {}
                     }
                 }
                 Err(e) => {
@@ -167,19 +240,20 @@ impl JsonStreamManager {
         }
     }
 
-    /// Read and parse JSON file
+    /// Read and parse JSON file (gzipped files are decompressed; YAML, TOML
+    /// and Parquet files, by extension, are converted to JSON)
     async fn read_json_file(path: &PathBuf) -> Result<Value> {
         log::info!("JsonStreamManager: read_json_file called for path: {:?}", path);
         
-        let file = File::open(path).await?;
-        log::info!("JsonStreamManager: Successfully opened file");
-        
-        let mut reader = BufReader::new(file);
-        let mut content = String::new();
-        reader.read_to_string(&mut content).await?;
+        let format = InputFormat::from_path(path);
+        let path = path.clone();
+        let content = tokio::task::spawn_blocking(move || {
+            read_input_file_as(&path, format, DEFAULT_MAX_INPUT_BYTES, DEFAULT_PARQUET_SAMPLE_ROWS)
+        })
+        .await??;
         log::info!("JsonStreamManager: Successfully read file content, length: {}", content.len());
         
-        let json: Value = serde_json::from_str(&content)?;
+        let json = format.parse(&content).map_err(|e| anyhow::anyhow!(e))?;
         log::info!("JsonStreamManager: Successfully parsed JSON");
         Ok(json)
     }
@@ -243,4 +317,40 @@ mod tests {
         // Stop watching
         manager.stop_watching(file_path).await.unwrap();
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_schema_fingerprint_ignores_values() {
+        let base = json!({"orders": [{"id": 1, "total": 9.5}], "region": "EU"});
+        let values_changed = json!({"orders": [{"id": 2, "total": 12.0}, {"id": 3, "total": 1.0}], "region": "US"});
+        let key_added = json!({"orders": [{"id": 1, "total": 9.5, "currency": "EUR"}], "region": "EU"});
+        let type_changed = json!({"orders": [{"id": "1", "total": 9.5}], "region": "EU"});
+
+        assert_eq!(schema_fingerprint(&base), schema_fingerprint(&values_changed));
+        assert_ne!(schema_fingerprint(&base), schema_fingerprint(&key_added));
+        assert_ne!(schema_fingerprint(&base), schema_fingerprint(&type_changed));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_schema_change_mode_skips_value_only_writes() {
+        let manager = JsonStreamManager::new();
+        let temp_file = NamedTempFile::new().unwrap();
+        let file_path = temp_file.path().to_str().unwrap();
+        std::fs::write(file_path, json!({"price": 10, "sku": "A"}).to_string()).unwrap();
+
+        let mut receiver = manager.watch_file_with_mode(file_path, WatchMode::SchemaChange).await.unwrap();
+        assert_eq!(receiver.recv().await.unwrap(), json!({"price": 10, "sku": "A"}));
+
+        // Value-only edits are not published
+        std::fs::write(file_path, json!({"price": 11, "sku": "B"}).to_string()).unwrap();
+        let quiet = tokio::time::timeout(std::time::Duration::from_millis(500), receiver.recv()).await;
+        assert!(quiet.is_err(), "value-only change was published");
+
+        // A structural change is
+        let restructured = json!({"price": 11, "sku": "B", "stock": 4});
+        std::fs::write(file_path, restructured.to_string()).unwrap();
+        let update = tokio::time::timeout(std::time::Duration::from_secs(5), receiver.recv()).await;
+        assert_eq!(update.expect("structural change was not published").unwrap(), restructured);
+
+        manager.stop_watching(file_path).await.unwrap();
+    }
+}
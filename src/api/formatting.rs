@@ -0,0 +1,169 @@
+//! Locale-aware formatting of monetary values and other numbers in results
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Metric names treated as monetary amounts
+const MONETARY_HINTS: &[&str] = &[
+    "revenue", "cost", "price", "amount", "profit", "spend", "sales", "balance", "income", "expense", "budget", "fee",
+];
+
+/// How numbers and money in a result should be presented
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FormatOptions {
+    /// BCP 47 locale such as `en-US` or `de-DE`; defaults to `en-US`
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// ISO 4217 currency code such as `USD`
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
+impl FormatOptions {
+    pub fn is_set(&self) -> bool {
+        self.locale.is_some() || self.currency.is_some()
+    }
+
+    fn locale(&self) -> &str {
+        self.locale.as_deref().unwrap_or("en-US")
+    }
+
+    /// Prompt instruction asking the model to present figures consistently
+    pub fn instruction(&self) -> String {
+        let mut instruction = format!(
+            "FORMATTING: Write numbers using {} conventions (e.g. {}) and keep units consistent throughout.",
+            self.locale(),
+            format_number(1234567.89, self.locale(), 2)
+        );
+        if let Some(currency) = &self.currency {
+            instruction.push_str(&format!(
+                " Express every monetary value in {} (e.g. {}), converting or labelling any other currency.",
+                currency.to_uppercase(),
+                format_currency(1234567.89, currency, self.locale())
+            ));
+        }
+        instruction
+    }
+
+    /// Add a `formatted_metrics` object rendering each numeric metric for the
+    /// locale, with monetary metrics in the currency. The raw numbers are kept.
+    pub fn apply(&self, analysis: &mut Value) {
+        let Some(metrics) = analysis.get("metrics").and_then(Value::as_object) else {
+            return;
+        };
+
+        let formatted: Map<String, Value> = metrics
+            .iter()
+            .filter_map(|(name, value)| {
+                let number = value.as_f64()?;
+                let text = match &self.currency {
+                    Some(currency) if is_monetary(name) => format_currency(number, currency, self.locale()),
+                    _ => format_number(number, self.locale(), if number.fract() == 0.0 { 0 } else { 2 }),
+                };
+                Some((name.clone(), Value::String(text)))
+            })
+            .collect();
+
+        if let Some(obj) = analysis.as_object_mut() {
+            obj.insert("formatted_metrics".to_string(), Value::Object(formatted));
+        }
+    }
+}
+
+fn is_monetary(name: &str) -> bool {
+    let name = name.to_lowercase();
+    MONETARY_HINTS.iter().any(|hint| name.contains(hint))
+}
+
+/// Thousands and decimal separators for a locale's language
+fn separators(locale: &str) -> (&'static str, char) {
+    let language = locale.split(['-', '_']).next().unwrap_or("").to_lowercase();
+    match language.as_str() {
+        "de" | "es" | "it" | "nl" | "pt" | "id" | "tr" | "da" => (".", ','),
+        "fr" | "sv" | "nb" | "fi" | "pl" | "cs" | "ru" => ("\u{a0}", ','),
+        _ => (",", '.'),
+    }
+}
+
+/// Whether the currency symbol follows the amount in this locale
+fn symbol_after(locale: &str) -> bool {
+    separators(locale).1 == ','
+}
+
+/// `value` rounded to `decimals` places with the locale's separators
+pub fn format_number(value: f64, locale: &str, decimals: usize) -> String {
+    let (thousands, decimal) = separators(locale);
+    let fixed = format!("{:.*}", decimals, value.abs());
+    let (integer, fraction) = fixed.split_once('.').unwrap_or((&fixed, ""));
+
+    let mut grouped = String::new();
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push_str(thousands);
+        }
+        grouped.push(digit);
+    }
+    if !fraction.is_empty() {
+        grouped.push(decimal);
+        grouped.push_str(fraction);
+    }
+
+    if value < 0.0 && fixed.chars().any(|c| c.is_ascii_digit() && c != '0') {
+        format!("-{}", grouped)
+    } else {
+        grouped
+    }
+}
+
+/// `value` as an amount of `currency`, e.g. `$1,234.50` or `1.234,50 €`
+pub fn format_currency(value: f64, currency: &str, locale: &str) -> String {
+    let code = currency.to_uppercase();
+    let (symbol, decimals) = match code.as_str() {
+        "USD" => ("$".to_string(), 2),
+        "EUR" => ("€".to_string(), 2),
+        "GBP" => ("£".to_string(), 2),
+        "JPY" => ("¥".to_string(), 0),
+        "KRW" => ("₩".to_string(), 0),
+        "INR" => ("₹".to_string(), 2),
+        _ => (format!("{} ", code), 2),
+    };
+
+    let amount = format_number(value.abs(), locale, decimals);
+    let sign = if value < 0.0 { "-" } else { "" };
+    if symbol_after(locale) {
+        format!("{}{} {}", sign, amount, symbol.trim_end())
+    } else {
+        format!("{}{}{}", sign, symbol, amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_numbers_and_currency_follow_locale() {
+        assert_eq!(format_number(1234567.891, "en-US", 2), "1,234,567.89");
+        assert_eq!(format_number(-950.0, "en-US", 0), "-950");
+        assert_eq!(format_currency(1234567.891, "usd", "en-US"), "$1,234,567.89");
+        assert_eq!(format_currency(1234567.891, "EUR", "de-DE"), "1.234.567,89 €");
+        assert_eq!(format_currency(-42.5, "CHF", "en-GB"), "-CHF 42.50");
+        assert_eq!(format_currency(1500.4, "JPY", "ja-JP"), "¥1,500");
+    }
+
+    #[test]
+    fn test_metrics_are_formatted_without_losing_raw_values() {
+        let options = FormatOptions {
+            locale: Some("en-US".to_string()),
+            currency: Some("USD".to_string()),
+        };
+        let mut analysis = json!({"metrics": {"total_revenue": 1234567.891, "orders": 12000, "region": "EMEA"}});
+        options.apply(&mut analysis);
+
+        assert_eq!(analysis["metrics"]["total_revenue"], 1234567.891);
+        assert_eq!(analysis["formatted_metrics"]["total_revenue"], "$1,234,567.89");
+        assert_eq!(analysis["formatted_metrics"]["orders"], "12,000");
+        assert!(analysis["formatted_metrics"].get("region").is_none());
+    }
+}
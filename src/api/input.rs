@@ -0,0 +1,237 @@
+//! Reading analysis input files, transparently decompressing gzip, and
+//! converting YAML, TOML or (with the `parquet` feature) Parquet input into JSON
+
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Largest input accepted when no limit is configured (100 MiB)
+pub const DEFAULT_MAX_INPUT_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Parquet records analyzed when no sample size is configured
+pub const DEFAULT_PARQUET_SAMPLE_ROWS: usize = 1000;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Read a (possibly gzipped) text file of at most `max_bytes`.
+///
+/// Gzip is detected by a `.gz` extension or the gzip magic bytes. The limit
+/// applies to the decompressed size, so a small archive cannot expand into an
+/// unbounded allocation. Oversized input fails with `ErrorKind::FileTooLarge`.
+pub fn read_input_file(path: &Path, max_bytes: u64) -> io::Result<String> {
+    let raw = read_limited(std::fs::File::open(path)?, max_bytes)?;
+
+    let is_gzip = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gz"))
+        || raw.starts_with(&GZIP_MAGIC);
+    let bytes = if is_gzip {
+        read_limited(GzDecoder::new(raw.as_slice()), max_bytes)?
+    } else {
+        raw
+    };
+
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Read an input file of `format`: text formats as with `read_input_file`,
+/// Parquet as the JSON text of its first `parquet_sample_rows` records
+pub fn read_input_file_as(path: &Path, format: InputFormat, max_bytes: u64, parquet_sample_rows: usize) -> io::Result<String> {
+    match format {
+        InputFormat::Parquet => {
+            let raw = read_limited(std::fs::File::open(path)?, max_bytes)?;
+            parquet_sample(raw, parquet_sample_rows)
+                .map(|records| records.to_string())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+        _ => read_input_file(path, max_bytes),
+    }
+}
+
+/// The first `max_rows` records of a Parquet file as a JSON array
+#[cfg(feature = "parquet")]
+pub fn parquet_sample(bytes: Vec<u8>, max_rows: usize) -> Result<Value, String> {
+    super::parquet_input::sample_records(bytes, max_rows)
+}
+
+#[cfg(not(feature = "parquet"))]
+pub fn parquet_sample(_bytes: Vec<u8>, _max_rows: usize) -> Result<Value, String> {
+    Err(PARQUET_DISABLED.to_string())
+}
+
+/// Like `parquet_sample`, for a Parquet file sent base64-encoded
+#[cfg(feature = "parquet")]
+pub fn parquet_sample_base64(encoded: &str, max_rows: usize) -> Result<Value, String> {
+    super::parquet_input::sample_base64_records(encoded, max_rows)
+}
+
+#[cfg(not(feature = "parquet"))]
+pub fn parquet_sample_base64(_encoded: &str, _max_rows: usize) -> Result<Value, String> {
+    Err(PARQUET_DISABLED.to_string())
+}
+
+#[cfg(not(feature = "parquet"))]
+const PARQUET_DISABLED: &str = "Parquet input is not supported by this build (enable the `parquet` feature)";
+
+/// Read everything from `reader`, failing once more than `max_bytes` arrive
+fn read_limited(reader: impl Read, max_bytes: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(max_bytes.saturating_add(1)).read_to_end(&mut bytes)?;
+
+    if bytes.len() as u64 > max_bytes {
+        return Err(io::Error::new(
+            io::ErrorKind::FileTooLarge,
+            format!("Input exceeds the {} byte limit", max_bytes),
+        ));
+    }
+    Ok(bytes)
+}
+
+/// Syntax of an input document; everything is analyzed as JSON
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputFormat {
+    #[default]
+    Json,
+    #[serde(alias = "yml")]
+    Yaml,
+    Toml,
+    /// Columnar binary data, sampled into JSON records. Files are read from
+    /// disk; inline `data` must be base64-encoded.
+    Parquet,
+}
+
+impl InputFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InputFormat::Json => "json",
+            InputFormat::Yaml => "yaml",
+            InputFormat::Toml => "toml",
+            InputFormat::Parquet => "parquet",
+        }
+    }
+
+    /// Format implied by a file's extension (ignoring a trailing `.gz`), defaulting to JSON
+    pub fn from_path(path: &Path) -> Self {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("").to_lowercase();
+        let name = name.strip_suffix(".gz").unwrap_or(&name);
+        match name.rsplit_once('.').map(|(_, ext)| ext) {
+            Some("yaml" | "yml") => InputFormat::Yaml,
+            Some("toml") => InputFormat::Toml,
+            Some("parquet") => InputFormat::Parquet,
+            _ => InputFormat::Json,
+        }
+    }
+
+    /// Parse `text` in this format into a JSON value. Parquet text is the
+    /// JSON already produced by `read_input_file_as`.
+    pub fn parse(&self, text: &str) -> Result<Value, String> {
+        match self {
+            InputFormat::Json | InputFormat::Parquet => {
+                serde_json::from_str(text).map_err(|e| format!("Invalid JSON: {}", e))
+            }
+            InputFormat::Yaml => serde_yaml::from_str(text).map_err(|e| format!("Invalid YAML: {}", e)),
+            InputFormat::Toml => text
+                .parse::<toml::Table>()
+                .map(|table| toml_to_json(toml::Value::Table(table)))
+                .map_err(|e| format!("Invalid TOML: {}", e)),
+        }
+    }
+
+    /// `text` as JSON text: unchanged for JSON (and Parquet, read as JSON),
+    /// converted for YAML and TOML
+    pub fn to_json_text(&self, text: String) -> Result<String, String> {
+        match self {
+            InputFormat::Json | InputFormat::Parquet => Ok(text),
+            _ => Ok(serde_json::to_string_pretty(&self.parse(&text)?).unwrap_or_default()),
+        }
+    }
+}
+
+/// TOML has datetimes, which JSON lacks; they become RFC 3339 strings
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(dt) => Value::String(dt.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(table.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_gzipped_json_is_decompressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metrics.json.gz");
+        std::fs::write(&path, gzip(br#"{"cpu": [10, 20, 30]}"#)).unwrap();
+
+        let content = read_input_file(&path, DEFAULT_MAX_INPUT_BYTES).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(value["cpu"][2], 30);
+
+        // Detected by magic bytes even without the extension
+        let renamed = dir.path().join("metrics.json");
+        std::fs::rename(&path, &renamed).unwrap();
+        assert_eq!(read_input_file(&renamed, DEFAULT_MAX_INPUT_BYTES).unwrap(), content);
+    }
+
+    #[test]
+    fn test_decompression_bomb_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bomb.json.gz");
+        let compressed = gzip(&vec![b' '; 10 * 1024 * 1024]);
+        assert!(compressed.len() < 64 * 1024);
+        std::fs::write(&path, compressed).unwrap();
+
+        let error = read_input_file(&path, 1024 * 1024).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::FileTooLarge);
+    }
+
+    #[test]
+    fn test_yaml_and_toml_convert_to_json() {
+        let yaml = "service: checkout\nreplicas: 3\nports:\n  - 80\n  - 443\nlimits:\n  cpu: 0.5\n  debug: false\n";
+        let expected = serde_json::json!({
+            "service": "checkout",
+            "replicas": 3,
+            "ports": [80, 443],
+            "limits": {"cpu": 0.5, "debug": false}
+        });
+        assert_eq!(InputFormat::Yaml.parse(yaml).unwrap(), expected);
+
+        let toml = "service = \"checkout\"\nreplicas = 3\nports = [80, 443]\ndeployed = 2024-05-01T12:00:00Z\n\n[limits]\ncpu = 0.5\ndebug = false\n";
+        let mut expected_toml = expected.clone();
+        expected_toml["deployed"] = "2024-05-01T12:00:00Z".into();
+        assert_eq!(InputFormat::Toml.parse(toml).unwrap(), expected_toml);
+
+        assert!(InputFormat::Toml.parse("replicas = ").unwrap_err().starts_with("Invalid TOML"));
+    }
+
+    #[test]
+    fn test_format_follows_extension() {
+        assert_eq!(InputFormat::from_path(Path::new("deploy/app.YML")), InputFormat::Yaml);
+        assert_eq!(InputFormat::from_path(Path::new("Cargo.toml.gz")), InputFormat::Toml);
+        assert_eq!(InputFormat::from_path(Path::new("data.json")), InputFormat::Json);
+        assert_eq!(InputFormat::from_path(Path::new("README")), InputFormat::Json);
+        assert_eq!(InputFormat::from_path(Path::new("sales.parquet")), InputFormat::Parquet);
+    }
+
+    #[cfg(not(feature = "parquet"))]
+    #[test]
+    fn test_parquet_needs_the_feature() {
+        assert!(parquet_sample_base64("UEFSMQ==", 10).unwrap_err().contains("`parquet` feature"));
+    }
+}
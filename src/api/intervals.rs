@@ -0,0 +1,220 @@
+//! Confidence intervals stated in prediction outputs, such as
+//! "$95k–$105k (90% CI)", parsed into structured bounds
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// A range the model gave for a predicted value
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConfidenceInterval {
+    pub lower: f64,
+    pub upper: f64,
+    /// Stated confidence as a fraction (0.9 for "90% CI"), when given
+    pub confidence: Option<f64>,
+}
+
+/// One number as written, before any shared magnitude is applied
+#[derive(Debug, Clone, Copy)]
+struct Amount {
+    value: f64,
+    /// Multiplier of a `k`/`m`/`b` suffix
+    magnitude: Option<f64>,
+}
+
+impl Amount {
+    fn scaled(&self, shared: Option<f64>) -> f64 {
+        self.value * self.magnitude.or(shared).unwrap_or(1.0)
+    }
+}
+
+/// The first interval in `text`. Only text that says it is giving a
+/// confidence interval ("CI", "confidence interval", "90% confidence") is
+/// considered, so ordinary ranges such as "2019-2020" are not picked up.
+pub fn parse_interval(text: &str) -> Option<ConfidenceInterval> {
+    let lower_text = text.to_lowercase();
+    if !(lower_text.contains("confidence") || has_word(&lower_text, "ci")) {
+        return None;
+    }
+
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let starts_number = i == 0 || !(chars[i - 1].is_alphanumeric() || chars[i - 1] == '.');
+        if starts_number {
+            if let Some((first, end)) = amount_at(&chars, i) {
+                if let Some((second, _)) = separator_end(&chars, end).and_then(|next| amount_at(&chars, next)) {
+                    // "$95–105k": a suffix on the upper bound applies to both
+                    let (lower, upper) = (first.scaled(second.magnitude), second.scaled(None));
+                    if lower <= upper {
+                        return Some(ConfidenceInterval {
+                            lower,
+                            upper,
+                            confidence: stated_confidence(&lower_text),
+                        });
+                    }
+                }
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Add an `interval` to each insight whose text states one. String insights
+/// that do are turned into `{"description": ..., "interval": ...}` objects.
+/// Returns how many insights gained an interval.
+pub fn annotate_intervals(output: &mut Value) -> usize {
+    let Some(insights) = output.get_mut("insights").and_then(Value::as_array_mut) else {
+        return 0;
+    };
+
+    let mut annotated = 0;
+    for insight in insights.iter_mut() {
+        let interval = match insight {
+            Value::String(text) => parse_interval(text),
+            Value::Object(fields) if !fields.contains_key("interval") => {
+                fields.values().filter_map(Value::as_str).find_map(parse_interval)
+            }
+            _ => None,
+        };
+        let Some(interval) = interval else {
+            continue;
+        };
+        if let Value::String(text) = insight {
+            *insight = serde_json::json!({ "description": text });
+        }
+        if let Some(fields) = insight.as_object_mut() {
+            fields.insert("interval".to_string(), serde_json::json!(interval));
+            annotated += 1;
+        }
+    }
+    annotated
+}
+
+/// Parse a number such as `-2.5`, `$1,200`, `€95k` or `4%` starting at `start`
+fn amount_at(chars: &[char], start: usize) -> Option<(Amount, usize)> {
+    let mut i = start;
+    let negative = matches!(chars.get(i), Some('-' | '−'));
+    if negative {
+        i += 1;
+    }
+    if matches!(chars.get(i), Some('$' | '€' | '£')) {
+        i += 1;
+    }
+    if !chars.get(i).is_some_and(char::is_ascii_digit) {
+        return None;
+    }
+
+    let mut digits = String::new();
+    while let Some(&c) = chars.get(i) {
+        match c {
+            '0'..='9' | '.' => digits.push(c),
+            // Thousands separators, only when followed by a digit
+            ',' if chars.get(i + 1).is_some_and(char::is_ascii_digit) => {}
+            _ => break,
+        }
+        i += 1;
+    }
+    let value: f64 = digits.trim_end_matches('.').parse().ok()?;
+
+    let suffix_ends_word = |at: usize| !chars.get(at).is_some_and(|c| c.is_alphanumeric());
+    let magnitude = match chars.get(i).map(|c| c.to_ascii_lowercase()) {
+        Some('k') if suffix_ends_word(i + 1) => Some(1e3),
+        Some('m') if suffix_ends_word(i + 1) => Some(1e6),
+        Some('b') if suffix_ends_word(i + 1) => Some(1e9),
+        _ => None,
+    };
+    if magnitude.is_some() || chars.get(i) == Some(&'%') {
+        i += 1;
+    }
+
+    let value = if negative { -value } else { value };
+    Some((Amount { value, magnitude }, i))
+}
+
+/// Where the upper bound starts, if a range separator (`-`, `–`, `—`,
+/// `to`) follows `start`
+fn separator_end(chars: &[char], start: usize) -> Option<usize> {
+    let skip_spaces = |mut i: usize| {
+        while chars.get(i).is_some_and(|c| *c == ' ') {
+            i += 1;
+        }
+        i
+    };
+
+    let i = skip_spaces(start);
+    let after = match chars.get(i) {
+        Some('-' | '–' | '—') => i + 1,
+        Some('t' | 'T') if chars.get(i + 1).is_some_and(|c| c.eq_ignore_ascii_case(&'o')) && i > start => i + 2,
+        _ => return None,
+    };
+    Some(skip_spaces(after))
+}
+
+/// The percentage written just before "CI" or "confidence", as a fraction
+fn stated_confidence(text: &str) -> Option<f64> {
+    let marker = text.find("confidence").or_else(|| word_position(text, "ci"))?;
+    let before = text[..marker].trim_end().strip_suffix('%')?.trim_end();
+    let start = before.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.').len();
+    let percent: f64 = before[start..].parse().ok()?;
+    (percent > 0.0 && percent < 100.0).then_some(percent / 100.0)
+}
+
+fn has_word(text: &str, word: &str) -> bool {
+    word_position(text, word).is_some()
+}
+
+/// Byte offset of `word` appearing as a whole word in `text`
+fn word_position(text: &str, word: &str) -> Option<usize> {
+    text.match_indices(word).map(|(at, _)| at).find(|&at| {
+        let before = text[..at].chars().next_back();
+        let after = text[at + word.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interval_expressions_parse_into_bounds() {
+        let interval = parse_interval("Q4 revenue of $95k–$105k (90% CI)").unwrap();
+        assert_eq!(interval, ConfidenceInterval { lower: 95_000.0, upper: 105_000.0, confidence: Some(0.9) });
+
+        let interval = parse_interval("Churn between 2.5% to 4% at 95% confidence").unwrap();
+        assert_eq!((interval.lower, interval.upper, interval.confidence), (2.5, 4.0, Some(0.95)));
+
+        // A magnitude on the upper bound is shared; a bare "CI" has no level
+        let interval = parse_interval("Demand 1.2-1.5k units (CI)").unwrap();
+        assert_eq!((interval.lower, interval.upper, interval.confidence), (1200.0, 1500.0, None));
+
+        let interval = parse_interval("Units 1,200 to 1,450 (CI)").unwrap();
+        assert_eq!((interval.lower, interval.upper), (1200.0, 1450.0));
+
+        let interval = parse_interval("Margin change -3% - 2% (80% CI)").unwrap();
+        assert_eq!((interval.lower, interval.upper), (-3.0, 2.0));
+
+        // Ranges that are not stated as confidence intervals are left alone
+        assert_eq!(parse_interval("Sales grew from 2019-2020"), None);
+        assert_eq!(parse_interval("Circuit 5-10 is decisive"), None);
+    }
+
+    #[test]
+    fn test_insights_gain_interval_fields() {
+        let mut output = serde_json::json!({
+            "insights": [
+                "Revenue will reach $95k–$105k (90% CI)",
+                {"type": "forecast", "description": "Orders 40 to 55 per day, 95% confidence interval"},
+                {"type": "pattern", "description": "Weekends are busiest"}
+            ]
+        });
+        assert_eq!(annotate_intervals(&mut output), 2);
+
+        assert_eq!(output["insights"][0]["description"], "Revenue will reach $95k–$105k (90% CI)");
+        assert_eq!(output["insights"][0]["interval"], serde_json::json!({"lower": 95000.0, "upper": 105000.0, "confidence": 0.9}));
+        assert_eq!(output["insights"][1]["interval"]["lower"], 40.0);
+        assert_eq!(output["insights"][1]["interval"]["confidence"], 0.95);
+        assert!(output["insights"][2].get("interval").is_none());
+    }
+}
@@ -0,0 +1,141 @@
+//! Salvaging JSON objects from model output that was cut off mid-document
+
+use serde_json::{Map, Value};
+
+/// Try to turn truncated JSON into a parseable object by closing any open
+/// string, array and object. When the cut falls mid-value (a dangling key,
+/// a half-written literal), trailing members are dropped back to the last one
+/// that was complete. Returns `None` if the text does not start with an object
+/// or nothing parseable can be salvaged.
+pub fn recover_truncated_json(text: &str) -> Option<Map<String, Value>> {
+    let text = strip_code_fence(text);
+    if !text.starts_with('{') {
+        return None;
+    }
+
+    let whole = scan(text);
+    if let Some(recovered) = try_close(text, &whole) {
+        return Some(recovered);
+    }
+
+    // Fall back to each earlier member boundary in turn
+    for &cut in whole.cut_points.iter().rev() {
+        let prefix = &text[..cut];
+        if let Some(recovered) = try_close(prefix, &scan(prefix)) {
+            return Some(recovered);
+        }
+    }
+    None
+}
+
+/// Drop a leading ```json fence and anything after a closing fence
+pub fn strip_code_fence(text: &str) -> &str {
+    let text = text.trim();
+    let Some(rest) = text.strip_prefix("```") else {
+        return text;
+    };
+    let rest = rest.trim_start_matches(|c: char| c.is_ascii_alphabetic()).trim_start();
+    rest.split("```").next().unwrap_or(rest).trim()
+}
+
+struct Scan {
+    /// Brackets still open at the end, innermost last
+    open: Vec<char>,
+    in_string: bool,
+    /// The text ends with an unfinished escape sequence inside a string
+    dangling_escape: bool,
+    /// Byte offsets where the text can be cut and still end on a whole member:
+    /// just after an opening bracket or just before a separating comma
+    cut_points: Vec<usize>,
+}
+
+fn scan(text: &str) -> Scan {
+    let mut scan = Scan {
+        open: Vec::new(),
+        in_string: false,
+        dangling_escape: false,
+        cut_points: Vec::new(),
+    };
+    let mut escaped = false;
+
+    for (offset, c) in text.char_indices() {
+        if scan.in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => scan.in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => scan.in_string = true,
+            '{' | '[' => {
+                scan.open.push(c);
+                scan.cut_points.push(offset + 1);
+            }
+            '}' | ']' => {
+                scan.open.pop();
+            }
+            ',' if !scan.open.is_empty() => scan.cut_points.push(offset),
+            _ => {}
+        }
+    }
+    scan.dangling_escape = escaped;
+    scan
+}
+
+fn try_close(text: &str, scan: &Scan) -> Option<Map<String, Value>> {
+    let mut candidate = text.trim_end().to_string();
+    if scan.in_string {
+        if scan.dangling_escape {
+            candidate.pop();
+        }
+        candidate.push('"');
+    }
+    let trimmed_len = candidate.trim_end_matches([',', ' ', '\n', '\r', '\t']).len();
+    candidate.truncate(trimmed_len);
+    for bracket in scan.open.iter().rev() {
+        candidate.push(if *bracket == '{' { '}' } else { ']' });
+    }
+
+    match serde_json::from_str(&candidate) {
+        Ok(Value::Object(object)) => Some(object),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_truncated_json_is_closed() {
+        let cut_mid_string = r#"{"summary": "Revenue rose", "insights": [{"title": "Spike", "description": "Orders doubled on Fri"#;
+        assert_eq!(
+            Value::Object(recover_truncated_json(cut_mid_string).unwrap()),
+            json!({"summary": "Revenue rose", "insights": [{"title": "Spike", "description": "Orders doubled on Fri"}]})
+        );
+
+        let cut_after_comma = "```json\n{\"summary\": \"ok\", \"recommendations\": [\"Restock\",";
+        assert_eq!(
+            Value::Object(recover_truncated_json(cut_after_comma).unwrap()),
+            json!({"summary": "ok", "recommendations": ["Restock"]})
+        );
+    }
+
+    #[test]
+    fn test_dangling_members_are_dropped() {
+        let dangling_key = r#"{"summary": "ok", "metrics": {"orders": 12, "revenue""#;
+        assert_eq!(
+            Value::Object(recover_truncated_json(dangling_key).unwrap()),
+            json!({"summary": "ok", "metrics": {"orders": 12}})
+        );
+
+        let half_literal = r#"{"summary": "ok", "complete": tr"#;
+        assert_eq!(Value::Object(recover_truncated_json(half_literal).unwrap()), json!({"summary": "ok"}));
+
+        assert!(recover_truncated_json("The data shows {mixed} results").is_none());
+    }
+}
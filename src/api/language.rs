@@ -0,0 +1,189 @@
+//! The language an analysis is written in, named by the request or detected
+//! from the input's text
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Fewest stopword hits needed before a language counts as detected
+const MIN_DETECTION_HITS: usize = 3;
+
+/// Languages that can be detected and asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    English,
+    Spanish,
+    French,
+    German,
+    Portuguese,
+    Italian,
+}
+
+impl Language {
+    pub const ALL: [Language; 6] = [
+        Language::English,
+        Language::Spanish,
+        Language::French,
+        Language::German,
+        Language::Portuguese,
+        Language::Italian,
+    ];
+
+    /// ISO 639-1 code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Spanish => "es",
+            Language::French => "fr",
+            Language::German => "de",
+            Language::Portuguese => "pt",
+            Language::Italian => "it",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::Spanish => "Spanish",
+            Language::French => "French",
+            Language::German => "German",
+            Language::Portuguese => "Portuguese",
+            Language::Italian => "Italian",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        let code = code.trim().to_lowercase();
+        Self::ALL.into_iter().find(|language| language.code() == code)
+    }
+
+    /// Function words frequent in running text of this language; a few are
+    /// shared with related languages, which the distinctive ones outweigh
+    fn stopwords(&self) -> &'static [&'static str] {
+        match self {
+            Language::English => &["the", "and", "is", "are", "of", "to", "with", "was", "this", "that", "for", "not"],
+            Language::Spanish => &["el", "la", "los", "las", "y", "es", "de", "que", "en", "con", "por", "del", "muy", "pero", "para"],
+            Language::French => &["le", "la", "les", "et", "est", "des", "une", "du", "que", "pour", "avec", "pas", "très", "mais"],
+            Language::German => &["der", "die", "das", "und", "ist", "nicht", "mit", "ein", "eine", "für", "sehr", "aber", "zu"],
+            Language::Portuguese => &["o", "os", "as", "e", "é", "do", "da", "que", "em", "com", "uma", "não", "muito", "mas"],
+            Language::Italian => &["il", "lo", "gli", "e", "è", "di", "che", "con", "una", "per", "non", "molto", "ma", "della"],
+        }
+    }
+
+    /// Prompt line asking for the answer in this language
+    pub fn instruction(&self) -> String {
+        format!(
+            "LANGUAGE: Respond in {name}. Write every summary, insight and recommendation in {name}, keeping JSON keys in English.",
+            name = self.name()
+        )
+    }
+}
+
+/// A request's `language`: `auto` to match the input, or a language code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum LanguageMode {
+    Auto,
+    Fixed(Language),
+}
+
+impl TryFrom<String> for LanguageMode {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value.trim().eq_ignore_ascii_case("auto") {
+            return Ok(LanguageMode::Auto);
+        }
+        Language::from_code(&value).map(LanguageMode::Fixed).ok_or_else(|| {
+            let codes: Vec<&str> = Language::ALL.iter().map(Language::code).collect();
+            format!("unknown language '{}', expected auto or one of {}", value, codes.join(", "))
+        })
+    }
+}
+
+impl From<LanguageMode> for String {
+    fn from(mode: LanguageMode) -> Self {
+        match mode {
+            LanguageMode::Auto => "auto".to_string(),
+            LanguageMode::Fixed(language) => language.code().to_string(),
+        }
+    }
+}
+
+impl LanguageMode {
+    /// The language to answer in; `None` when `auto` finds no dominant language
+    pub fn resolve(&self, data: &Value) -> Option<Language> {
+        match self {
+            LanguageMode::Auto => detect_language(data),
+            LanguageMode::Fixed(language) => Some(*language),
+        }
+    }
+}
+
+/// The dominant language of the string values in `data`, by counting each
+/// language's stopwords. Numbers, keys and short codes carry no signal, so
+/// data without enough running text (or with a tie) detects nothing.
+pub fn detect_language(data: &Value) -> Option<Language> {
+    let mut text = String::new();
+    collect_text(data, &mut text);
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let mut hits: Vec<(Language, usize)> = Language::ALL
+        .into_iter()
+        .map(|language| {
+            let stopwords = language.stopwords();
+            (language, words.iter().filter(|word| stopwords.contains(word)).count())
+        })
+        .collect();
+    hits.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    match hits.as_slice() {
+        [(language, best), (_, runner_up), ..] if *best >= MIN_DETECTION_HITS && best > runner_up => Some(*language),
+        _ => None,
+    }
+}
+
+/// Append every string value in `data`, lowercased, to `text`
+fn collect_text(data: &Value, text: &mut String) {
+    match data {
+        Value::String(value) => {
+            text.push(' ');
+            text.push_str(&value.to_lowercase());
+        }
+        Value::Array(items) => items.iter().for_each(|item| collect_text(item, text)),
+        Value::Object(fields) => fields.values().for_each(|value| collect_text(value, text)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_dominant_language_of_text_fields_is_detected() {
+        let spanish = json!([
+            {"cliente": "Ana", "comentario": "El envío llegó tarde y la caja estaba dañada"},
+            {"cliente": "Luis", "comentario": "Muy buen producto, pero el precio es alto para la calidad"},
+            {"cliente": "Marta", "comentario": "The app crashed"}
+        ]);
+        assert_eq!(detect_language(&spanish), Some(Language::Spanish));
+
+        let english = json!({"notes": ["The order was late and the box is damaged", "Great value for the price"]});
+        assert_eq!(detect_language(&english), Some(Language::English));
+
+        // Numbers and identifiers alone say nothing about the language
+        assert_eq!(detect_language(&json!([{"sku": "A-17", "qty": 4}, {"sku": "B-2", "qty": 9}])), None);
+    }
+
+    #[test]
+    fn test_language_mode_parses_auto_and_codes() {
+        assert_eq!(serde_json::from_value::<LanguageMode>(json!("auto")).unwrap(), LanguageMode::Auto);
+        assert_eq!(serde_json::from_value::<LanguageMode>(json!("ES")).unwrap(), LanguageMode::Fixed(Language::Spanish));
+        assert!(serde_json::from_value::<LanguageMode>(json!("klingon")).is_err());
+        assert_eq!(serde_json::to_value(LanguageMode::Fixed(Language::German)).unwrap(), json!("de"));
+    }
+}
@@ -1,13 +1,45 @@
 //! Core API module for AI-powered JSON analysis
 //! Provides REST endpoints and WebSocket support for streaming JSON data
 
+pub mod attachments;
 pub mod file_streaming;
+pub mod input;
+pub mod intervals;
+#[cfg(feature = "parquet")]
+pub mod parquet_input;
+pub mod language;
+pub mod json_recovery;
+pub mod backpressure;
+pub mod circuit_breaker;
+pub mod data_profiles;
+pub mod data_source;
+pub mod dashboard_counters;
+pub mod data_stats;
+pub mod dedup;
 pub mod api_server;
 pub mod core_handlers;
 pub mod domains;
+pub mod exports;
+pub mod extract;
+pub mod formatting;
 pub mod prompts;
+pub mod prompt_ab;
+pub mod prompt_templates;
+pub mod presets;
+pub mod reembedding;
+pub mod sanitize;
+pub mod sampling;
+#[cfg(feature = "serverless")]
+pub mod serverless;
+pub mod storage_usage;
+pub mod store;
+pub mod transcripts;
+pub mod trends;
+pub mod windowing;
 pub mod integration_manager;
 pub mod auth;
 pub mod user_handlers;
+pub mod webhook_payload;
+pub mod webhooks;
 
 pub use api_server::start_api_server; 
\ No newline at end of file
@@ -0,0 +1,85 @@
+//! Sampling Parquet files into JSON records (built with the `parquet` feature)
+
+use axum::body::Bytes;
+use base64::Engine;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use serde_json::Value;
+
+/// The first `max_rows` records of a Parquet file, as a JSON array of objects
+/// keyed by column name
+pub fn sample_records(bytes: Vec<u8>, max_rows: usize) -> Result<Value, String> {
+    let reader = SerializedFileReader::new(Bytes::from(bytes)).map_err(|e| format!("Invalid Parquet: {}", e))?;
+    let rows = reader.get_row_iter(None).map_err(|e| format!("Invalid Parquet: {}", e))?;
+
+    let records = rows
+        .take(max_rows)
+        .map(|row| row.map(|row| row.to_json_value()).map_err(|e| format!("Invalid Parquet row: {}", e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Value::Array(records))
+}
+
+/// Like `sample_records`, for a Parquet file sent base64-encoded in a JSON string
+pub fn sample_base64_records(encoded: &str, max_rows: usize) -> Result<Value, String> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| format!("Parquet data must be base64-encoded: {}", e))?;
+    sample_records(bytes, max_rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    /// A three-row file of sales records, one with a missing price
+    fn sales_parquet() -> Vec<u8> {
+        let schema = parse_message_type(
+            "message sales { REQUIRED BYTE_ARRAY sku (UTF8); REQUIRED INT64 units; OPTIONAL DOUBLE price; }",
+        )
+        .unwrap();
+        let mut bytes = Vec::new();
+        let mut writer =
+            SerializedFileWriter::new(&mut bytes, Arc::new(schema), Arc::new(WriterProperties::builder().build())).unwrap();
+        let mut row_group = writer.next_row_group().unwrap();
+
+        let mut column = row_group.next_column().unwrap().unwrap();
+        let skus: Vec<ByteArray> = ["A1", "B2", "C3"].into_iter().map(ByteArray::from).collect();
+        column.typed::<ByteArrayType>().write_batch(&skus, None, None).unwrap();
+        column.close().unwrap();
+
+        let mut column = row_group.next_column().unwrap().unwrap();
+        column.typed::<Int64Type>().write_batch(&[12, 4, 30], None, None).unwrap();
+        column.close().unwrap();
+
+        let mut column = row_group.next_column().unwrap().unwrap();
+        column.typed::<DoubleType>().write_batch(&[9.5, 2.25], Some(&[1, 0, 1]), None).unwrap();
+        column.close().unwrap();
+
+        row_group.close().unwrap();
+        writer.close().unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_parquet_rows_become_json_records() {
+        let sample = sample_records(sales_parquet(), 2).unwrap();
+        assert_eq!(
+            sample,
+            json!([
+                {"sku": "A1", "units": 12, "price": 9.5},
+                {"sku": "B2", "units": 4, "price": null}
+            ])
+        );
+
+        let encoded = base64::engine::general_purpose::STANDARD.encode(sales_parquet());
+        let all = sample_base64_records(&encoded, 1000).unwrap();
+        assert_eq!(all[2], json!({"sku": "C3", "units": 30, "price": 2.25}));
+
+        assert!(sample_records(b"not parquet".to_vec(), 10).unwrap_err().starts_with("Invalid Parquet"));
+    }
+}
@@ -0,0 +1,354 @@
+//! Named analysis presets: saved request settings invoked by name
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::domains::{AnalysisType, OutputFormat};
+use super::auth::require_admin;
+use super::extract::JsonBody;
+use super::integration_manager::{AnalysisRequest, IntegrationManager};
+use super::sampling::SamplingStrategy;
+use super::store::PendingWrite;
+
+/// A reusable set of analysis settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisPreset {
+    pub name: String,
+    #[serde(default)]
+    pub domain: Option<String>,
+    #[serde(default)]
+    pub analysis_type: Option<AnalysisType>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub output_format: Option<OutputFormat>,
+    #[serde(default)]
+    pub options: PresetOptions,
+    #[serde(default = "Utc::now")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Request options a preset can carry
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PresetOptions {
+    #[serde(default)]
+    pub reasoning: Option<bool>,
+    #[serde(default)]
+    pub sampling: Option<SamplingStrategy>,
+    #[serde(default)]
+    pub timeout_seconds: Option<u64>,
+    #[serde(default)]
+    pub fields: Option<Vec<String>>,
+}
+
+impl AnalysisPreset {
+    /// Fill every setting the request leaves unset from this preset; anything
+    /// the request sets itself wins
+    pub fn expand(&self, request: &mut AnalysisRequest) {
+        if request.domain.is_none() {
+            request.domain = self.domain.clone();
+        }
+        if request.analysis_type.is_none() {
+            request.analysis_type = self.analysis_type.clone();
+        }
+        if request.model.is_none() {
+            request.model = self.model.clone();
+        }
+        if request.output_format.is_none() {
+            request.output_format = self.output_format.clone();
+        }
+        if request.reasoning.is_none() {
+            request.reasoning = self.options.reasoning;
+        }
+        if request.sampling.is_none() {
+            request.sampling = self.options.sampling.clone();
+        }
+        if request.timeout_seconds.is_none() {
+            request.timeout_seconds = self.options.timeout_seconds;
+        }
+        if request.fields.is_none() {
+            request.fields = self.options.fields.clone();
+        }
+    }
+}
+
+impl IntegrationManager {
+    /// Save a new preset, failing if the name is taken
+    pub async fn create_preset(&self, mut preset: AnalysisPreset) -> Result<AnalysisPreset, String> {
+        let mut presets = self.presets.write().await;
+        if presets.contains_key(&preset.name) {
+            return Err(format!("Preset '{}' already exists", preset.name));
+        }
+
+        preset.created_at = Utc::now();
+        preset.updated_at = preset.created_at;
+        presets.insert(preset.name.clone(), preset.clone());
+        drop(presets);
+        self.persist(PendingWrite::Preset(Box::new(preset.clone()))).await;
+        Ok(preset)
+    }
+
+    /// Replace an existing preset's settings
+    pub async fn update_preset(&self, name: &str, mut preset: AnalysisPreset) -> Option<AnalysisPreset> {
+        {
+            let mut presets = self.presets.write().await;
+            let existing = presets.get_mut(name)?;
+
+            preset.name = name.to_string();
+            preset.created_at = existing.created_at;
+            preset.updated_at = Utc::now();
+            *existing = preset.clone();
+        }
+        self.persist(PendingWrite::Preset(Box::new(preset.clone()))).await;
+        Some(preset)
+    }
+
+    pub async fn get_preset(&self, name: &str) -> Option<AnalysisPreset> {
+        self.presets.read().await.get(name).cloned()
+    }
+
+    /// All presets, sorted by name
+    pub async fn list_presets(&self) -> Vec<AnalysisPreset> {
+        let mut presets: Vec<AnalysisPreset> = self.presets.read().await.values().cloned().collect();
+        presets.sort_by(|a, b| a.name.cmp(&b.name));
+        presets
+    }
+
+    pub async fn delete_preset(&self, name: &str) -> bool {
+        let removed = self.presets.write().await.remove(name).is_some();
+        if removed {
+            self.persist(PendingWrite::DeletePreset(name.to_string())).await;
+        }
+        removed
+    }
+
+    /// Fill the request's unset settings from its named preset, if any
+    pub async fn expand_preset(&self, request: &mut AnalysisRequest) -> Result<(), String> {
+        if let Some(name) = &request.preset {
+            let preset = self.get_preset(name).await
+                .ok_or_else(|| format!("Unknown preset: {}", name))?;
+            preset.expand(request);
+        }
+        Ok(())
+    }
+}
+
+pub(super) async fn create_preset(
+    State(manager): State<Arc<IntegrationManager>>,
+    headers: HeaderMap,
+    JsonBody(preset): JsonBody<AnalysisPreset>,
+) -> Result<(StatusCode, Json<AnalysisPreset>), (StatusCode, String)> {
+    require_admin(&headers, manager.config().admin_token.as_deref()).map_err(|status| (status, String::new()))?;
+    manager
+        .create_preset(preset)
+        .await
+        .map(|preset| (StatusCode::CREATED, Json(preset)))
+        .map_err(|e| (StatusCode::CONFLICT, e))
+}
+
+pub(super) async fn list_presets(
+    State(manager): State<Arc<IntegrationManager>>,
+) -> Json<Vec<AnalysisPreset>> {
+    Json(manager.list_presets().await)
+}
+
+pub(super) async fn get_preset(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path(name): Path<String>,
+) -> Result<Json<AnalysisPreset>, StatusCode> {
+    manager.get_preset(&name).await.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+pub(super) async fn update_preset(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    JsonBody(preset): JsonBody<AnalysisPreset>,
+) -> Result<Json<AnalysisPreset>, StatusCode> {
+    require_admin(&headers, manager.config().admin_token.as_deref())?;
+    manager.update_preset(&name, preset).await.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+pub(super) async fn delete_preset(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> StatusCode {
+    if let Err(status) = require_admin(&headers, manager.config().admin_token.as_deref()) {
+        return status;
+    }
+    if manager.delete_preset(&name).await {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::integration_manager::create_integration_routes;
+    use crate::ollama::Config;
+    use crate::test_support::*;
+    use axum::{body::Body, http::header};
+    use tower::ServiceExt;
+    use serde_json::json;
+
+    fn request(body: serde_json::Value) -> AnalysisRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    fn preset() -> AnalysisPreset {
+        serde_json::from_value(json!({
+            "name": "weekly-risk",
+            "domain": "finance",
+            "analysis_type": "riskassessment",
+            "model": "llama3",
+            "output_format": "table",
+            "options": {"reasoning": true, "timeout_seconds": 30}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_preset_expands_into_request() {
+        let mut request = request(json!({
+            "integration_id": "i", "api_key": "k", "data": {}, "preset": "weekly-risk"
+        }));
+        preset().expand(&mut request);
+
+        assert_eq!(request.domain.as_deref(), Some("finance"));
+        assert_eq!(request.analysis_type, Some(AnalysisType::RiskAssessment));
+        assert_eq!(request.model.as_deref(), Some("llama3"));
+        assert!(matches!(request.output_format, Some(OutputFormat::Table)));
+        assert_eq!(request.reasoning, Some(true));
+        assert_eq!(request.timeout_seconds, Some(30));
+    }
+
+    #[test]
+    fn test_request_settings_override_preset() {
+        let mut request = request(json!({
+            "integration_id": "i", "api_key": "k", "data": {}, "preset": "weekly-risk",
+            "model": "mistral", "timeout_seconds": 5, "reasoning": false
+        }));
+        preset().expand(&mut request);
+
+        assert_eq!(request.model.as_deref(), Some("mistral"));
+        assert_eq!(request.reasoning, Some(false));
+        assert_eq!(request.timeout_seconds, Some(5));
+        assert_eq!(request.domain.as_deref(), Some("finance"));
+    }
+
+    #[tokio::test]
+    async fn test_preset_expands_analysis_request() {
+        let (providers, calls) = mock_ollama("All services healthy").await;
+        let manager = Arc::new(IntegrationManager::with_config(Config {
+            admin_token: Some("s3cret".to_string()),
+            ..Config::default()
+        }));
+        let integration = manager.create_integration(sample_request("presets")).await.unwrap();
+
+        let app = create_integration_routes(offline_providers()).with_state(manager.clone());
+        let preset = serde_json::json!({
+            "name": "ops-check",
+            "domain": "monitoring",
+            "analysis_type": "anomalydetection",
+            "model": "llama3",
+            "output_format": "bulletpoints",
+            "options": {"fields": ["insights"]}
+        });
+        let response = app
+            .clone()
+            .oneshot(
+                axum::http::Request::post("/presets")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header(header::AUTHORIZATION, "Bearer s3cret")
+                    .body(Body::from(preset.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // Presets apply to every integration, so only the admin may change them
+        let response = app
+            .clone()
+            .oneshot(
+                axum::http::Request::delete("/presets/ops-check")
+                    .header(header::AUTHORIZATION, format!("Bearer {}", integration.api_key))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // Creating the same name twice conflicts
+        let response = app
+            .oneshot(
+                axum::http::Request::post("/presets")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header(header::AUTHORIZATION, "Bearer s3cret")
+                    .body(Body::from(preset.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let mut request = analysis_request(&integration, serde_json::json!({"up": 3}));
+        request.domain = None;
+        request.model = Some("mistral".to_string());
+        request.preset = Some("ops-check".to_string());
+        let result = manager.process_analysis_request(request, &providers).await.unwrap();
+
+        // Preset fields were applied, while the request's own model won
+        assert!(result.analysis_result.get("summary").is_none());
+        let sent = calls.lock().unwrap()[0].clone();
+        assert_eq!(sent["model"], "mistral");
+        let prompt = sent["prompt"].as_str().unwrap();
+        assert!(prompt.contains("Analyze this monitoring data"));
+        assert!(prompt.contains("ANALYSIS TYPE: anomaly_detection"));
+        assert!(prompt.contains("OUTPUT FORMAT: Please format your response as bullet points"));
+    }
+
+    #[tokio::test]
+    async fn test_presets_survive_a_restart() {
+        let store = Arc::new(MemoryStore::default());
+        let manager = IntegrationManager::new().with_store(store.clone());
+        let preset = |name: &str, model: &str| -> AnalysisPreset {
+            serde_json::from_value(serde_json::json!({"name": name, "model": model})).unwrap()
+        };
+        manager.create_preset(preset("ops-check", "llama3")).await.unwrap();
+        manager.create_preset(preset("retired", "llama3")).await.unwrap();
+        manager.update_preset("ops-check", preset("ignored", "mistral")).await.unwrap();
+        assert!(manager.delete_preset("retired").await);
+
+        let restarted = IntegrationManager::new().with_store(store);
+        restarted.load_from_store().await.unwrap();
+        let presets = restarted.list_presets().await;
+        assert_eq!(presets.len(), 1);
+        assert_eq!(presets[0].name, "ops-check");
+        assert_eq!(presets[0].model.as_deref(), Some("mistral"));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_preset_is_rejected() {
+        let (providers, _) = mock_ollama("All services healthy").await;
+        let manager = Arc::new(IntegrationManager::new());
+        let integration = manager.create_integration(sample_request("no-preset")).await.unwrap();
+
+        let mut request = analysis_request(&integration, serde_json::json!({"up": 3}));
+        request.preset = Some("missing".to_string());
+        let error = manager.process_analysis_request(request, &providers).await.unwrap_err();
+        assert_eq!(error.to_string(), "Unknown preset: missing");
+    }
+}
@@ -0,0 +1,122 @@
+//! Side-by-side runs of two prompt templates on the same data, for tuning a
+//! domain's prompt
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// One side of an A/B test: literal template text, or the name of a domain
+/// whose current template is used
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum TemplateChoice {
+    Text { text: String },
+    Named { name: String },
+}
+
+/// Body of `POST /api/prompts/ab-test`
+#[derive(Debug, Deserialize)]
+pub struct PromptAbTestRequest {
+    pub a: TemplateChoice,
+    pub b: TemplateChoice,
+    pub data: Value,
+    /// Domain literal templates are rendered for; defaults to `generic`
+    #[serde(default)]
+    pub domain: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// What one template produced
+#[derive(Debug, Clone, Serialize)]
+pub struct AbVariant {
+    /// Rendered template text the prompt opened with
+    pub template: String,
+    pub output: Value,
+    pub latency_ms: u128,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptAbTestReport {
+    pub model: String,
+    pub a: AbVariant,
+    pub b: AbVariant,
+    pub diff: OutputDiff,
+    pub latency: LatencyComparison,
+}
+
+/// How the two outputs differ, field by field at the top level
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OutputDiff {
+    pub only_in_a: Vec<String>,
+    pub only_in_b: Vec<String>,
+    pub changed: Vec<String>,
+    pub unchanged: Vec<String>,
+    /// Insight and recommendation counts of `a` and `b`
+    pub insights: (usize, usize),
+    pub recommendations: (usize, usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LatencyComparison {
+    /// `a`, `b` or `tie`
+    pub faster: &'static str,
+    pub difference_ms: u128,
+}
+
+/// Compare two structured outputs
+pub fn diff_outputs(a: &Value, b: &Value) -> OutputDiff {
+    let empty = serde_json::Map::new();
+    let (a_fields, b_fields) = (a.as_object().unwrap_or(&empty), b.as_object().unwrap_or(&empty));
+    let mut diff = OutputDiff {
+        only_in_a: a_fields.keys().filter(|key| !b_fields.contains_key(*key)).cloned().collect(),
+        only_in_b: b_fields.keys().filter(|key| !a_fields.contains_key(*key)).cloned().collect(),
+        changed: Vec::new(),
+        unchanged: Vec::new(),
+        insights: (item_count(a, "insights"), item_count(b, "insights")),
+        recommendations: (item_count(a, "recommendations"), item_count(b, "recommendations")),
+    };
+    for (key, value) in a_fields {
+        match b_fields.get(key) {
+            Some(other) if other == value => diff.unchanged.push(key.clone()),
+            Some(_) => diff.changed.push(key.clone()),
+            None => {}
+        }
+    }
+    diff
+}
+
+pub fn compare_latency(a_ms: u128, b_ms: u128) -> LatencyComparison {
+    LatencyComparison {
+        faster: match a_ms.cmp(&b_ms) {
+            std::cmp::Ordering::Less => "a",
+            std::cmp::Ordering::Greater => "b",
+            std::cmp::Ordering::Equal => "tie",
+        },
+        difference_ms: a_ms.abs_diff(b_ms),
+    }
+}
+
+fn item_count(output: &Value, field: &str) -> usize {
+    output.get(field).and_then(Value::as_array).map_or(0, Vec::len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_outputs_are_diffed_by_top_level_field() {
+        let a = json!({"summary": "Sales rose", "insights": ["Q4 peak"], "metrics": {"rows": 4}});
+        let b = json!({"summary": "Sales rose 12%", "insights": ["Q4 peak", "Weekend dip"], "recommendations": ["Stock up"], "metrics": {"rows": 4}});
+        let diff = diff_outputs(&a, &b);
+
+        assert!(diff.only_in_a.is_empty());
+        assert_eq!(diff.only_in_b, ["recommendations"]);
+        assert_eq!(diff.changed, ["insights", "summary"]);
+        assert_eq!(diff.unchanged, ["metrics"]);
+        assert_eq!((diff.insights, diff.recommendations), ((1, 2), (0, 1)));
+
+        assert_eq!(compare_latency(120, 90), LatencyComparison { faster: "b", difference_ms: 30 });
+    }
+}
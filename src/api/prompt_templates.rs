@@ -0,0 +1,208 @@
+//! Versioned per-domain templates for the opening of integration analysis prompts
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::auth::require_admin;
+use super::extract::JsonBody;
+use super::integration_manager::IntegrationManager;
+use super::store::PendingWrite;
+
+/// Template used for a domain that has none of its own
+pub const DEFAULT_PROMPT_TEMPLATE: &str =
+    "Analyze this {domain} data from external system '{integration}' and provide comprehensive insights.";
+
+/// The text that opens every analysis prompt of a domain. `{domain}` and
+/// `{integration}` are replaced with the analysis's domain and integration name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub domain: String,
+    /// Starts at 1 and increases with every change; 0 is the built-in default
+    pub version: u32,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl PromptTemplate {
+    /// The built-in template for `domain`
+    pub fn builtin(domain: &str) -> Self {
+        Self {
+            domain: domain.to_string(),
+            version: 0,
+            text: DEFAULT_PROMPT_TEMPLATE.to_string(),
+            updated_at: None,
+        }
+    }
+
+    /// The version following `previous` (if any) with `text`
+    pub fn next(domain: &str, text: String, previous: Option<&PromptTemplate>) -> Self {
+        Self {
+            domain: domain.to_string(),
+            version: previous.map_or(1, |previous| previous.version + 1),
+            text,
+            updated_at: Some(Utc::now()),
+        }
+    }
+
+    /// The template text with its placeholders filled in
+    pub fn render(&self, integration_name: &str) -> String {
+        self.text
+            .replace("{domain}", &self.domain)
+            .replace("{integration}", integration_name)
+    }
+}
+
+/// Body of a request replacing a domain's template
+#[derive(Debug, Deserialize)]
+pub struct UpdatePromptTemplateRequest {
+    pub text: String,
+}
+
+impl IntegrationManager {
+    /// The prompt template currently in effect for `domain`
+    pub async fn prompt_template(&self, domain: &str) -> PromptTemplate {
+        self.prompt_templates
+            .read()
+            .await
+            .get(domain)
+            .cloned()
+            .unwrap_or_else(|| PromptTemplate::builtin(domain))
+    }
+
+    /// Replace `domain`'s prompt template, returning the new version
+    pub async fn set_prompt_template(&self, domain: &str, text: String) -> Result<PromptTemplate, String> {
+        if text.trim().is_empty() {
+            return Err("Prompt template text must not be empty".to_string());
+        }
+        let template = {
+            let mut templates = self.prompt_templates.write().await;
+            let template = PromptTemplate::next(domain, text, templates.get(domain));
+            templates.insert(domain.to_string(), template.clone());
+            template
+        };
+        self.persist(PendingWrite::PromptTemplate(Box::new(template.clone()))).await;
+        Ok(template)
+    }
+}
+
+/// The prompt template a result was produced with, as it was at the time
+pub(super) async fn get_result_prompt_template(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path((integration_id, result_id)): Path<(String, String)>,
+) -> Result<Json<PromptTemplate>, StatusCode> {
+    manager
+        .get_analysis_results(&integration_id, None)
+        .await
+        .into_iter()
+        .find(|r| r.id == result_id)
+        .and_then(|r| r.prompt_template)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+pub(super) async fn get_prompt_template(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path(domain): Path<String>,
+) -> Json<PromptTemplate> {
+    Json(manager.prompt_template(&domain).await)
+}
+
+pub(super) async fn update_prompt_template(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path(domain): Path<String>,
+    headers: HeaderMap,
+    JsonBody(request): JsonBody<UpdatePromptTemplateRequest>,
+) -> Result<Json<PromptTemplate>, (StatusCode, String)> {
+    require_admin(&headers, manager.config().admin_token.as_deref()).map_err(|status| (status, String::new()))?;
+    manager
+        .set_prompt_template(&domain, request.text)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::integration_manager::create_integration_routes;
+    use crate::ollama::{Config, ProviderRegistry};
+    use crate::test_support::*;
+    use axum::{body::Body, http::header};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_versions_increase_and_placeholders_render() {
+        let builtin = PromptTemplate::builtin("retail");
+        assert_eq!(
+            builtin.render("Shopify"),
+            "Analyze this retail data from external system 'Shopify' and provide comprehensive insights."
+        );
+
+        let first = PromptTemplate::next("retail", "Review {integration} sales.".to_string(), None);
+        let second = PromptTemplate::next("retail", "Audit {domain} sales.".to_string(), Some(&first));
+        assert_eq!((first.version, second.version), (1, 2));
+        assert_eq!(second.render("Shopify"), "Audit retail sales.");
+    }
+
+    #[tokio::test]
+    async fn test_result_prompt_template_survives_later_template_changes() {
+        let providers = ProviderRegistry::new(Arc::new(ChunkedProvider(&["Sales are flat."])));
+        let store = Arc::new(MemoryStore::default());
+        let config = Config { admin_token: Some("s3cret".into()), ..Config::default() };
+        let manager = Arc::new(IntegrationManager::with_config(config.clone()).with_store(store.clone()));
+        let integration = manager.create_integration(sample_request("templates")).await.unwrap();
+        let app = create_integration_routes(offline_providers()).with_state(manager.clone());
+
+        let put_template = |text: &str| {
+            axum::http::Request::put("/prompt-templates/generic")
+                .header(header::CONTENT_TYPE, "application/json")
+                .header(header::AUTHORIZATION, "Bearer s3cret")
+                .body(Body::from(serde_json::json!({"text": text}).to_string()))
+                .unwrap()
+        };
+        let response = app.clone().oneshot(put_template("Review {integration}'s {domain} figures.")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Templates apply to every tenant, so only the admin may change them
+        let mut anonymous = put_template("Ignore the data.");
+        anonymous.headers_mut().remove(header::AUTHORIZATION);
+        assert_eq!(app.clone().oneshot(anonymous).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+        let mut request = analysis_request(&integration, serde_json::json!({"sales": [10, 10]}));
+        request.domain = Some("generic".to_string());
+        let first = manager.process_analysis_request(request, &providers).await.unwrap();
+
+        app.clone().oneshot(put_template("Audit every {domain} figure.")).await.unwrap();
+        let mut request = analysis_request(&integration, serde_json::json!({"sales": [10, 10]}));
+        request.domain = Some("generic".to_string());
+        let second = manager.process_analysis_request(request, &providers).await.unwrap();
+        assert_eq!(second.prompt_template.as_ref().unwrap().version, 2);
+
+        let response = app
+            .oneshot(
+                axum::http::Request::get(format!("/integrations/{}/results/{}/prompt-template", integration.id, first.id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let template: serde_json::Value = serde_json::from_str(&body_string(response).await).unwrap();
+        assert_eq!(template["version"], 1);
+        assert_eq!(template["text"], "Review {integration}'s {domain} figures.");
+        assert_eq!(manager.prompt_template("generic").await.version, 2);
+
+        // After a restart the next change continues the stored versions
+        let restarted = IntegrationManager::with_config(config).with_store(store);
+        restarted.load_from_store().await.unwrap();
+        assert_eq!(restarted.prompt_template("generic").await.text, "Audit every {domain} figure.");
+        assert_eq!(restarted.set_prompt_template("generic", "Summarize.".to_string()).await.unwrap().version, 3);
+    }
+}
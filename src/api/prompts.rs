@@ -1,25 +1,82 @@
 //! Flexible prompt builder system for multi-domain AI analysis
 
-use crate::api::domains::{Domain, AnalysisType, OutputFormat, MultiDomainAnalysisRequest, DomainRegistry, ProcessingPriority};
+use crate::api::domains::{Domain, AnalysisType, OutputFormat, MultiDomainAnalysisRequest, DomainRegistry, ProcessingPriority, TrimPriority};
+use crate::ollama::model_metadata::{estimate_tokens, ModelMetadataTable};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+/// Builder without custom templates, shared by the `utils` helpers
+static SHARED_BUILDER: OnceLock<PromptBuilder> = OnceLock::new();
 
 /// Advanced prompt builder that creates domain-specific prompts
 pub struct PromptBuilder {
-    registry: DomainRegistry,
+    registry: &'static DomainRegistry,
     custom_templates: HashMap<String, String>,
 }
 
 impl PromptBuilder {
     pub fn new() -> Self {
         Self {
-            registry: DomainRegistry::new(),
+            registry: DomainRegistry::shared(),
             custom_templates: HashMap::new(),
         }
     }
 
+    /// Process-wide builder without custom templates, for hot paths that
+    /// only need the built-in domain prompts
+    pub fn shared() -> &'static PromptBuilder {
+        SHARED_BUILDER.get_or_init(PromptBuilder::new)
+    }
+
     /// Build a complete prompt for the given request
     pub fn build_prompt(&self, request: &MultiDomainAnalysisRequest, data: &str) -> String {
+        self.assemble_prompt(request, &self.reference_context(request), data)
+    }
+
+    /// Build a prompt that fits `budget`, trimming the reference context and
+    /// the data in the order given by the request's `trim_priority`
+    pub fn build_prompt_within(&self, request: &MultiDomainAnalysisRequest, data: &str, budget: &TokenBudget) -> String {
+        let reference = self.reference_context(request);
+        let overhead = estimate_tokens(&self.assemble_prompt(request, "", ""));
+        let available = budget.prompt_tokens().saturating_sub(overhead);
+
+        let (reference_tokens, data_tokens) = (estimate_tokens(&reference), estimate_tokens(data));
+        let (reference_tokens, data_tokens) = match request.trim_priority {
+            TrimPriority::ReferenceFirst => {
+                let data_tokens = data_tokens.min(available);
+                (reference_tokens.min(available - data_tokens), data_tokens)
+            }
+            TrimPriority::DataFirst => {
+                let reference_tokens = reference_tokens.min(available);
+                (reference_tokens, data_tokens.min(available - reference_tokens))
+            }
+        };
+
+        self.assemble_prompt(
+            request,
+            &truncate_to_tokens(&reference, reference_tokens),
+            &truncate_to_tokens(data, data_tokens),
+        )
+    }
+
+    /// Reference documents as one numbered block
+    fn reference_context(&self, request: &MultiDomainAnalysisRequest) -> String {
+        request
+            .context_documents
+            .iter()
+            .enumerate()
+            .map(|(i, document)| format!("[{}] {}", i + 1, document.trim()))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    fn assemble_prompt(&self, request: &MultiDomainAnalysisRequest, reference: &str, data: &str) -> String {
+        // Power users supply the complete prompt; only the data is appended
+        if let (true, Some(raw_prompt)) = (request.raw_prompt, &request.prompt) {
+            return format!("{}\n\n{}", raw_prompt, data);
+        }
+
         let base_prompt = if let Some(custom_prompt) = &request.prompt {
             // Use custom prompt if provided
             custom_prompt.clone()
@@ -28,12 +85,23 @@ impl PromptBuilder {
             self.get_domain_prompt(&request.domain, &request.analysis_type)
         };
 
-        let enhanced_prompt = self.enhance_prompt(&base_prompt, request, data);
-        self.format_output(&enhanced_prompt, &request.output_format)
+        let enhanced_prompt = self.enhance_prompt(&base_prompt, request, reference, data);
+        // A request-level format wins over the domain's default
+        let output_format = request
+            .output_format
+            .clone()
+            .or_else(|| self.registry.default_output_format(&request.domain));
+        let formatted = self.format_output(&enhanced_prompt, &output_format);
+        format!("{}\n\n{}", formatted, reasoning_instruction(request.reasoning))
     }
 
-    /// Get domain-specific prompt template
+    /// Get domain-specific prompt template, preferring custom templates over the registry
     fn get_domain_prompt(&self, domain: &Domain, analysis_type: &AnalysisType) -> String {
+        let key = format!("{}:{}", domain.as_str(), analysis_type.as_str());
+        if let Some(template) = self.custom_templates.get(&key) {
+            return template.clone();
+        }
+
         self.registry
             .get_domain_prompt(domain, analysis_type)
             .unwrap_or_else(|| self.get_fallback_prompt(analysis_type))
@@ -90,18 +158,26 @@ Provide clear, actionable analysis.".to_string()
     }
 
     /// Enhance prompt with domain-specific context and custom instructions
-    fn enhance_prompt(&self, base_prompt: &str, request: &MultiDomainAnalysisRequest, data: &str) -> String {
+    fn enhance_prompt(&self, base_prompt: &str, request: &MultiDomainAnalysisRequest, reference: &str, data: &str) -> String {
         let mut enhanced = base_prompt.to_string();
 
         // Add domain context
         enhanced.push_str(&format!("\n\nDOMAIN: {}", request.domain.as_str().to_uppercase()));
-        enhanced.push_str(&format!("\nANALYSIS TYPE: {}", request.analysis_type.as_str().to_uppercase()));
+        enhanced.push_str(&format!("\nANALYSIS TYPE: {}", request.analysis_type.as_str().replace('_', " ").to_uppercase()));
 
         // Add custom instructions if provided
         if let Some(custom_instructions) = &request.custom_instructions {
             enhanced.push_str(&format!("\n\nCUSTOM INSTRUCTIONS: {}", custom_instructions));
         }
 
+        // Reference material is kept apart from the data so it isn't analyzed as input
+        if !reference.is_empty() {
+            enhanced.push_str(&format!(
+                "\n\nREFERENCE CONTEXT (background information, not data to analyze):\n{}",
+                reference
+            ));
+        }
+
         // Add data context
         enhanced.push_str(&format!("\n\nDATA TO ANALYZE:\n{}", self.format_data_for_domain(&request.domain, data)));
 
@@ -136,8 +212,15 @@ Provide clear, actionable analysis.".to_string()
     fn format_finance_data(&self, data: &str) -> String {
         // Try to parse and structure financial data
         if let Ok(json_data) = serde_json::from_str::<Value>(data) {
+            let has_portfolio_fields = json_data
+                .as_object()
+                .map(|obj| obj.keys().any(|k| k.starts_with("portfolio")))
+                .unwrap_or(false);
+
             if let Some(portfolio_summary) = json_data.get("portfolio_summary") {
                 format!("PORTFOLIO DATA:\n{}", serde_json::to_string_pretty(portfolio_summary).unwrap_or(data.to_string()))
+            } else if has_portfolio_fields {
+                format!("PORTFOLIO DATA:\n{}", serde_json::to_string_pretty(&json_data).unwrap_or(data.to_string()))
             } else {
                 format!("FINANCIAL DATA:\n{}", serde_json::to_string_pretty(&json_data).unwrap_or(data.to_string()))
             }
@@ -185,24 +268,7 @@ Provide clear, actionable analysis.".to_string()
     /// Format output based on requested format
     fn format_output(&self, prompt: &str, output_format: &Option<OutputFormat>) -> String {
         match output_format {
-            Some(OutputFormat::Structured) => {
-                format!("{}\n\nOUTPUT FORMAT: Please structure your response with clear sections and bullet points for easy reading.", prompt)
-            }
-            Some(OutputFormat::Narrative) => {
-                format!("{}\n\nOUTPUT FORMAT: Please provide a narrative, story-like response that flows naturally.", prompt)
-            }
-            Some(OutputFormat::BulletPoints) => {
-                format!("{}\n\nOUTPUT FORMAT: Please format your response as bullet points with clear, concise statements.", prompt)
-            }
-            Some(OutputFormat::Table) => {
-                format!("{}\n\nOUTPUT FORMAT: Please format key findings in table format where appropriate.", prompt)
-            }
-            Some(OutputFormat::Json) => {
-                format!("{}\n\nOUTPUT FORMAT: Please provide your response in JSON format with structured fields.", prompt)
-            }
-            Some(OutputFormat::Custom(format)) => {
-                format!("{}\n\nOUTPUT FORMAT: {}", prompt, format)
-            }
+            Some(format) => format!("{}\n\n{}", prompt, output_format_instruction(format)),
             None => prompt.to_string(),
         }
     }
@@ -218,20 +284,9 @@ Provide clear, actionable analysis.".to_string()
         self.registry.get_supported_domains()
     }
 
-    /// Get supported analysis types for a domain
+    /// Get the analysis types that have a native prompt for a domain
     pub fn get_supported_analysis_types(&self, domain: &Domain) -> Vec<AnalysisType> {
-        // This would typically come from domain config
-        vec![
-            AnalysisType::Prediction,
-            AnalysisType::Optimization,
-            AnalysisType::Monitoring,
-            AnalysisType::Classification,
-            AnalysisType::AnomalyDetection,
-            AnalysisType::TrendAnalysis,
-            AnalysisType::RiskAssessment,
-            AnalysisType::PerformanceAnalysis,
-            AnalysisType::Custom,
-        ]
+        self.registry.supported_analysis_types(domain).unwrap_or_default()
     }
 }
 
@@ -241,13 +296,179 @@ impl Default for PromptBuilder {
     }
 }
 
+/// The `OUTPUT FORMAT:` line asking the model for `format`
+pub fn output_format_instruction(format: &OutputFormat) -> String {
+    match format {
+        OutputFormat::Structured => {
+            "OUTPUT FORMAT: Please structure your response with clear sections and bullet points for easy reading.".to_string()
+        }
+        OutputFormat::Narrative => {
+            "OUTPUT FORMAT: Please provide a narrative, story-like response that flows naturally.".to_string()
+        }
+        OutputFormat::BulletPoints => {
+            "OUTPUT FORMAT: Please format your response as bullet points with clear, concise statements.".to_string()
+        }
+        OutputFormat::Table => {
+            "OUTPUT FORMAT: Please format key findings in table format where appropriate.".to_string()
+        }
+        OutputFormat::Json => {
+            "OUTPUT FORMAT: Please provide your response in JSON format with structured fields.".to_string()
+        }
+        OutputFormat::Custom(format) => format!("OUTPUT FORMAT: {}", format.instruction()),
+    }
+}
+
+/// Heading line the model is asked to start a format's section with
+fn section_heading(format: &OutputFormat) -> String {
+    format!("=== {} ===", format.as_str().to_uppercase())
+}
+
+/// The `OUTPUT FORMAT:` instruction asking for one labeled section per format,
+/// so a single response carries every requested format
+pub fn output_sections_instruction(formats: &[OutputFormat]) -> String {
+    let mut instruction = String::from(
+        "OUTPUT FORMAT: Answer in each of the formats below. Start each answer on its own line with the heading shown, and give nothing outside those sections.",
+    );
+    for format in formats {
+        let detail = output_format_instruction(format);
+        let detail = detail.strip_prefix("OUTPUT FORMAT: ").unwrap_or(&detail);
+        instruction.push_str(&format!("\n{} {}", section_heading(format), detail));
+    }
+    instruction
+}
+
+/// Split a response into the section under each format's heading, keyed by
+/// format name. Headings are matched case-insensitively; formats whose
+/// heading is missing are left out.
+pub fn split_output_sections(response: &str, formats: &[OutputFormat]) -> BTreeMap<String, String> {
+    // ASCII lowercasing keeps byte offsets aligned with `response`
+    let lowered = response.to_ascii_lowercase();
+    let mut starts: Vec<(usize, usize, &'static str)> = formats
+        .iter()
+        .filter_map(|format| {
+            let heading = section_heading(format).to_ascii_lowercase();
+            lowered.find(&heading).map(|at| (at, heading.len(), format.as_str()))
+        })
+        .collect();
+    starts.sort_unstable();
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &(at, heading_len, name))| {
+            let end = starts.get(i + 1).map(|&(next, _, _)| next).unwrap_or(response.len());
+            (name.to_string(), response[at + heading_len..end].trim().to_string())
+        })
+        .collect()
+}
+
+/// Tokens held back from the context window for the model's response
+const RESPONSE_TOKEN_RESERVE: usize = 512;
+
+/// Token budget for a prompt, derived from the target model's context window
+#[derive(Debug, Clone, Copy)]
+pub struct TokenBudget {
+    pub context_window: usize,
+    pub reserved_for_response: usize,
+}
+
+/// Data after being fitted into a token budget
+#[derive(Debug, Clone)]
+pub struct BudgetedData {
+    pub text: String,
+    pub truncated: bool,
+    pub original_chars: usize,
+    pub used_chars: usize,
+}
+
+impl TokenBudget {
+    /// Budget for a model, looked up in the metadata table
+    pub fn for_model(model: &str, metadata: &ModelMetadataTable) -> Self {
+        Self {
+            context_window: metadata.context_window(model),
+            reserved_for_response: RESPONSE_TOKEN_RESERVE,
+        }
+    }
+
+    /// Tokens available for the prompt itself
+    pub fn prompt_tokens(&self) -> usize {
+        self.context_window.saturating_sub(self.reserved_for_response)
+    }
+
+    /// Trim `data` so that it fits alongside `overhead` (the rest of the prompt)
+    pub fn fit_data(&self, overhead: &str, data: &str) -> BudgetedData {
+        let original_chars = data.chars().count();
+        let available_tokens = self.prompt_tokens().saturating_sub(estimate_tokens(overhead));
+
+        if estimate_tokens(data) <= available_tokens {
+            return BudgetedData {
+                text: data.to_string(),
+                truncated: false,
+                original_chars,
+                used_chars: original_chars,
+            };
+        }
+
+        let text: String = data.chars().take(available_tokens * 4).collect();
+        let used_chars = text.chars().count();
+        BudgetedData {
+            text: format!("{}\n...[truncated to fit model context]", text),
+            truncated: true,
+            original_chars,
+            used_chars,
+        }
+    }
+}
+
+/// Keep roughly `tokens` worth of `text`, marking it when something was cut
+fn truncate_to_tokens(text: &str, tokens: usize) -> String {
+    if estimate_tokens(text) <= tokens {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(tokens * 4).collect();
+    format!("{}\n...[truncated to fit model context]", kept)
+}
+
+/// Heading the model is asked to put its reasoning under
+const REASONING_HEADING: &str = "REASONING:";
+/// Heading the model is asked to put its conclusions under
+const CONCLUSIONS_HEADING: &str = "CONCLUSIONS:";
+
+/// Prompt instruction asking for step-by-step reasoning or conclusions only
+pub fn reasoning_instruction(reasoning: bool) -> String {
+    if reasoning {
+        format!(
+            "Show your reasoning step by step under a '{}' heading, then give your final conclusions under a '{}' heading.",
+            REASONING_HEADING, CONCLUSIONS_HEADING
+        )
+    } else {
+        "Provide only final conclusions, no reasoning.".to_string()
+    }
+}
+
+/// Split a response into its reasoning section (if present) and the conclusions
+pub fn split_reasoning(response: &str) -> (Option<String>, String) {
+    let Some(start) = response.find(REASONING_HEADING) else {
+        return (None, response.to_string());
+    };
+
+    let after = &response[start + REASONING_HEADING.len()..];
+    match after.find(CONCLUSIONS_HEADING) {
+        Some(end) => (
+            Some(after[..end].trim().to_string()),
+            after[end + CONCLUSIONS_HEADING.len()..].trim().to_string(),
+        ),
+        None => (Some(after.trim().to_string()), response[..start].trim().to_string()),
+    }
+}
+
 /// Utility functions for prompt building
 pub mod utils {
     use super::*;
 
     /// Create a quick prompt for common use cases
     pub fn create_quick_prompt(domain: Domain, analysis_type: AnalysisType, data: &str) -> String {
-        let builder = PromptBuilder::new();
+        let builder = PromptBuilder::shared();
         let request = MultiDomainAnalysisRequest {
             file_path: "inline_data".to_string(),
             prompt: None,
@@ -257,6 +478,12 @@ pub mod utils {
             custom_instructions: None,
             output_format: Some(OutputFormat::Structured),
             priority: Some(ProcessingPriority::Normal),
+            reasoning: false,
+            context_documents: Vec::new(),
+            trim_priority: TrimPriority::default(),
+            data: None,
+            raw_prompt: false,
+            generation_options: None,
         };
         
         builder.build_prompt(&request, data)
@@ -264,8 +491,7 @@ pub mod utils {
 
     /// Validate that a domain/analysis type combination is supported
     pub fn validate_domain_analysis_combination(domain: &Domain, analysis_type: &AnalysisType) -> bool {
-        let builder = PromptBuilder::new();
-        builder.registry.get_domain_prompt(domain, analysis_type).is_some()
+        PromptBuilder::shared().registry.get_domain_prompt(domain, analysis_type).is_some()
     }
 
     /// Get example prompts for different domains
@@ -313,6 +539,12 @@ mod tests {
             custom_instructions: None,
             output_format: Some(OutputFormat::Structured),
             priority: Some(ProcessingPriority::High),
+            reasoning: false,
+            context_documents: Vec::new(),
+            trim_priority: TrimPriority::default(),
+            data: None,
+            raw_prompt: false,
+            generation_options: None,
         };
 
         let data = r#"{"portfolio_value": 100000, "cash": 20000}"#;
@@ -341,6 +573,12 @@ mod tests {
             custom_instructions: None,
             output_format: None,
             priority: None,
+            reasoning: false,
+            context_documents: Vec::new(),
+            trim_priority: TrimPriority::default(),
+            data: None,
+            raw_prompt: false,
+            generation_options: None,
         };
 
         let prompt = builder.build_prompt(&request, "test data");
@@ -353,4 +591,194 @@ mod tests {
         assert!(prompt.contains("MEDICAL DATA"));
         assert!(prompt.contains("ANOMALY DETECTION"));
     }
+
+    #[test]
+    fn test_budget_uses_larger_window_for_large_context_model() {
+        let metadata = ModelMetadataTable::new();
+        let data = "x".repeat(50_000);
+
+        let small = TokenBudget::for_model("llama2", &metadata).fit_data("Analyze:", &data);
+        assert!(small.truncated);
+        assert!(small.used_chars < data.len());
+
+        let large = TokenBudget::for_model("llama3.1:8b", &metadata).fit_data("Analyze:", &data);
+        assert!(!large.truncated);
+        assert_eq!(large.used_chars, data.len());
+        assert!(large.used_chars > small.used_chars);
+    }
+
+    #[test]
+    fn test_reasoning_toggle_changes_prompt() {
+        let builder = PromptBuilder::new();
+        let mut request = MultiDomainAnalysisRequest {
+            file_path: "test.json".to_string(),
+            prompt: None,
+            model: None,
+            domain: Domain::Finance,
+            analysis_type: AnalysisType::Prediction,
+            custom_instructions: None,
+            output_format: None,
+            priority: None,
+            reasoning: false,
+            context_documents: Vec::new(),
+            trim_priority: TrimPriority::default(),
+            data: None,
+            raw_prompt: false,
+            generation_options: None,
+        };
+
+        let concise = builder.build_prompt(&request, "{}");
+        request.reasoning = true;
+        let reasoned = builder.build_prompt(&request, "{}");
+
+        assert_ne!(concise, reasoned);
+        assert!(concise.contains("no reasoning"));
+        assert!(reasoned.contains("step by step"));
+    }
+
+    #[test]
+    fn test_split_reasoning() {
+        let (reasoning, conclusions) = split_reasoning("REASONING: Sales rose each month.\nCONCLUSIONS: Demand is growing.");
+        assert_eq!(reasoning.as_deref(), Some("Sales rose each month."));
+        assert_eq!(conclusions, "Demand is growing.");
+
+        let (reasoning, conclusions) = split_reasoning("Demand is growing.");
+        assert!(reasoning.is_none());
+        assert_eq!(conclusions, "Demand is growing.");
+    }
+
+    #[test]
+    fn test_domain_default_output_format() {
+        let builder = PromptBuilder::new();
+        let mut request = MultiDomainAnalysisRequest {
+            file_path: "test.json".to_string(),
+            prompt: None,
+            model: None,
+            domain: Domain::Finance,
+            analysis_type: AnalysisType::Prediction,
+            custom_instructions: None,
+            output_format: None,
+            priority: None,
+            reasoning: false,
+            context_documents: Vec::new(),
+            trim_priority: TrimPriority::default(),
+            data: None,
+            raw_prompt: false,
+            generation_options: None,
+        };
+
+        let defaulted = builder.build_prompt(&request, "{}");
+        assert!(defaulted.contains("table format"));
+
+        request.output_format = Some(OutputFormat::Narrative);
+        let overridden = builder.build_prompt(&request, "{}");
+        assert!(overridden.contains("narrative"));
+        assert!(!overridden.contains("table format"));
+    }
+
+    fn reference_request(trim_priority: TrimPriority) -> MultiDomainAnalysisRequest {
+        MultiDomainAnalysisRequest {
+            file_path: "test.json".to_string(),
+            prompt: None,
+            model: None,
+            domain: Domain::Finance,
+            analysis_type: AnalysisType::RiskAssessment,
+            custom_instructions: None,
+            output_format: None,
+            priority: None,
+            reasoning: false,
+            context_documents: vec!["Positions above 10% of the portfolio are concentrated.".to_string()],
+            trim_priority,
+            data: None,
+            raw_prompt: false,
+            generation_options: None,
+        }
+    }
+
+    #[test]
+    fn test_reference_context_is_separate_from_data() {
+        let builder = PromptBuilder::new();
+        let prompt = builder.build_prompt(&reference_request(TrimPriority::default()), r#"{"AAPL": 0.25}"#);
+
+        let reference_at = prompt.find("REFERENCE CONTEXT").unwrap();
+        let data_at = prompt.find("DATA TO ANALYZE").unwrap();
+        assert!(reference_at < data_at);
+
+        let reference_section = &prompt[reference_at..data_at];
+        assert!(reference_section.contains("[1] Positions above 10%"));
+        assert!(!reference_section.contains("AAPL"));
+    }
+
+    #[test]
+    fn test_trim_priority_decides_what_is_cut() {
+        let builder = PromptBuilder::new();
+        let mut request = reference_request(TrimPriority::ReferenceFirst);
+        request.context_documents = vec!["glossary ".repeat(1_000)];
+        let data = "d".repeat(8_000);
+        let budget = TokenBudget { context_window: 4096, reserved_for_response: 512 };
+
+        let reference_trimmed = builder.build_prompt_within(&request, &data, &budget);
+        assert!(reference_trimmed.contains(&data));
+        assert!(reference_trimmed.contains("[truncated to fit model context]"));
+
+        request.trim_priority = TrimPriority::DataFirst;
+        let data_trimmed = builder.build_prompt_within(&request, &data, &budget);
+        assert!(!data_trimmed.contains(&data));
+        assert!(data_trimmed.contains(request.context_documents[0].trim()));
+    }
+
+    #[test]
+    fn test_raw_prompt_skips_enhancements() {
+        let builder = PromptBuilder::new();
+        let request = MultiDomainAnalysisRequest::builder()
+            .domain(Domain::Finance)
+            .prompt("Summarize exposure by sector.")
+            .raw_prompt(true)
+            .build();
+
+        let prompt = builder.build_prompt(&request, r#"{"AAPL": 0.25}"#);
+        assert_eq!(prompt, "Summarize exposure by sector.\n\n{\"AAPL\": 0.25}");
+
+        let enhanced = builder.build_prompt(&MultiDomainAnalysisRequest { raw_prompt: false, ..request }, r#"{"AAPL": 0.25}"#);
+        assert!(enhanced.starts_with("Summarize exposure by sector."));
+        assert!(enhanced.contains("DOMAIN: FINANCE"));
+        assert!(enhanced.contains("ANALYSIS TYPE:"));
+        assert!(enhanced.contains("OUTPUT FORMAT:"));
+    }
+
+    #[test]
+    fn test_output_sections_are_split_by_heading() {
+        let formats = [OutputFormat::Narrative, OutputFormat::Json];
+        let instruction = output_sections_instruction(&formats);
+        assert!(instruction.contains("=== NARRATIVE === Please provide a narrative"));
+        assert!(instruction.contains("=== JSON === Please provide your response in JSON"));
+
+        let response = "=== Json ===\n{\"summary\": \"ok\"}\n\n=== NARRATIVE ===\nSales held steady.";
+        let sections = split_output_sections(response, &formats);
+        assert_eq!(sections["json"], "{\"summary\": \"ok\"}");
+        assert_eq!(sections["narrative"], "Sales held steady.");
+        assert!(split_output_sections("No headings here", &formats).is_empty());
+    }
+
+    #[test]
+    fn test_domain_registry_is_built_once_across_prompt_builds() {
+        let shared = DomainRegistry::shared() as *const DomainRegistry;
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                std::thread::spawn(|| {
+                    (0..50)
+                        .map(|_| {
+                            utils::create_quick_prompt(Domain::Finance, AnalysisType::Prediction, "{}");
+                            PromptBuilder::new().registry as *const DomainRegistry as usize
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert!(handle.join().unwrap().iter().all(|&registry| registry == shared as usize));
+        }
+        assert!(std::ptr::eq(PromptBuilder::shared(), PromptBuilder::shared()));
+        assert!(std::ptr::eq(PromptBuilder::shared().registry, DomainRegistry::shared()));
+    }
 }
@@ -0,0 +1,273 @@
+//! Progress of the background task that re-embeds stored results after the
+//! embedding model changes
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+
+use super::integration_manager::{AnalysisStatus, IntegrationAnalysisResult, IntegrationManager};
+use super::store::PendingWrite;
+use crate::ollama::{LlmProvider, ProviderRegistry};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReembedStatus {
+    Running,
+    Completed,
+}
+
+/// Counts of one re-embedding run, updated as it goes
+#[derive(Debug, Clone, Serialize)]
+pub struct ReembedProgress {
+    pub id: String,
+    /// Embedding model results are being brought up to
+    pub model: String,
+    pub status: ReembedStatus,
+    /// Results that needed a new embedding when the run started
+    pub total: usize,
+    pub embedded: usize,
+    /// Results already embedded with `model`, left untouched
+    pub skipped: usize,
+    /// Results the model failed to embed; they keep their old stamp
+    pub failed: usize,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl ReembedProgress {
+    pub fn start(model: &str) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            model: model.to_string(),
+            status: ReembedStatus::Running,
+            total: 0,
+            embedded: 0,
+            skipped: 0,
+            failed: 0,
+            started_at: Utc::now(),
+            finished_at: None,
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.status = ReembedStatus::Completed;
+        self.finished_at = Some(Utc::now());
+    }
+}
+
+/// The text a result is embedded from: its summary, or the whole analysis
+/// when it has none
+pub fn embedding_text(result: &IntegrationAnalysisResult) -> String {
+    match result.analysis_result.get("summary") {
+        Some(serde_json::Value::String(summary)) => summary.clone(),
+        _ => result.analysis_result.to_string(),
+    }
+}
+
+impl IntegrationManager {
+    /// Embed a completed result with the configured embedding model. A failed
+    /// embedding leaves the result unembedded rather than failing it.
+    pub(super) async fn embed_result(&self, result: &mut IntegrationAnalysisResult, providers: &ProviderRegistry) {
+        let Some(model) = &self.config.embedding_model else {
+            return;
+        };
+        match providers.for_model(model).embed(model, &embedding_text(result)).await {
+            Ok(embedding) => {
+                result.embedding = Some(embedding);
+                result.embedding_model = Some(model.clone());
+            }
+            Err(e) => log::warn!("Could not embed result {} with {}: {}", result.id, model, e),
+        }
+    }
+
+    /// Start re-embedding, in the background, every completed result not
+    /// already embedded with the configured embedding model. Fails when no
+    /// embedding model is configured or a run is already in progress.
+    pub async fn start_reembedding(self: &Arc<Self>, providers: &ProviderRegistry) -> Result<ReembedProgress, String> {
+        let model = self
+            .config
+            .embedding_model
+            .clone()
+            .ok_or("No embedding model is configured (EMBEDDING_MODEL)")?;
+        {
+            let mut current = self.reembedding.lock().unwrap();
+            if current.as_ref().is_some_and(|run| run.status == ReembedStatus::Running) {
+                return Err("A re-embedding run is already in progress".to_string());
+            }
+            *current = Some(ReembedProgress::start(&model));
+        }
+
+        let mut pending = Vec::new();
+        let mut skipped = 0;
+        for result in self.analysis_results.read().await.values().flatten() {
+            if result.status != AnalysisStatus::Completed {
+                continue;
+            }
+            if result.embedding_model.as_deref() == Some(model.as_str()) {
+                skipped += 1;
+            } else {
+                pending.push((result.integration_id.clone(), result.id.clone()));
+            }
+        }
+        let progress = self.update_reembedding(|run| {
+            run.total = pending.len();
+            run.skipped = skipped;
+        });
+
+        let manager = self.clone();
+        let provider = providers.for_model(&model);
+        tokio::spawn(async move {
+            for (integration_id, result_id) in pending {
+                let embedded = manager.reembed_one(provider.as_ref(), &model, &integration_id, &result_id).await;
+                manager.update_reembedding(|run| match embedded {
+                    Ok(true) => run.embedded += 1,
+                    // Deleted since the run started
+                    Ok(false) => run.skipped += 1,
+                    Err(_) => run.failed += 1,
+                });
+            }
+            let run = manager.update_reembedding(ReembedProgress::finish);
+            log::info!(
+                "Re-embedding with {} finished: {} embedded, {} skipped, {} failed",
+                run.model, run.embedded, run.skipped, run.failed
+            );
+        });
+        Ok(progress)
+    }
+
+    /// The latest re-embedding run, if one has been started
+    pub fn reembedding_progress(&self) -> Option<ReembedProgress> {
+        self.reembedding.lock().unwrap().clone()
+    }
+
+    pub(super) fn update_reembedding(&self, update: impl FnOnce(&mut ReembedProgress)) -> ReembedProgress {
+        let mut current = self.reembedding.lock().unwrap();
+        let run = current.get_or_insert_with(|| ReembedProgress::start(""));
+        update(run);
+        run.clone()
+    }
+
+    /// Embed one stored result with `model` and stamp it. Returns whether the
+    /// result still existed.
+    pub(super) async fn reembed_one(
+        &self,
+        provider: &dyn LlmProvider,
+        model: &str,
+        integration_id: &str,
+        result_id: &str,
+    ) -> Result<bool, String> {
+        let text = {
+            let results = self.analysis_results.read().await;
+            let stored = results.get(integration_id).and_then(|results| results.iter().find(|r| r.id == result_id));
+            match stored {
+                Some(result) => embedding_text(result),
+                None => return Ok(false),
+            }
+        };
+
+        let embedding = provider.embed(model, &text).await.map_err(|e| {
+            log::warn!("Could not re-embed result {} with {}: {}", result_id, model, e);
+            e.to_string()
+        })?;
+
+        let updated = {
+            let mut results = self.analysis_results.write().await;
+            let stored = results
+                .get_mut(integration_id)
+                .and_then(|results| results.iter_mut().find(|r| r.id == result_id));
+            let Some(result) = stored else {
+                return Ok(false);
+            };
+            result.embedding = Some(embedding);
+            result.embedding_model = Some(model.to_string());
+            self.storage_usage.update(result);
+            result.clone()
+        };
+        self.persist(PendingWrite::Result(Box::new(updated))).await;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::store::{IntegrationStore, JsonFileStore};
+    use crate::ollama::Config;
+    use crate::test_support::*;
+
+    #[tokio::test]
+    async fn test_reembedding_stamps_stale_results_and_skips_current_ones() {
+        let config = Config {
+            embedding_model: Some("embed-v2".to_string()),
+            ..Config::default()
+        };
+        let manager = Arc::new(IntegrationManager::with_config(config));
+        let integration = manager.create_integration(sample_request("embeddings")).await.unwrap();
+        let provider = Arc::new(EmbeddingProvider::default());
+        let providers = ProviderRegistry::new(provider.clone());
+
+        let results: Vec<IntegrationAnalysisResult> = [None, Some("embed-v1"), Some("embed-v2")]
+            .into_iter()
+            .enumerate()
+            .map(|(i, model)| IntegrationAnalysisResult {
+                analysis_result: serde_json::json!({"summary": format!("summary {}", i)}),
+                embedding_model: model.map(str::to_string),
+                ..sample_result(&integration.id, AnalysisStatus::Completed)
+            })
+            .chain([sample_result(&integration.id, AnalysisStatus::Failed)])
+            .collect();
+        seed_results(&manager, &integration.id, results).await;
+
+        let started = manager.start_reembedding(&providers).await.unwrap();
+        assert_eq!((started.total, started.skipped), (2, 1));
+
+        let mut progress = started;
+        for _ in 0..100 {
+            progress = manager.reembedding_progress().unwrap();
+            if progress.status == ReembedStatus::Completed {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(progress.status, ReembedStatus::Completed);
+        assert_eq!((progress.embedded, progress.skipped, progress.failed), (2, 1, 0));
+
+        // Only the stale results were sent to the model, and every completed result is now current
+        let mut embedded = provider.embedded.lock().unwrap().clone();
+        embedded.sort();
+        assert_eq!(embedded, ["summary 0", "summary 1"]);
+        for result in manager.get_analysis_results(&integration.id, None).await {
+            let expected = (result.status == AnalysisStatus::Completed).then_some("embed-v2");
+            assert_eq!(result.embedding_model.as_deref(), expected);
+        }
+
+        // A second run has nothing left to do
+        let rerun = manager.start_reembedding(&providers).await.unwrap();
+        assert_eq!((rerun.total, rerun.skipped), (0, 3));
+
+        // New analyses are embedded as they complete
+        let result = manager
+            .process_analysis_request(analysis_request(&integration, serde_json::json!({"stock": 3})), &providers)
+            .await
+            .unwrap();
+        assert_eq!(result.embedding_model.as_deref(), Some("embed-v2"));
+        assert_eq!(result.embedding, Some(vec!["Stock is low".len() as f32]));
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_are_stored_but_not_serialized() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JsonFileStore::new(dir.path());
+        let mut result = sample_result("integration-1", AnalysisStatus::Completed);
+        result.embedding = Some(vec![0.5, 1.5]);
+        result.embedding_model = Some("embed-v1".to_string());
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert!(json.get("embedding").is_none());
+        assert_eq!(json["embedding_model"], "embed-v1");
+
+        store.save_result(&result).await.unwrap();
+        let loaded = store.load_results("integration-1").await.unwrap();
+        assert_eq!(loaded[0].embedding, Some(vec![0.5, 1.5]));
+    }
+}
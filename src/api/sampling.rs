@@ -0,0 +1,140 @@
+//! Sampling strategies for picking representative rows out of input data
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// How rows are picked when only a sample of the data is kept
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum SamplingStrategy {
+    /// The first rows
+    #[default]
+    Head,
+    /// The last rows (the most recent ones for time-ordered data)
+    Tail,
+    /// Rows picked at random; the same seed always picks the same rows
+    Random { seed: u64 },
+    /// Rows picked round-robin across the distinct values of `key`
+    Stratified { key: String },
+}
+
+impl SamplingStrategy {
+    /// Indices of up to `n` items to keep, in their original order
+    pub fn sample_indices(&self, items: &[Value], n: usize) -> Vec<usize> {
+        let len = items.len();
+        if len <= n {
+            return (0..len).collect();
+        }
+
+        let mut indices: Vec<usize> = match self {
+            SamplingStrategy::Head => (0..n).collect(),
+            SamplingStrategy::Tail => (len - n..len).collect(),
+            SamplingStrategy::Random { seed } => {
+                // Partial Fisher-Yates shuffle driven by a seeded generator
+                let mut rng = SplitMix64(*seed);
+                let mut pool: Vec<usize> = (0..len).collect();
+                for i in 0..n {
+                    let j = i + (rng.next() % (len - i) as u64) as usize;
+                    pool.swap(i, j);
+                }
+                pool.truncate(n);
+                pool
+            }
+            SamplingStrategy::Stratified { key } => {
+                let mut strata: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+                for (index, item) in items.iter().enumerate() {
+                    let stratum = match item.get(key) {
+                        Some(Value::String(s)) => s.clone(),
+                        Some(other) => other.to_string(),
+                        None => String::new(),
+                    };
+                    strata.entry(stratum).or_default().push(index);
+                }
+
+                let mut picked = Vec::with_capacity(n);
+                let mut round = 0;
+                while picked.len() < n {
+                    for members in strata.values() {
+                        if let Some(index) = members.get(round) {
+                            picked.push(*index);
+                            if picked.len() == n {
+                                break;
+                            }
+                        }
+                    }
+                    round += 1;
+                }
+                picked
+            }
+        };
+
+        indices.sort_unstable();
+        indices
+    }
+
+    /// Up to `n` items picked by this strategy
+    pub fn sample(&self, items: &[Value], n: usize) -> Vec<Value> {
+        self.sample_indices(items, n)
+            .into_iter()
+            .map(|index| items[index].clone())
+            .collect()
+    }
+}
+
+/// Small deterministic generator so seeded samples are reproducible across runs
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rows(count: usize) -> Vec<Value> {
+        (0..count).map(|i| json!({"id": i})).collect()
+    }
+
+    #[test]
+    fn test_random_sampling_is_reproducible_with_seed() {
+        let items = rows(100);
+        let strategy = SamplingStrategy::Random { seed: 42 };
+
+        let first = strategy.sample(&items, 5);
+        let second = strategy.sample(&items, 5);
+        assert_eq!(first, second);
+        assert_eq!(first.len(), 5);
+
+        let other = SamplingStrategy::Random { seed: 7 }.sample(&items, 5);
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn test_stratified_sampling_covers_each_stratum() {
+        let mut items: Vec<Value> = (0..10).map(|i| json!({"region": "eu", "id": i})).collect();
+        items.push(json!({"region": "us", "id": 10}));
+        items.push(json!({"region": "apac", "id": 11}));
+
+        let strategy = SamplingStrategy::Stratified { key: "region".to_string() };
+        let sample = strategy.sample(&items, 3);
+
+        let mut regions: Vec<&str> = sample.iter().map(|v| v["region"].as_str().unwrap()).collect();
+        regions.sort_unstable();
+        assert_eq!(regions, vec!["apac", "eu", "us"]);
+    }
+
+    #[test]
+    fn test_tail_sampling_keeps_latest_rows() {
+        let sample = SamplingStrategy::Tail.sample(&rows(10), 3);
+        assert_eq!(sample, vec![json!({"id": 7}), json!({"id": 8}), json!({"id": 9})]);
+    }
+}
@@ -0,0 +1,73 @@
+//! Cleanup of model output before it is stored, so control characters and
+//! runaway tokens cannot break CSV or JSON consumers downstream
+
+use serde_json::Value;
+
+/// Marks where an overlong token was cut
+const TRUNCATION_MARKER: char = '…';
+
+/// Strip control characters (other than newline and tab) from every string
+/// in `output`, and cut whitespace-separated tokens longer than
+/// `max_token_chars`. Returns how many strings were changed.
+pub fn sanitize_output(output: &mut Value, max_token_chars: usize) -> usize {
+    match output {
+        Value::String(text) => match sanitize_text(text, max_token_chars) {
+            Some(clean) => {
+                *text = clean;
+                1
+            }
+            None => 0,
+        },
+        Value::Array(items) => items.iter_mut().map(|item| sanitize_output(item, max_token_chars)).sum(),
+        Value::Object(fields) => fields.values_mut().map(|value| sanitize_output(value, max_token_chars)).sum(),
+        _ => 0,
+    }
+}
+
+/// The cleaned text, or `None` when `text` is already clean
+fn sanitize_text(text: &str, max_token_chars: usize) -> Option<String> {
+    let is_stripped = |c: char| c.is_control() && c != '\n' && c != '\t';
+    let has_long_token = text.split_whitespace().any(|token| token.chars().count() > max_token_chars);
+    if !text.chars().any(is_stripped) && !has_long_token {
+        return None;
+    }
+
+    let mut clean = String::with_capacity(text.len());
+    let mut token_chars = 0;
+    for c in text.chars().filter(|c| !is_stripped(*c)) {
+        if c.is_whitespace() {
+            token_chars = 0;
+        } else {
+            token_chars += 1;
+            if token_chars == max_token_chars + 1 {
+                clean.push(TRUNCATION_MARKER);
+            }
+            if token_chars > max_token_chars {
+                continue;
+            }
+        }
+        clean.push(c);
+    }
+    Some(clean)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_control_characters_and_long_tokens_are_cleaned() {
+        let mut output = json!({
+            "summary": "Revenue\u{0} rose\u{1b}[31m 12%\r\nin Q3",
+            "insights": [{"description": "Hash aaaaaaaaaaaaaaaaaaaa ends here"}, "clean\ttext"],
+            "metrics": {"rows": 3}
+        });
+        assert_eq!(sanitize_output(&mut output, 8), 2);
+
+        assert_eq!(output["summary"], "Revenue rose[31m 12%\nin Q3");
+        assert_eq!(output["insights"][0]["description"], "Hash aaaaaaaa… ends here");
+        assert_eq!(output["insights"][1], "clean\ttext");
+        assert_eq!(output["metrics"]["rows"], 3);
+    }
+}
@@ -1,34 +1,81 @@
 //! Serverless compatibility layer for platforms like Vercel
 
 use axum::{
-    extract::{Path, Query, State},
+    extract::State,
     http::StatusCode,
-    response::Json,
+    response::{IntoResponse, Json, Response},
     routing::{get, post},
     Router,
 };
+use serde::Deserialize;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
 
-use crate::api::{core_handlers, domains, prompts};
+use crate::api::core_handlers::input_error_status;
+use crate::api::domains::{AnalysisType, OutputFormat};
 use crate::api::file_streaming::JsonStreamManager;
+use crate::api::input::{read_input_file_as, InputFormat};
+use crate::api::integration_manager::{
+    AnalysisRequest, CreateIntegrationRequest, Integration, IntegrationConfig, IntegrationManager, SystemType,
+};
+use crate::ollama::{Config, ProviderRegistry};
 
 /// Serverless API state
 #[derive(Clone)]
 pub struct ServerlessState {
-    pub json_manager: std::sync::Arc<JsonStreamManager>,
+    pub json_manager: Arc<JsonStreamManager>,
+    pub manager: Arc<IntegrationManager>,
+    /// Integration every serverless analysis runs under
+    pub integration: Integration,
+    pub providers: ProviderRegistry,
+}
+
+/// Body of `POST /api/ollama/process`
+#[derive(Debug, Deserialize)]
+pub struct ServerlessProcessRequest {
+    pub file_path: PathBuf,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub domain: Option<String>,
+    #[serde(default)]
+    pub analysis_type: Option<AnalysisType>,
+    /// Defaults to the deployment's `DEFAULT_OUTPUT_FORMAT`
+    #[serde(default)]
+    pub output_format: Option<OutputFormat>,
+    /// Defaults to the format implied by the file's extension
+    #[serde(default)]
+    pub input_format: Option<InputFormat>,
 }
 
 /// Create serverless router
-pub fn create_serverless_router() -> Router {
-    let json_manager = std::sync::Arc::new(JsonStreamManager::new());
-    let state = ServerlessState { json_manager };
+pub async fn create_serverless_router(config: Config, providers: ProviderRegistry) -> Result<Router, String> {
+    let manager = Arc::new(IntegrationManager::with_config(config));
+    let integration = manager
+        .create_integration(CreateIntegrationRequest {
+            name: "serverless".to_string(),
+            system_type: SystemType::FileSystem,
+            webhook_url: None,
+            webhook_secret: None,
+            configuration: IntegrationConfig::default(),
+            expires_at: None,
+            tags: Vec::new(),
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+    let state = ServerlessState {
+        json_manager: Arc::new(JsonStreamManager::new()),
+        manager,
+        integration,
+        providers,
+    };
 
-    Router::new()
+    Ok(Router::new()
         .route("/health", get(health_check))
         .route("/api/ollama/process", post(serverless_ollama_process))
         .route("/api/available-files", get(list_available_files))
-        .with_state(state)
+        .with_state(state))
 }
 
 /// Health check for serverless
@@ -41,78 +88,76 @@ pub async fn health_check() -> Json<Value> {
     }))
 }
 
-/// Simplified Ollama processing for serverless
+/// Analyze a file through the same pipeline as `/analyze`, without file watching
 pub async fn serverless_ollama_process(
-    State(_state): State<ServerlessState>,
-    Json(payload): Json<serde_json::Value>,
-) -> Result<Json<Value>, StatusCode> {
-    // Extract parameters
-    let file_path = payload.get("file_path")
-        .and_then(|v| v.as_str())
-        .ok_or(StatusCode::BAD_REQUEST)?;
-    
-    let prompt = payload.get("prompt")
-        .and_then(|v| v.as_str())
-        .unwrap_or("Analyze this data and provide insights");
-    
-    let model = payload.get("model")
-        .and_then(|v| v.as_str())
-        .unwrap_or("llama2");
-
-    // Simple processing without file watching (serverless limitation)
-    let result = process_json_data(file_path, prompt, model).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    Ok(Json(result))
-}
+    State(state): State<ServerlessState>,
+    Json(payload): Json<ServerlessProcessRequest>,
+) -> Result<Json<Value>, Response> {
+    let config = state.manager.config();
+    let format = payload.input_format.unwrap_or_else(|| InputFormat::from_path(&payload.file_path));
+    let (path, max_bytes, sample_rows) = (payload.file_path.clone(), config.max_input_bytes, config.parquet_sample_rows);
+    let text = tokio::task::spawn_blocking(move || read_input_file_as(&path, format, max_bytes, sample_rows))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?
+        .map_err(|e| {
+            log::error!("Failed to read file {}: {}", payload.file_path.display(), e);
+            input_error_status(&e).into_response()
+        })?;
+    let data = format
+        .parse(&text)
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(serde_json::json!({"status": "error", "error": e}))).into_response())?;
 
-/// Process JSON data (serverless version)
-async fn process_json_data(
-    file_path: &str,
-    prompt: &str,
-    model: &str,
-) -> Result<Value, Box<dyn std::error::Error>> {
-    // Read file content
-    let file_content = std::fs::read_to_string(file_path)?;
-    
-    // Create enhanced prompt
-    let enhanced_prompt = format!(
-        "{}\n\nData: {}",
-        prompt,
-        serde_json::to_string_pretty(&file_content)?
+    let mut request: AnalysisRequest = serde_json::from_value(serde_json::json!({
+        "integration_id": state.integration.id,
+        "api_key": state.integration.api_key,
+        "data": data
+    }))
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+    request.model = payload.model;
+    request.domain = payload.domain;
+    request.analysis_type = payload.analysis_type;
+    request.output_format = payload.output_format;
+    // Resolve the defaults up front so the response can name what was used
+    state.manager.apply_defaults(&mut request);
+    let (model, domain, analysis_type, output_format) = (
+        request.model.clone(),
+        request.domain.clone(),
+        request.analysis_type.clone(),
+        request.output_format.clone(),
     );
 
-    // For serverless, we'll return a mock response
-    // In production, you'd integrate with your AI service
-    Ok(serde_json::json!({
+    let result = state
+        .manager
+        .process_analysis_request(request, &state.providers)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(serde_json::json!({
         "status": "success",
-        "file_path": file_path,
-        "prompt": prompt,
+        "file_path": payload.file_path,
         "model": model,
-        "analysis": "Serverless analysis completed - AI integration needed",
-        "data_processed": file_content.len(),
+        "domain": domain,
+        "analysis_type": analysis_type.as_ref().map(|t| t.as_str()),
+        "output_format": output_format.as_ref().map(|f| f.as_str()),
+        "result": result,
         "mode": "serverless"
-    }))
+    })))
 }
 
 /// List available files (serverless version)
 pub async fn list_available_files() -> Json<Value> {
     let current_dir = std::env::current_dir().unwrap_or_default();
     let mut json_files = Vec::new();
-    
+
     if let Ok(entries) = std::fs::read_dir(&current_dir) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if let Some(extension) = path.extension() {
-                    if extension == "json" {
-                        json_files.push(path.to_string_lossy().to_string());
-                    }
-                }
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|extension| extension == "json") {
+                json_files.push(path.to_string_lossy().to_string());
             }
         }
     }
-    
+
     Json(serde_json::json!({
         "status": "success",
         "current_directory": current_dir.to_string_lossy(),
@@ -121,3 +166,59 @@ pub async fn list_available_files() -> Json<Value> {
         "mode": "serverless"
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::prompts::output_format_instruction;
+    use axum::body::Body;
+    use axum::http::Request;
+    use crate::test_support::RecordingProvider;
+    use tower::ServiceExt;
+
+    async fn process(app: Router, body: Value) -> (StatusCode, Value) {
+        let response = app
+            .oneshot(
+                Request::post("/api/ollama/process")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_process_uses_requested_or_default_output_format() {
+        let dir = std::env::temp_dir().join(format!("serverless-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("orders.json");
+        std::fs::write(&file, r#"[{"day": "sat", "orders": 40}, {"day": "mon", "orders": 20}]"#).unwrap();
+
+        let provider = Arc::new(RecordingProvider::new(r#"{"summary": "Orders doubled", "insights": ["Weekend peak"]}"#));
+        let config = Config { default_output_format: Some(OutputFormat::Narrative), ..Config::default() };
+        let app = create_serverless_router(config, ProviderRegistry::new(provider.clone())).await.unwrap();
+
+        let (status, body) = process(
+            app.clone(),
+            serde_json::json!({"file_path": file, "domain": "business", "output_format": "table"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((body["output_format"].as_str(), body["domain"].as_str()), (Some("table"), Some("business")));
+        assert_eq!(body["result"]["analysis_result"]["summary"], "Orders doubled");
+        assert!(provider.prompts.lock().unwrap()[0].contains(&output_format_instruction(&OutputFormat::Table)));
+
+        let (status, body) = process(app.clone(), serde_json::json!({"file_path": file})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["output_format"], "narrative");
+        assert!(provider.prompts.lock().unwrap()[1].contains(&output_format_instruction(&OutputFormat::Narrative)));
+
+        let (status, _) = process(app, serde_json::json!({"file_path": dir.join("missing.json")})).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,311 @@
+//! Running byte totals of each integration's stored results, kept in step
+//! with the results so the byte quota never re-serializes all of them
+
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use super::integration_manager::{AnalysisStatus, IntegrationAnalysisResult, IntegrationManager};
+use super::store::PendingWrite;
+
+/// Serialized sizes of stored results and their totals per integration
+#[derive(Debug, Default)]
+pub struct StorageUsage {
+    /// Sizes are only measured when there is a byte quota to enforce
+    enabled: bool,
+    sizes: Mutex<Sizes>,
+}
+
+#[derive(Debug, Default)]
+struct Sizes {
+    by_result: HashMap<String, u64>,
+    by_integration: HashMap<String, u64>,
+}
+
+impl StorageUsage {
+    /// Track sizes when `enabled`, otherwise every total stays zero
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled, sizes: Mutex::default() }
+    }
+
+    /// Measure a result that was stored or changed
+    pub fn update(&self, result: &IntegrationAnalysisResult) {
+        if !self.enabled {
+            return;
+        }
+        let size = serde_json::to_vec(result).map(|b| b.len() as u64).unwrap_or(0);
+        let mut sizes = self.sizes.lock().unwrap();
+        let old = sizes.by_result.insert(result.id.clone(), size).unwrap_or(0);
+        let total = sizes.by_integration.entry(result.integration_id.clone()).or_insert(0);
+        *total = (*total + size).saturating_sub(old);
+    }
+
+    /// Forget a result that was evicted or deleted
+    pub fn remove(&self, result: &IntegrationAnalysisResult) {
+        let mut sizes = self.sizes.lock().unwrap();
+        let Some(size) = sizes.by_result.remove(&result.id) else {
+            return;
+        };
+        if let Some(total) = sizes.by_integration.get_mut(&result.integration_id) {
+            *total = total.saturating_sub(size);
+            if *total == 0 {
+                sizes.by_integration.remove(&result.integration_id);
+            }
+        }
+    }
+
+    /// Reset the totals to exactly `results`
+    pub fn rebuild<'a>(&self, results: impl IntoIterator<Item = &'a IntegrationAnalysisResult>) {
+        *self.sizes.lock().unwrap() = Sizes::default();
+        for result in results {
+            self.update(result);
+        }
+    }
+
+    /// Bytes stored for an integration
+    pub fn bytes(&self, integration_id: &str) -> u64 {
+        self.sizes.lock().unwrap().by_integration.get(integration_id).copied().unwrap_or(0)
+    }
+
+    /// Last measured size of a result
+    pub fn size(&self, result_id: &str) -> u64 {
+        self.sizes.lock().unwrap().by_result.get(result_id).copied().unwrap_or(0)
+    }
+}
+
+impl IntegrationManager {
+    /// Bring one integration's results within the configured count and byte
+    /// quotas by evicting its oldest finished results, never `keep`. Returns
+    /// how many were evicted, or a warning when the integration is still over
+    /// quota (always, when eviction is disabled). The ids of evicted results
+    /// are appended to `evicted_ids`, for the caller to delete from the store.
+    pub(super) fn apply_storage_quota(
+        &self,
+        integration_id: &str,
+        results: &mut Vec<IntegrationAnalysisResult>,
+        keep: &str,
+        evicted_ids: &mut Vec<String>,
+    ) -> Result<usize, String> {
+        let (max_results, max_bytes) = (self.config.max_results_per_integration, self.config.max_result_bytes_per_integration);
+        if max_results.is_none() && max_bytes.is_none() {
+            return Ok(0);
+        }
+
+        let over = |count: usize, bytes: u64| {
+            max_results.is_some_and(|max| count > max) || max_bytes.is_some_and(|max| bytes > max)
+        };
+        let mut count = results.len();
+        let mut bytes = self.storage_usage.bytes(integration_id);
+        if !over(count, bytes) {
+            return Ok(0);
+        }
+
+        let mut evicted = std::collections::HashSet::new();
+        if self.config.evict_over_quota {
+            let mut candidates: Vec<&IntegrationAnalysisResult> = results
+                .iter()
+                .filter(|r| r.id != keep && matches!(r.status, AnalysisStatus::Completed | AnalysisStatus::Failed))
+                .collect();
+            candidates.sort_by_key(|r| r.sequence);
+            for candidate in candidates {
+                if !over(count, bytes) {
+                    break;
+                }
+                count -= 1;
+                bytes = bytes.saturating_sub(self.storage_usage.size(&candidate.id));
+                evicted.insert(candidate.id.clone());
+            }
+            results.retain(|r| {
+                let keep = !evicted.contains(&r.id);
+                if !keep {
+                    self.counters.replace(Some(r), None);
+                    self.storage_usage.remove(r);
+                }
+                keep
+            });
+            evicted_ids.extend(evicted.iter().cloned());
+        }
+
+        if over(count, bytes) {
+            Err(format!(
+                "Storage quota exceeded: {} results and {} bytes stored (limits: {} results, {} bytes)",
+                count,
+                bytes,
+                max_results.map_or("none".to_string(), |m| m.to_string()),
+                max_bytes.map_or("none".to_string(), |m| m.to_string())
+            ))
+        } else {
+            Ok(evicted.len())
+        }
+    }
+
+    /// Apply the storage quotas to every integration, returning how many results were evicted
+    pub async fn enforce_storage_quotas(&self) -> usize {
+        let mut deletes = Vec::new();
+        let mut all_evicted = Vec::new();
+        {
+            let mut results = self.analysis_results.write().await;
+            for (integration_id, integration_results) in results.iter_mut() {
+                let mut evicted_ids = Vec::new();
+                if let Err(warning) = self.apply_storage_quota(integration_id, integration_results, "", &mut evicted_ids) {
+                    log::warn!("Integration {}: {}", integration_id, warning);
+                }
+                self.unindex_correlations(integration_id, &evicted_ids, integration_results);
+                all_evicted.extend(evicted_ids.iter().cloned());
+                deletes.extend(evicted_ids.into_iter().map(|result_id| PendingWrite::DeleteResult {
+                    integration_id: integration_id.clone(),
+                    result_id,
+                }));
+            }
+        }
+
+        self.forget_results(&all_evicted).await;
+
+        let evicted = deletes.len();
+        for delete in deletes {
+            self.persist(delete).await;
+        }
+        evicted
+    }
+
+    /// Enforce the storage quotas every `every`, catching integrations that
+    /// went over between writes (e.g. after the quotas were lowered), and
+    /// delete exports whose download URL has expired
+    pub fn spawn_storage_pruner(self: &Arc<Self>, every: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(every);
+            loop {
+                ticker.tick().await;
+                let evicted = manager.enforce_storage_quotas().await;
+                if evicted > 0 {
+                    log::info!("Storage pruning evicted {} results", evicted);
+                }
+                let exports = manager.prune_exports(Utc::now());
+                if exports > 0 {
+                    log::info!("Storage pruning deleted {} expired exports", exports);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama::Config;
+    use crate::test_support::*;
+
+    fn result(id: &str, integration_id: &str, summary: &str) -> IntegrationAnalysisResult {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "integration_id": integration_id,
+            "system_name": "test",
+            "data_source": "external_system",
+            "analysis_result": {"summary": summary},
+            "status": "Completed",
+            "created_at": chrono::Utc::now(),
+            "processing_time": 0.0,
+            "insights_count": 0,
+            "recommendations_count": 0
+        }))
+        .unwrap()
+    }
+
+    fn measured(result: &IntegrationAnalysisResult) -> u64 {
+        serde_json::to_vec(result).unwrap().len() as u64
+    }
+
+    #[test]
+    fn test_totals_follow_updates_and_removals() {
+        let usage = StorageUsage::new(true);
+        let first = result("r1", "int-1", "short");
+        let second = result("r2", "int-1", "another");
+        let other = result("r3", "int-2", "elsewhere");
+        usage.update(&first);
+        usage.update(&second);
+        usage.update(&other);
+        assert_eq!(usage.bytes("int-1"), measured(&first) + measured(&second));
+
+        let grown = result("r1", "int-1", &"much longer summary ".repeat(10));
+        usage.update(&grown);
+        assert_eq!(usage.size("r1"), measured(&grown));
+        assert_eq!(usage.bytes("int-1"), measured(&grown) + measured(&second));
+
+        usage.remove(&grown);
+        usage.remove(&second);
+        assert_eq!(usage.bytes("int-1"), 0);
+        assert_eq!(usage.bytes("int-2"), measured(&other));
+
+        usage.rebuild([&first]);
+        assert_eq!((usage.bytes("int-1"), usage.bytes("int-2")), (measured(&first), 0));
+    }
+
+    #[test]
+    fn test_disabled_usage_measures_nothing() {
+        let usage = StorageUsage::new(false);
+        usage.update(&result("r1", "int-1", "short"));
+        assert_eq!((usage.bytes("int-1"), usage.size("r1")), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_oldest_results_are_evicted_at_the_storage_cap() {
+        let store = Arc::new(MemoryStore::default());
+        let manager = IntegrationManager::with_config(Config {
+            max_results_per_integration: Some(3),
+            ..Config::default()
+        })
+        .with_store(store.clone());
+        let integration = manager.create_integration(sample_request("quota")).await.unwrap();
+
+        for _ in 0..5 {
+            manager.store_result(&mut sample_result(&integration.id, AnalysisStatus::Completed)).await;
+        }
+        let sequences: Vec<u64> = manager.get_analysis_results(&integration.id, None).await.iter().map(|r| r.sequence).collect();
+        assert_eq!(sequences, [5, 4, 3]);
+
+        // A result still being processed is never evicted
+        let mut processing = sample_result(&integration.id, AnalysisStatus::Processing);
+        manager.store_result(&mut processing).await;
+        let stored = manager.get_analysis_results(&integration.id, None).await;
+        assert_eq!(stored.len(), 3);
+        assert_eq!(stored[0].id, processing.id);
+
+        // Evicted results are deleted from the store too, so a restart does not bring them back
+        let restarted = IntegrationManager::new().with_store(store);
+        assert_eq!(restarted.load_from_store().await.unwrap(), 3);
+        let reloaded: Vec<String> = restarted.get_analysis_results(&integration.id, None).await.into_iter().map(|r| r.id).collect();
+        assert_eq!(reloaded, stored.into_iter().map(|r| r.id).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_byte_quota_warns_instead_of_evicting_when_configured() {
+        let one_result = serde_json::to_vec(&sample_result("x", AnalysisStatus::Completed)).unwrap().len() as u64;
+        let manager = IntegrationManager::with_config(Config {
+            max_result_bytes_per_integration: Some(one_result * 2),
+            evict_over_quota: false,
+            ..Config::default()
+        });
+        let integration = manager.create_integration(sample_request("bytes")).await.unwrap();
+
+        let mut first = sample_result(&integration.id, AnalysisStatus::Completed);
+        manager.store_result(&mut first).await;
+        assert!(first.storage_warning.is_none());
+        for _ in 0..3 {
+            manager.store_result(&mut sample_result(&integration.id, AnalysisStatus::Completed)).await;
+        }
+
+        let stored = manager.get_analysis_results(&integration.id, None).await;
+        assert_eq!(stored.len(), 4);
+        assert!(stored[0].storage_warning.as_deref().unwrap().starts_with("Storage quota exceeded"));
+
+        // With eviction back on, the pruner trims the integration to its byte cap
+        let pruning = IntegrationManager::with_config(Config {
+            max_result_bytes_per_integration: Some(one_result * 2),
+            ..Config::default()
+        });
+        seed_results(&pruning, &integration.id, stored).await;
+        assert!(pruning.enforce_storage_quotas().await >= 2);
+        assert!(pruning.get_analysis_results(&integration.id, None).await.len() <= 2);
+    }
+}
@@ -0,0 +1,815 @@
+//! Persistence backend for integrations and their analysis results
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use super::attachments::{Attachment, AttachmentInfo};
+use super::data_profiles::DataProfile;
+use super::integration_manager::{AnalysisSession, Integration, IntegrationAnalysisResult, IntegrationManager};
+use super::presets::AnalysisPreset;
+use super::prompt_templates::PromptTemplate;
+
+/// Longest a readiness check waits for the store's sentinel round-trip
+const STORE_CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Durable storage behind the `IntegrationManager`'s in-memory cache
+#[async_trait]
+pub trait IntegrationStore: Send + Sync + std::fmt::Debug {
+    /// Insert or replace an integration
+    async fn save_integration(&self, integration: &Integration) -> Result<(), String>;
+
+    /// Every stored integration
+    async fn load_all(&self) -> Result<Vec<Integration>, String>;
+
+    /// Remove an integration with its results, their attachments and its
+    /// sessions; removing one that is not stored succeeds
+    async fn delete_integration(&self, integration_id: &str) -> Result<(), String>;
+
+    /// Insert or replace a result (matched by id)
+    async fn save_result(&self, result: &IntegrationAnalysisResult) -> Result<(), String>;
+
+    /// Stored results for one integration
+    async fn load_results(&self, integration_id: &str) -> Result<Vec<IntegrationAnalysisResult>, String>;
+
+    /// Remove a result and its attachments; removing one that is not stored succeeds
+    async fn delete_result(&self, integration_id: &str, result_id: &str) -> Result<(), String>;
+
+    /// Insert or replace a result's attachment (matched by name)
+    async fn save_attachment(&self, result_id: &str, attachment: &Attachment) -> Result<(), String>;
+
+    /// A result's attachment, including its content
+    async fn load_attachment(&self, result_id: &str, name: &str) -> Result<Option<Attachment>, String>;
+
+    /// Insert or replace a domain's prompt template (matched by domain)
+    async fn save_prompt_template(&self, template: &PromptTemplate) -> Result<(), String>;
+
+    /// Every stored prompt template
+    async fn load_prompt_templates(&self) -> Result<Vec<PromptTemplate>, String>;
+
+    /// Insert or replace a data profile (matched by name)
+    async fn save_data_profile(&self, profile: &DataProfile) -> Result<(), String>;
+
+    /// Every stored data profile
+    async fn load_data_profiles(&self) -> Result<Vec<DataProfile>, String>;
+
+    /// Remove a data profile; removing one that is not stored succeeds
+    async fn delete_data_profile(&self, name: &str) -> Result<(), String>;
+
+    /// Insert or replace an analysis preset (matched by name)
+    async fn save_preset(&self, preset: &AnalysisPreset) -> Result<(), String>;
+
+    /// Every stored analysis preset
+    async fn load_presets(&self) -> Result<Vec<AnalysisPreset>, String>;
+
+    /// Remove an analysis preset; removing one that is not stored succeeds
+    async fn delete_preset(&self, name: &str) -> Result<(), String>;
+
+    /// Insert or replace an analysis session (matched by integration and id)
+    async fn save_session(&self, session: &AnalysisSession) -> Result<(), String>;
+
+    /// Every stored analysis session
+    async fn load_sessions(&self) -> Result<Vec<AnalysisSession>, String>;
+
+    /// Remove an analysis session; removing one that is not stored succeeds
+    async fn delete_session(&self, integration_id: &str, session_id: &str) -> Result<(), String>;
+
+    /// Write a health-check sentinel under `key`, which each probe picks
+    /// afresh so concurrent probes never see each other's value
+    async fn write_sentinel(&self, key: &str, value: &str) -> Result<(), String>;
+
+    /// The sentinel written under `key`, if any
+    async fn read_sentinel(&self, key: &str) -> Result<Option<String>, String>;
+
+    /// Remove the sentinel under `key`; removing one that is not there succeeds
+    async fn remove_sentinel(&self, key: &str) -> Result<(), String>;
+}
+
+/// A write that has not reached the store yet
+#[derive(Debug, Clone)]
+pub enum PendingWrite {
+    Integration(Box<Integration>),
+    Result(Box<IntegrationAnalysisResult>),
+    Attachment { result_id: String, attachment: Box<Attachment> },
+    DeleteIntegration(String),
+    DeleteResult { integration_id: String, result_id: String },
+    PromptTemplate(Box<PromptTemplate>),
+    DataProfile(Box<DataProfile>),
+    DeleteDataProfile(String),
+    Preset(Box<AnalysisPreset>),
+    DeletePreset(String),
+    Session(Box<AnalysisSession>),
+    DeleteSession { integration_id: String, session_id: String },
+}
+
+impl PendingWrite {
+    pub async fn apply(&self, store: &dyn IntegrationStore) -> Result<(), String> {
+        match self {
+            PendingWrite::Integration(integration) => store.save_integration(integration).await,
+            PendingWrite::Result(result) => store.save_result(result).await,
+            PendingWrite::Attachment { result_id, attachment } => store.save_attachment(result_id, attachment).await,
+            PendingWrite::DeleteIntegration(integration_id) => store.delete_integration(integration_id).await,
+            PendingWrite::DeleteResult { integration_id, result_id } => store.delete_result(integration_id, result_id).await,
+            PendingWrite::PromptTemplate(template) => store.save_prompt_template(template).await,
+            PendingWrite::DataProfile(profile) => store.save_data_profile(profile).await,
+            PendingWrite::DeleteDataProfile(name) => store.delete_data_profile(name).await,
+            PendingWrite::Preset(preset) => store.save_preset(preset).await,
+            PendingWrite::DeletePreset(name) => store.delete_preset(name).await,
+            PendingWrite::Session(session) => store.save_session(session).await,
+            PendingWrite::DeleteSession { integration_id, session_id } => store.delete_session(integration_id, session_id).await,
+        }
+    }
+
+    /// The record this write replaces; a later write with the same key,
+    /// including a delete, supersedes an earlier one
+    pub fn key(&self) -> String {
+        match self {
+            PendingWrite::Integration(integration) => format!("integration {}", integration.id),
+            PendingWrite::DeleteIntegration(integration_id) => format!("integration {}", integration_id),
+            PendingWrite::Result(result) => format!("result {}", result.id),
+            PendingWrite::DeleteResult { result_id, .. } => format!("result {}", result_id),
+            PendingWrite::Attachment { result_id, attachment } => {
+                format!("attachment {}/{}", result_id, attachment.info.name)
+            }
+            PendingWrite::PromptTemplate(template) => format!("prompt template {}", template.domain),
+            PendingWrite::DataProfile(profile) => format!("data profile {}", profile.name),
+            PendingWrite::DeleteDataProfile(name) => format!("data profile {}", name),
+            PendingWrite::Preset(preset) => format!("preset {}", preset.name),
+            PendingWrite::DeletePreset(name) => format!("preset {}", name),
+            PendingWrite::Session(session) => format!("session {}/{}", session.integration_id, session.id),
+            PendingWrite::DeleteSession { integration_id, session_id } => format!("session {}/{}", integration_id, session_id),
+        }
+    }
+}
+
+/// Most writes buffered while the store is down; past this the oldest is dropped
+pub const MAX_PENDING_WRITES: usize = 10_000;
+
+/// Writes waiting for the store, oldest first. A newer write to a record
+/// replaces the buffered one, so the buffer holds at most one write per
+/// record, and past its capacity the oldest write is dropped.
+#[derive(Debug)]
+pub struct PendingWrites {
+    writes: VecDeque<(u64, PendingWrite)>,
+    next_ticket: u64,
+    capacity: usize,
+}
+
+impl Default for PendingWrites {
+    fn default() -> Self {
+        Self::new(MAX_PENDING_WRITES)
+    }
+}
+
+impl PendingWrites {
+    pub fn new(capacity: usize) -> Self {
+        Self { writes: VecDeque::new(), next_ticket: 0, capacity: capacity.max(1) }
+    }
+
+    /// Queue a write, returning the one dropped to stay within capacity
+    pub fn push(&mut self, write: PendingWrite) -> Option<PendingWrite> {
+        let key = write.key();
+        self.writes.retain(|(_, queued)| queued.key() != key);
+        let dropped = if self.writes.len() >= self.capacity {
+            self.writes.pop_front().map(|(_, write)| write)
+        } else {
+            None
+        };
+        self.next_ticket += 1;
+        self.writes.push_back((self.next_ticket, write));
+        dropped
+    }
+
+    /// The oldest write, with the ticket to `complete` it by
+    pub fn front(&self) -> Option<(u64, PendingWrite)> {
+        self.writes.front().cloned()
+    }
+
+    /// Remove a write once the store has it, unless a newer write to the
+    /// same record replaced it in the meantime
+    pub fn complete(&mut self, ticket: u64) {
+        self.writes.retain(|(queued, _)| *queued != ticket);
+    }
+
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+}
+
+/// Store keeping each record in its own JSON file under one directory:
+///
+/// ```text
+/// integrations/<integration_id>.json
+/// results/<integration_id>/<result_id>.json
+/// attachments/<result_id>/data/<name>         (content)
+/// attachments/<result_id>/info/<name>.json    (AttachmentInfo)
+/// prompt_templates/<domain>.json
+/// data_profiles/<name>.json
+/// presets/<name>.json
+/// sessions/<integration_id>/<session_id>.json
+/// sentinels/<key>
+/// ```
+///
+/// Files are written to a temporary name and renamed into place, so a crash
+/// mid-write leaves the previous version intact.
+#[derive(Debug, Clone)]
+pub struct JsonFileStore {
+    root: PathBuf,
+}
+
+impl JsonFileStore {
+    /// A store rooted at `root`; directories are created on first write
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn integration_path(&self, integration_id: &str) -> Result<PathBuf, String> {
+        Ok(self.root.join("integrations").join(format!("{}.json", path_component(integration_id)?)))
+    }
+
+    fn results_dir(&self, integration_id: &str) -> Result<PathBuf, String> {
+        Ok(self.root.join("results").join(path_component(integration_id)?))
+    }
+
+    fn sessions_dir(&self, integration_id: &str) -> Result<PathBuf, String> {
+        Ok(self.root.join("sessions").join(path_component(integration_id)?))
+    }
+
+    fn data_profile_path(&self, name: &str) -> Result<PathBuf, String> {
+        Ok(self.root.join("data_profiles").join(format!("{}.json", path_component(name)?)))
+    }
+
+    fn preset_path(&self, name: &str) -> Result<PathBuf, String> {
+        Ok(self.root.join("presets").join(format!("{}.json", path_component(name)?)))
+    }
+
+    fn attachment_paths(&self, result_id: &str, name: &str) -> Result<(PathBuf, PathBuf), String> {
+        let dir = self.root.join("attachments").join(path_component(result_id)?);
+        let name = path_component(name)?;
+        Ok((dir.join("data").join(name), dir.join("info").join(format!("{}.json", name))))
+    }
+}
+
+/// `id` as a single file name, refusing anything that could leave its directory
+fn path_component(id: &str) -> Result<&str, String> {
+    if id.is_empty() || id.starts_with('.') || id.contains(['/', '\\', '\0']) {
+        return Err(format!("Invalid store key '{}'", id));
+    }
+    Ok(id)
+}
+
+/// Write `data` to `path` through a temporary file in the same directory
+async fn write_atomic(path: &Path, data: &[u8]) -> Result<(), String> {
+    let dir = path.parent().ok_or_else(|| format!("Invalid store path {}", path.display()))?;
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let temp = dir.join(format!(".{}.tmp", uuid::Uuid::new_v4()));
+    tokio::fs::write(&temp, data)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", temp.display(), e))?;
+    tokio::fs::rename(&temp, path)
+        .await
+        .map_err(|e| format!("Failed to replace {}: {}", path.display(), e))
+}
+
+/// A result as stored: its API form plus the embedding, which API
+/// responses leave out
+#[derive(serde::Serialize)]
+struct StoredResult<'a> {
+    #[serde(flatten)]
+    result: &'a IntegrationAnalysisResult,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embedding: &'a Option<Vec<f32>>,
+}
+
+/// An integration as stored: its API form plus the API key and webhook
+/// secret, which API responses leave out
+#[derive(serde::Serialize)]
+struct StoredIntegration<'a> {
+    #[serde(flatten)]
+    integration: &'a Integration,
+    api_key: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    webhook_secret: &'a Option<String>,
+}
+
+async fn write_json<T: serde::Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let data = serde_json::to_vec_pretty(value).map_err(|e| e.to_string())?;
+    write_atomic(path, &data).await
+}
+
+/// The file's content, or `None` when it does not exist
+async fn read_optional(path: &Path) -> Result<Option<Vec<u8>>, String> {
+    match tokio::fs::read(path).await {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+/// Remove `path`, a file or a whole directory; a missing path is already removed
+async fn remove_path(path: &Path) -> Result<(), String> {
+    let removed = match tokio::fs::metadata(path).await {
+        Ok(metadata) if metadata.is_dir() => tokio::fs::remove_dir_all(path).await,
+        Ok(_) => tokio::fs::remove_file(path).await,
+        Err(e) => Err(e),
+    };
+    match removed {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!("Failed to remove {}: {}", path.display(), e)),
+        _ => Ok(()),
+    }
+}
+
+/// Every `.json` file in `dir`, parsed; a missing directory holds nothing.
+/// A file that does not parse is logged and skipped, so one corrupt record
+/// cannot keep the rest from loading.
+async fn read_json_dir<T: DeserializeOwned>(dir: &Path) -> Result<Vec<T>, String> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to list {}: {}", dir.display(), e)),
+    };
+    let mut records = Vec::new();
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|e| format!("Failed to list {}: {}", dir.display(), e))?
+    {
+        let path = entry.path();
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        let data = tokio::fs::read(&path)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        match serde_json::from_slice(&data) {
+            Ok(record) => records.push(record),
+            Err(e) => log::error!("Skipping corrupt store file {}: {}", path.display(), e),
+        }
+    }
+    Ok(records)
+}
+
+#[async_trait]
+impl IntegrationStore for JsonFileStore {
+    async fn save_integration(&self, integration: &Integration) -> Result<(), String> {
+        let stored = StoredIntegration { integration, api_key: &integration.api_key, webhook_secret: &integration.webhook_secret };
+        write_json(&self.integration_path(&integration.id)?, &stored).await
+    }
+
+    async fn load_all(&self) -> Result<Vec<Integration>, String> {
+        read_json_dir(&self.root.join("integrations")).await
+    }
+
+    async fn delete_integration(&self, integration_id: &str) -> Result<(), String> {
+        // The integration file goes first, so a delete cut short never leaves
+        // an integration whose results are missing
+        remove_path(&self.integration_path(integration_id)?).await?;
+        let results_dir = self.results_dir(integration_id)?;
+        let mut entries = match tokio::fs::read_dir(&results_dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(format!("Failed to list {}: {}", results_dir.display(), e)),
+        };
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| format!("Failed to list {}: {}", results_dir.display(), e))?
+        {
+            let path = entry.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            // Result files are named by result id, which also names its attachments
+            if let Some(result_id) = path.file_stem().and_then(|stem| stem.to_str()).filter(|id| path_component(id).is_ok()) {
+                remove_path(&self.root.join("attachments").join(result_id)).await?;
+            }
+        }
+        remove_path(&results_dir).await?;
+        remove_path(&self.sessions_dir(integration_id)?).await
+    }
+
+    async fn save_result(&self, result: &IntegrationAnalysisResult) -> Result<(), String> {
+        let path = self.results_dir(&result.integration_id)?.join(format!("{}.json", path_component(&result.id)?));
+        write_json(&path, &StoredResult { result, embedding: &result.embedding }).await
+    }
+
+    async fn load_results(&self, integration_id: &str) -> Result<Vec<IntegrationAnalysisResult>, String> {
+        read_json_dir(&self.results_dir(integration_id)?).await
+    }
+
+    async fn delete_result(&self, integration_id: &str, result_id: &str) -> Result<(), String> {
+        let result_id = path_component(result_id)?;
+        remove_path(&self.results_dir(integration_id)?.join(format!("{}.json", result_id))).await?;
+        remove_path(&self.root.join("attachments").join(result_id)).await
+    }
+
+    async fn save_attachment(&self, result_id: &str, attachment: &Attachment) -> Result<(), String> {
+        let (data_path, info_path) = self.attachment_paths(result_id, &attachment.info.name)?;
+        // Content first, so a listed attachment always has its content
+        write_atomic(&data_path, &attachment.data).await?;
+        write_json(&info_path, &attachment.info).await
+    }
+
+    async fn load_attachment(&self, result_id: &str, name: &str) -> Result<Option<Attachment>, String> {
+        let (data_path, info_path) = self.attachment_paths(result_id, name)?;
+        let (Some(info), Some(data)) = (read_optional(&info_path).await?, read_optional(&data_path).await?) else {
+            return Ok(None);
+        };
+        let info: AttachmentInfo =
+            serde_json::from_slice(&info).map_err(|e| format!("Corrupt store file {}: {}", info_path.display(), e))?;
+        Ok(Some(Attachment { info, data }))
+    }
+
+    async fn save_prompt_template(&self, template: &PromptTemplate) -> Result<(), String> {
+        let path = self.root.join("prompt_templates").join(format!("{}.json", path_component(&template.domain)?));
+        write_json(&path, template).await
+    }
+
+    async fn load_prompt_templates(&self) -> Result<Vec<PromptTemplate>, String> {
+        read_json_dir(&self.root.join("prompt_templates")).await
+    }
+
+    async fn save_data_profile(&self, profile: &DataProfile) -> Result<(), String> {
+        write_json(&self.data_profile_path(&profile.name)?, profile).await
+    }
+
+    async fn load_data_profiles(&self) -> Result<Vec<DataProfile>, String> {
+        read_json_dir(&self.root.join("data_profiles")).await
+    }
+
+    async fn delete_data_profile(&self, name: &str) -> Result<(), String> {
+        remove_path(&self.data_profile_path(name)?).await
+    }
+
+    async fn save_preset(&self, preset: &AnalysisPreset) -> Result<(), String> {
+        write_json(&self.preset_path(&preset.name)?, preset).await
+    }
+
+    async fn load_presets(&self) -> Result<Vec<AnalysisPreset>, String> {
+        read_json_dir(&self.root.join("presets")).await
+    }
+
+    async fn delete_preset(&self, name: &str) -> Result<(), String> {
+        remove_path(&self.preset_path(name)?).await
+    }
+
+    async fn save_session(&self, session: &AnalysisSession) -> Result<(), String> {
+        let path = self.sessions_dir(&session.integration_id)?.join(format!("{}.json", path_component(&session.id)?));
+        write_json(&path, session).await
+    }
+
+    async fn load_sessions(&self) -> Result<Vec<AnalysisSession>, String> {
+        let root = self.root.join("sessions");
+        let mut entries = match tokio::fs::read_dir(&root).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("Failed to list {}: {}", root.display(), e)),
+        };
+        let mut sessions = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| format!("Failed to list {}: {}", root.display(), e))?
+        {
+            sessions.extend(read_json_dir::<AnalysisSession>(&entry.path()).await?);
+        }
+        Ok(sessions)
+    }
+
+    async fn delete_session(&self, integration_id: &str, session_id: &str) -> Result<(), String> {
+        remove_path(&self.sessions_dir(integration_id)?.join(format!("{}.json", path_component(session_id)?))).await
+    }
+
+    async fn write_sentinel(&self, key: &str, value: &str) -> Result<(), String> {
+        write_atomic(&self.root.join("sentinels").join(path_component(key)?), value.as_bytes()).await
+    }
+
+    async fn read_sentinel(&self, key: &str) -> Result<Option<String>, String> {
+        Ok(read_optional(&self.root.join("sentinels").join(path_component(key)?))
+            .await?
+            .map(|data| String::from_utf8_lossy(&data).into_owned()))
+    }
+
+    async fn remove_sentinel(&self, key: &str) -> Result<(), String> {
+        remove_path(&self.root.join("sentinels").join(path_component(key)?)).await
+    }
+}
+
+impl IntegrationManager {
+    /// Fill the in-memory cache with every integration, result, prompt
+    /// template, data profile, preset and session in the store and rebuild
+    /// the dashboard counters from them; run at startup.
+    /// Without a store only the counters are rebuilt. Returns how many
+    /// results were loaded.
+    pub async fn load_from_store(&self) -> Result<usize, String> {
+        if let Some(store) = &self.store {
+            let loaded = store.load_all().await?;
+            let mut integrations = self.integrations.write().await;
+            let mut results = self.analysis_results.write().await;
+            for integration in loaded {
+                let mut integration_results = store.load_results(&integration.id).await?;
+                integration_results.sort_by_key(|r| r.sequence);
+                // Let the next result be numbered after the loaded ones
+                self.sequences.lock().unwrap().remove(&integration.id);
+                results.insert(integration.id.clone(), integration_results);
+                integrations.insert(integration.id.clone(), integration);
+            }
+
+            // Versions continue from the stored ones, so they never repeat one stamped on a result
+            let mut templates = self.prompt_templates.write().await;
+            for template in store.load_prompt_templates().await? {
+                templates.insert(template.domain.clone(), template);
+            }
+            let mut profiles = self.data_profiles.write().await;
+            for profile in store.load_data_profiles().await? {
+                profiles.insert(profile.name.clone(), profile);
+            }
+            let mut presets = self.presets.write().await;
+            for preset in store.load_presets().await? {
+                presets.insert(preset.name.clone(), preset);
+            }
+            let mut sessions = self.sessions.write().await;
+            for session in store.load_sessions().await? {
+                if integrations.contains_key(&session.integration_id) {
+                    sessions.insert((session.integration_id.clone(), session.id.clone()), session);
+                }
+            }
+        }
+
+        let results = self.analysis_results.read().await;
+        self.counters.rebuild(results.values().flatten());
+        self.storage_usage.rebuild(results.values().flatten());
+        for result in results.values().flatten() {
+            self.index_correlation(result);
+        }
+        Ok(results.values().map(Vec::len).sum())
+    }
+
+    /// Whether the store is failing and writes are being buffered in memory
+    pub fn store_degraded(&self) -> bool {
+        self.store_degraded.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// Write a fresh sentinel to the store and read it back, failing if the
+    /// round-trip errors, returns something else, or takes too long. Passes
+    /// trivially when there is no store.
+    pub async fn check_store(&self) -> Result<(), String> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+
+        // Each probe has its own key, so concurrent probes (or instances
+        // sharing the store) never read one another's sentinel
+        let key = Uuid::new_v4().to_string();
+        let sentinel = Uuid::new_v4().to_string();
+        let round_trip = async {
+            store.write_sentinel(&key, &sentinel).await?;
+            let read = store.read_sentinel(&key).await;
+            store.remove_sentinel(&key).await?;
+            read
+        };
+        match tokio::time::timeout(STORE_CHECK_TIMEOUT, round_trip).await {
+            Err(_) => Err(format!("Store did not answer within {}s", STORE_CHECK_TIMEOUT.as_secs())),
+            Ok(Err(e)) => Err(e),
+            Ok(Ok(Some(read))) if read == sentinel => Ok(()),
+            Ok(Ok(_)) => Err("Store returned a different sentinel than was written".to_string()),
+        }
+    }
+
+    /// Number of writes waiting for the store to recover
+    pub async fn pending_store_writes(&self) -> usize {
+        self.pending_writes.lock().unwrap().len()
+    }
+
+    /// Send a write to the store. If the store errors, keep operating from
+    /// memory: mark the store degraded and buffer the write, replaying the
+    /// buffer in order on the next write once the store accepts it again.
+    pub(super) async fn persist(&self, write: PendingWrite) {
+        let Some(store) = &self.store else {
+            return;
+        };
+
+        if let Some(dropped) = self.pending_writes.lock().unwrap().push(write) {
+            log::error!("Store write buffer is full, dropped the buffered write for {}", dropped.key());
+        }
+
+        // One task replays the buffer at a time; a write queued mid-flush is
+        // picked up by that task, which checks for new writes before it stops
+        loop {
+            let Ok(flushing) = self.flushing.try_lock() else {
+                return;
+            };
+            if !self.flush_pending_writes(store.as_ref()).await {
+                return;
+            }
+            drop(flushing);
+            if self.pending_writes.lock().unwrap().is_empty() {
+                return;
+            }
+        }
+    }
+
+    /// Replay buffered writes oldest first, without holding the buffer's
+    /// lock across store I/O. False if the store failed and writes remain.
+    pub(super) async fn flush_pending_writes(&self, store: &dyn IntegrationStore) -> bool {
+        loop {
+            let next = self.pending_writes.lock().unwrap().front();
+            let Some((ticket, write)) = next else {
+                break;
+            };
+            if let Err(e) = write.apply(store).await {
+                if !self.store_degraded.swap(true, std::sync::atomic::Ordering::SeqCst) {
+                    log::warn!("Integration store unavailable, continuing in memory: {}", e);
+                }
+                return false;
+            }
+            self.pending_writes.lock().unwrap().complete(ticket);
+        }
+
+        if self.store_degraded.swap(false, std::sync::atomic::Ordering::SeqCst) {
+            log::info!("Integration store recovered, buffered writes flushed");
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::attachments::AttachmentGenerator;
+    use crate::api::integration_manager::AnalysisStatus;
+    use crate::test_support::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_json_file_store_refuses_keys_outside_its_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JsonFileStore::new(dir.path().join("store"));
+        assert!(store.load_all().await.unwrap().is_empty());
+        assert_eq!(store.read_sentinel("probe-1").await.unwrap(), None);
+
+        store.write_sentinel("probe-1", "ping").await.unwrap();
+        assert_eq!(store.read_sentinel("probe-1").await.unwrap().as_deref(), Some("ping"));
+        store.remove_sentinel("probe-1").await.unwrap();
+        assert_eq!(store.read_sentinel("probe-1").await.unwrap(), None);
+        assert!(store.write_sentinel("../sentinel", "ping").await.is_err());
+
+        assert!(store.load_results("../../etc").await.is_err());
+        assert!(store.load_attachment("result-1", "..").await.is_err());
+        assert_eq!(store.load_attachment("result-1", "chart.json").await.unwrap().map(|a| a.data), None);
+    }
+
+    #[tokio::test]
+    async fn test_json_file_store_deletes_records_and_skips_corrupt_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JsonFileStore::new(dir.path());
+        let results_dir = dir.path().join("results").join("shop");
+        tokio::fs::create_dir_all(&results_dir).await.unwrap();
+        tokio::fs::write(results_dir.join("broken.json"), b"{\"id\": ").await.unwrap();
+        assert!(store.load_results("shop").await.unwrap().is_empty());
+
+        store.save_attachment("broken", &Attachment::new("notes.txt", "text/plain", b"hi".to_vec())).await.unwrap();
+        store.delete_result("shop", "broken").await.unwrap();
+        assert!(!results_dir.join("broken.json").exists());
+        assert!(store.load_attachment("broken", "notes.txt").await.unwrap().is_none());
+
+        // Deleting what is already gone succeeds
+        store.delete_result("shop", "broken").await.unwrap();
+        store.delete_integration("shop").await.unwrap();
+        assert!(!results_dir.exists());
+        assert!(store.delete_result("shop", "../shop").await.is_err());
+    }
+
+    #[test]
+    fn test_pending_writes_keep_the_latest_write_per_record_within_capacity() {
+        let write = |name: &str, data: &[u8]| PendingWrite::Attachment {
+            result_id: "result-1".to_string(),
+            attachment: Box::new(Attachment::new(name, "text/plain", data.to_vec())),
+        };
+        let mut pending = PendingWrites::new(2);
+        assert!(pending.push(write("a.txt", b"1")).is_none());
+        assert!(pending.push(write("b.txt", b"1")).is_none());
+
+        // A newer write to a buffered record replaces it rather than growing the buffer
+        let (stale, _) = pending.front().unwrap();
+        assert!(pending.push(write("a.txt", b"2")).is_none());
+        assert_eq!(pending.len(), 2);
+        pending.complete(stale);
+        assert_eq!(pending.len(), 2);
+
+        // Past capacity the oldest write is dropped
+        let dropped = pending.push(write("c.txt", b"1")).unwrap();
+        assert_eq!(dropped.key(), "attachment result-1/b.txt");
+        let (ticket, next) = pending.front().unwrap();
+        assert!(matches!(next, PendingWrite::Attachment { attachment, .. } if attachment.data == b"2"));
+        pending.complete(ticket);
+        assert_eq!(pending.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_store_check_round_trips_a_sentinel() {
+        assert!(IntegrationManager::new().check_store().await.is_ok());
+
+        let store = Arc::new(FlakyStore::default());
+        let manager = IntegrationManager::new().with_store(store.clone());
+        assert!(manager.check_store().await.is_ok());
+        store.failing.store(true, std::sync::atomic::Ordering::SeqCst);
+        assert_eq!(manager.check_store().await.unwrap_err(), "disk unavailable");
+
+        // Concurrent probes of one store directory do not disturb each other
+        let dir = tempfile::tempdir().unwrap();
+        let manager = IntegrationManager::new().with_store(Arc::new(JsonFileStore::new(dir.path())));
+        let checks = futures_util::future::join_all((0..8).map(|_| manager.check_store())).await;
+        assert!(checks.iter().all(Result::is_ok));
+        assert!(std::fs::read_dir(dir.path().join("sentinels")).unwrap().next().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_analysis_succeeds_while_store_is_down() {
+        let (providers, _) = mock_ollama("Stable load").await;
+        let store = Arc::new(FlakyStore::default());
+        let manager = Arc::new(IntegrationManager::new().with_store(store.clone()));
+        let integration = manager.create_integration(sample_request("degraded")).await.unwrap();
+
+        store.failing.store(true, std::sync::atomic::Ordering::SeqCst);
+        let mut result = manager
+            .process_analysis_request(analysis_request(&integration, serde_json::json!({"cpu": 40})), &providers)
+            .await
+            .unwrap();
+
+        assert!(matches!(result.status, AnalysisStatus::Completed));
+        assert!(manager.store_degraded());
+        // The processing and completed saves of the result collapse into one write
+        assert_eq!(manager.pending_store_writes().await, 1);
+        assert_eq!(manager.get_analysis_results(&integration.id, None).await.len(), 1);
+
+        // Once the store is back the buffered writes are flushed on the next write
+        store.failing.store(false, std::sync::atomic::Ordering::SeqCst);
+        manager.store_result(&mut result).await;
+        assert!(!manager.store_degraded());
+        assert_eq!(manager.pending_store_writes().await, 0);
+        assert_eq!(*store.saved_results.lock().unwrap(), vec![result.id.clone()]);
+    }
+
+    #[tokio::test]
+    async fn test_deletes_reach_the_store_and_stay_deleted_after_restart() {
+        let (providers, _) = mock_ollama("Steady growth").await;
+        let store = Arc::new(MemoryStore::default());
+        let manager = Arc::new(IntegrationManager::new().with_store(store.clone()));
+        let shop = manager.create_integration(sample_request("shop")).await.unwrap();
+        let clinic = manager.create_integration(sample_request("clinic")).await.unwrap();
+        for integration in [&shop, &shop, &clinic] {
+            let request = analysis_request(integration, serde_json::json!({"orders": [1, 2]}));
+            manager.process_analysis_request(request, &providers).await.unwrap();
+        }
+
+        assert_eq!(manager.delete_analysis_results(&shop.id, None, None).await, 2);
+        assert!(manager.delete_integration(&clinic.id).await);
+
+        let restarted = IntegrationManager::new().with_store(store.clone());
+        assert_eq!(restarted.load_from_store().await.unwrap(), 0);
+        assert!(restarted.get_integration(&clinic.id).await.is_none());
+        assert!(restarted.get_integration(&shop.id).await.is_some());
+        assert!(store.results.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_json_file_store_keeps_integrations_and_results_across_restarts() {
+        let (providers, _) = mock_ollama(r#"{"summary": "Sales grew", "metrics": {"orders": 42}}"#).await;
+        let dir = tempfile::tempdir().unwrap();
+        let manager = Arc::new(IntegrationManager::new().with_store(Arc::new(JsonFileStore::new(dir.path()))));
+        let mut request = sample_request("durable");
+        request.configuration.attachments = vec![AttachmentGenerator::MetricsCsv];
+        let integration = manager.create_integration(request).await.unwrap();
+        let result = manager
+            .process_analysis_request(analysis_request(&integration, serde_json::json!({"orders": [40, 2]})), &providers)
+            .await
+            .unwrap();
+
+        let restarted = IntegrationManager::new().with_store(Arc::new(JsonFileStore::new(dir.path())));
+        assert_eq!(restarted.load_from_store().await.unwrap(), 1);
+        let loaded = restarted.get_integration(&integration.id).await.unwrap();
+        assert_eq!((loaded.name.as_str(), loaded.api_key.as_str()), ("durable", integration.api_key.as_str()));
+        assert!(restarted.authenticate(&integration.api_key).await.is_ok());
+
+        let results = restarted.get_analysis_results(&integration.id, None).await;
+        assert_eq!(results.len(), 1);
+        assert_eq!((results[0].id.as_str(), &results[0].status), (result.id.as_str(), &AnalysisStatus::Completed));
+        assert_eq!(results[0].analysis_result["summary"], "Sales grew");
+
+        let csv = restarted.get_attachment(&integration.id, &result.id, "metrics.csv").await.unwrap().unwrap();
+        assert_eq!(csv.data, b"metric,value\norders,42\n");
+    }
+}
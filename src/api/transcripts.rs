@@ -0,0 +1,145 @@
+//! Prompt/response transcripts kept behind each result when enabled, for
+//! checking exactly what the model was sent and what it answered
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::integration_manager::{authorize_integration, IntegrationManager};
+
+/// One prompt sent to the model and the raw text it returned
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptExchange {
+    pub prompt: String,
+    pub raw_response: String,
+}
+
+/// Exact model input and output behind a result, kept when transcripts are enabled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transcript {
+    pub prompt: String,
+    pub raw_response: String,
+    pub model: String,
+    pub options: serde_json::Value,
+    /// Earlier exchanges for multi-step analyses (e.g. one per window), oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub earlier_exchanges: Vec<TranscriptExchange>,
+}
+
+impl IntegrationManager {
+    /// Transcript for a result, if transcripts were enabled when it ran
+    pub async fn get_transcript(&self, result_id: &str) -> Option<Transcript> {
+        self.transcripts.read().await.get(result_id).cloned()
+    }
+}
+
+/// The exact prompt and reply behind a result; needs the integration's API
+/// key or the admin token
+pub(super) async fn get_result_transcript(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path((integration_id, result_id)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Json<Transcript>, StatusCode> {
+    authorize_integration(&manager, &headers, &integration_id).await?;
+    let belongs_to_integration = manager
+        .get_analysis_results(&integration_id, None)
+        .await
+        .iter()
+        .any(|r| r.id == result_id);
+    if !belongs_to_integration {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    manager.get_transcript(&result_id).await.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::integration_manager::{create_integration_routes, REDACTED};
+    use crate::ollama::Config;
+    use crate::test_support::*;
+    use axum::{body::Body, http::header};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_transcript_matches_what_was_sent() {
+        let (providers, calls) = mock_ollama("Throughput is steady").await;
+        let config = Config {
+            store_transcripts: true,
+            ..Config::default()
+        };
+        let manager = Arc::new(IntegrationManager::with_config(config));
+        let integration = manager.create_integration(sample_request("audit")).await.unwrap();
+
+        let result = manager
+            .process_analysis_request(analysis_request(&integration, serde_json::json!({"rps": 120})), &providers)
+            .await
+            .unwrap();
+
+        let app = create_integration_routes(offline_providers()).with_state(manager.clone());
+        let path = format!("/integrations/{}/results/{}/transcript", integration.id, result.id);
+        let response = app
+            .clone()
+            .oneshot(axum::http::Request::get(&path).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = app
+            .oneshot(
+                axum::http::Request::get(&path)
+                    .header(header::AUTHORIZATION, format!("Bearer {}", integration.api_key))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let transcript: serde_json::Value = serde_json::from_str(&body_string(response).await).unwrap();
+        let sent = calls.lock().unwrap()[0].clone();
+        assert_eq!(transcript["prompt"], sent["prompt"]);
+        assert_eq!(transcript["model"], sent["model"]);
+        assert_eq!(transcript["raw_response"], "Throughput is steady");
+        assert!(transcript["options"].is_object());
+
+        // Deleting the result deletes its transcript
+        assert_eq!(manager.delete_analysis_results(&integration.id, None, None).await, 1);
+        assert!(manager.get_transcript(&result.id).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_transcript_holds_only_redacted_input() {
+        let (providers, calls) = mock_ollama("Claims look normal").await;
+        let manager = Arc::new(IntegrationManager::with_config(Config { store_transcripts: true, ..Config::default() }));
+        let mut create = sample_request("claims");
+        create.configuration.redact_fields = vec!["ssn".to_string()];
+        let integration = manager.create_integration(create).await.unwrap();
+
+        let data = serde_json::json!({"claims": [{"ssn": "123-45-6789", "amount": 120}]});
+        let result = manager.process_analysis_request(analysis_request(&integration, data), &providers).await.unwrap();
+
+        let transcript = manager.get_transcript(&result.id).await.unwrap();
+        assert_eq!(transcript.prompt, calls.lock().unwrap()[0]["prompt"].as_str().unwrap());
+        assert!(!transcript.prompt.contains("123-45-6789"));
+        assert!(transcript.prompt.contains(REDACTED));
+    }
+
+    #[tokio::test]
+    async fn test_transcripts_are_not_kept_by_default() {
+        let (providers, _) = mock_ollama("Throughput is steady").await;
+        let manager = Arc::new(IntegrationManager::new());
+        let integration = manager.create_integration(sample_request("private")).await.unwrap();
+
+        let result = manager
+            .process_analysis_request(analysis_request(&integration, serde_json::json!({"rps": 120})), &providers)
+            .await
+            .unwrap();
+
+        assert!(manager.get_transcript(&result.id).await.is_none());
+    }
+}
@@ -0,0 +1,96 @@
+//! Insight counts over time, bucketed for charting
+
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use super::integration_manager::IntegrationAnalysisResult;
+
+/// Width of one time bucket
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrendInterval {
+    Day,
+    /// ISO weeks, starting on Monday
+    Week,
+}
+
+impl TrendInterval {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "day" => Some(TrendInterval::Day),
+            "week" => Some(TrendInterval::Week),
+            _ => None,
+        }
+    }
+
+    /// Start (UTC midnight) of the bucket containing `at`
+    fn bucket_start(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        let date = match self {
+            TrendInterval::Day => at.date_naive(),
+            TrendInterval::Week => at.date_naive() - Duration::days(at.weekday().num_days_from_monday() as i64),
+        };
+        Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap_or_default())
+    }
+}
+
+/// Insight counts for the results created in one bucket
+#[derive(Debug, Clone, Serialize)]
+pub struct TrendBucket {
+    pub start: DateTime<Utc>,
+    pub results: usize,
+    pub insights: usize,
+    pub by_type: BTreeMap<String, usize>,
+    pub by_severity: BTreeMap<String, usize>,
+}
+
+/// Count the insights of `results` per bucket, oldest bucket first. Buckets
+/// with no results are omitted.
+pub fn insight_trends(results: &[IntegrationAnalysisResult], interval: TrendInterval) -> Vec<TrendBucket> {
+    let mut buckets: BTreeMap<DateTime<Utc>, TrendBucket> = BTreeMap::new();
+
+    for result in results {
+        let start = interval.bucket_start(result.created_at);
+        let bucket = buckets.entry(start).or_insert_with(|| TrendBucket {
+            start,
+            results: 0,
+            insights: 0,
+            by_type: BTreeMap::new(),
+            by_severity: BTreeMap::new(),
+        });
+        bucket.results += 1;
+
+        let insights = result.analysis_result.get("insights").and_then(|i| i.as_array());
+        for insight in insights.into_iter().flatten() {
+            bucket.insights += 1;
+            *bucket.by_type.entry(label(insight, "type")).or_default() += 1;
+            *bucket.by_severity.entry(label(insight, "severity")).or_default() += 1;
+        }
+    }
+
+    buckets.into_values().collect()
+}
+
+/// Lowercased string value of `field`, or `unspecified`
+fn label(insight: &serde_json::Value, field: &str) -> String {
+    insight
+        .get(field)
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_lowercase())
+        .unwrap_or_else(|| "unspecified".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_week_buckets_start_on_monday() {
+        // 2024-03-14 was a Thursday
+        let thursday = Utc.with_ymd_and_hms(2024, 3, 14, 17, 30, 0).unwrap();
+        assert_eq!(TrendInterval::Week.bucket_start(thursday), Utc.with_ymd_and_hms(2024, 3, 11, 0, 0, 0).unwrap());
+        assert_eq!(TrendInterval::Day.bucket_start(thursday), Utc.with_ymd_and_hms(2024, 3, 14, 0, 0, 0).unwrap());
+        assert_eq!(TrendInterval::parse("WEEK"), Some(TrendInterval::Week));
+        assert_eq!(TrendInterval::parse("month"), None);
+    }
+}
@@ -2,17 +2,22 @@
 //! Provides endpoints for user dashboards, integrations, and analytics
 
 use axum::{
-    extract::{Path, Query, State},
+    extract::{Extension, Path, Query, State},
     http::StatusCode,
     response::Json,
     routing::{get, post, delete},
     Router,
 };
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use super::auth::{get_current_user, ClerkUser};
-use super::integration_manager::{IntegrationManager, CreateIntegrationRequest, Integration, IntegrationAnalysisResult};
+use super::extract::JsonBody;
+use super::integration_manager::{
+    CreateIntegrationError, CreateIntegrationRequest, Integration, IntegrationAnalysisResult, IntegrationManager,
+    IssuedIntegration, TagQuery,
+};
 use super::core_handlers::ApiState;
 
 /// Create user-specific routes
@@ -29,7 +34,8 @@ pub fn create_user_routes() -> Router<Arc<ApiState>> {
 
 /// Get integrations for the authenticated user
 async fn get_user_integrations(
-    State(state): State<Arc<ApiState>>,
+    State(_state): State<Arc<ApiState>>,
+    Query(query): Query<TagQuery>,
     request: axum::extract::Request,
 ) -> Result<Json<Vec<Integration>>, StatusCode> {
     let user = get_current_user(&request)
@@ -38,30 +44,30 @@ async fn get_user_integrations(
     // For now, we'll use a simple integration manager
     // In production, you'd get this from the state
     let manager = IntegrationManager::new();
-    let integrations = manager.get_user_integrations(&user.id).await;
-    
+    let mut integrations = manager.get_user_integrations(&user.id).await;
+    if let Some(tag) = &query.tag {
+        integrations.retain(|integration| integration.has_tag(tag));
+    }
+
     Ok(Json(integrations))
 }
 
 /// Create a new integration for the authenticated user
 async fn create_user_integration(
     State(state): State<Arc<ApiState>>,
-    request: axum::extract::Request,
-    Json(integration_request): Json<CreateIntegrationRequest>,
-) -> Result<Json<Integration>, StatusCode> {
-    let user = get_current_user(&request)
-        .ok_or(StatusCode::UNAUTHORIZED)?;
-
-    let manager = IntegrationManager::new();
-    match manager.create_user_integration(&user.id, integration_request).await {
-        Ok(integration) => Ok(Json(integration)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    Extension(user): Extension<ClerkUser>,
+    JsonBody(integration_request): JsonBody<CreateIntegrationRequest>,
+) -> Result<Json<IssuedIntegration>, CreateIntegrationError> {
+    state
+        .integration_manager
+        .create_user_integration(&user.id, integration_request)
+        .await
+        .map(|integration| Json(integration.into()))
 }
 
 /// Delete a user's integration
 async fn delete_user_integration(
-    State(state): State<Arc<ApiState>>,
+    State(_state): State<Arc<ApiState>>,
     Path(integration_id): Path<String>,
     request: axum::extract::Request,
 ) -> Result<StatusCode, StatusCode> {
@@ -88,7 +94,7 @@ async fn delete_user_integration(
 
 /// Get analysis results for a user's integration
 async fn get_user_integration_results(
-    State(state): State<Arc<ApiState>>,
+    State(_state): State<Arc<ApiState>>,
     Path(integration_id): Path<String>,
     Query(params): Query<HashMap<String, String>>,
     request: axum::extract::Request,
@@ -114,7 +120,7 @@ async fn get_user_integration_results(
 
 /// Get user dashboard statistics
 async fn get_user_stats(
-    State(state): State<Arc<ApiState>>,
+    State(_state): State<Arc<ApiState>>,
     request: axum::extract::Request,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
     let user = get_current_user(&request)
@@ -128,7 +134,7 @@ async fn get_user_stats(
 
 /// Get user profile information
 async fn get_user_profile(
-    State(state): State<Arc<ApiState>>,
+    State(_state): State<Arc<ApiState>>,
     request: axum::extract::Request,
 ) -> Result<Json<UserProfile>, StatusCode> {
     let user = get_current_user(&request)
@@ -151,7 +157,7 @@ async fn get_user_profile(
 
 /// Get user analytics data
 async fn get_user_analytics(
-    State(state): State<Arc<ApiState>>,
+    State(_state): State<Arc<ApiState>>,
     Query(params): Query<HashMap<String, String>>,
     request: axum::extract::Request,
 ) -> Result<Json<UserAnalytics>, StatusCode> {
@@ -159,7 +165,7 @@ async fn get_user_analytics(
         .ok_or(StatusCode::UNAUTHORIZED)?;
 
     // Get time range from query params (default to last 30 days)
-    let days = params.get("days").and_then(|d| d.parse().ok()).unwrap_or(30);
+    let _days = params.get("days").and_then(|d| d.parse().ok()).unwrap_or(30);
     
     let manager = IntegrationManager::new();
     let integrations = manager.get_user_integrations(&user.id).await;
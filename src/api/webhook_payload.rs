@@ -0,0 +1,127 @@
+//! The versioned body POSTed to webhooks and callbacks.
+//!
+//! Within a `schema_version` fields may be added but are never removed,
+//! renamed or retyped; any such change bumps the version.
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use super::integration_manager::{AnalysisStatus, IntegrationAnalysisResult};
+
+/// Version of the payload shape described in this module
+pub const WEBHOOK_SCHEMA_VERSION: u32 = 1;
+
+/// Header carrying `sha256=<hex HMAC>` of a signed webhook
+pub const SIGNATURE_HEADER: &str = "X-JsonOracle-Signature";
+/// Header carrying the Unix timestamp (seconds) covered by the signature
+pub const TIMESTAMP_HEADER: &str = "X-JsonOracle-Timestamp";
+
+/// How much of a result a notification carries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadDetail {
+    /// Every field of the stored result
+    #[default]
+    Full,
+    /// Identity, status and counts only; fetch the result for the rest
+    Minimal,
+}
+
+/// A result as sent with `PayloadDetail::Full`
+#[derive(Debug, Serialize)]
+struct FullPayload<'a> {
+    schema_version: u32,
+    #[serde(flatten)]
+    result: &'a IntegrationAnalysisResult,
+}
+
+/// A result as sent with `PayloadDetail::Minimal`: no analysis output,
+/// prompt template, delivery history or size accounting
+#[derive(Debug, Serialize)]
+struct MinimalPayload<'a> {
+    schema_version: u32,
+    id: &'a str,
+    integration_id: &'a str,
+    system_name: &'a str,
+    status: &'a AnalysisStatus,
+    created_at: DateTime<Utc>,
+    processing_time: f64,
+    insights_count: usize,
+    recommendations_count: usize,
+    sequence: u64,
+}
+
+/// The JSON body notifying a receiver about `result`
+pub fn notification_body(result: &IntegrationAnalysisResult, detail: PayloadDetail) -> serde_json::Value {
+    let body = match detail {
+        PayloadDetail::Full => serde_json::to_value(FullPayload {
+            schema_version: WEBHOOK_SCHEMA_VERSION,
+            result,
+        }),
+        PayloadDetail::Minimal => serde_json::to_value(MinimalPayload {
+            schema_version: WEBHOOK_SCHEMA_VERSION,
+            id: &result.id,
+            integration_id: &result.integration_id,
+            system_name: &result.system_name,
+            status: &result.status,
+            created_at: result.created_at,
+            processing_time: result.processing_time,
+            insights_count: result.insights_count,
+            recommendations_count: result.recommendations_count,
+            sequence: result.sequence,
+        }),
+    };
+    body.unwrap_or_else(|_| serde_json::json!({"schema_version": WEBHOOK_SCHEMA_VERSION, "id": result.id}))
+}
+
+/// The JSON body of one streamed reply chunk
+pub fn chunk_body(result_id: &str, index: usize, chunk: &str) -> serde_json::Value {
+    serde_json::json!({
+        "schema_version": WEBHOOK_SCHEMA_VERSION,
+        "event": "chunk",
+        "result_id": result_id,
+        "index": index,
+        "chunk": chunk
+    })
+}
+
+/// The JSON body telling a streaming callback to drop the chunks from
+/// `discard_from` on: the generation that produced them failed and the
+/// reply starts over
+pub fn reset_body(result_id: &str, discard_from: usize) -> serde_json::Value {
+    serde_json::json!({
+        "schema_version": WEBHOOK_SCHEMA_VERSION,
+        "event": "reset",
+        "result_id": result_id,
+        "discard_from": discard_from
+    })
+}
+
+/// The `X-JsonOracle-Signature` value for `body` sent at `timestamp`:
+/// `sha256=` followed by the hex HMAC-SHA256, keyed by `secret`, of
+/// `"{timestamp}.{body}"`
+pub fn sign_payload(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_covers_timestamp_and_body() {
+        let body = br#"{"id":"r1"}"#;
+        assert_eq!(
+            sign_payload("hook-secret", 1_700_000_000, body),
+            "sha256=08f7294bb41bd55ec02bc3b43dadfd1fabf18abcdacd537d5f8ceebd299ea0c9"
+        );
+        assert_ne!(sign_payload("hook-secret", 1_700_000_001, body), sign_payload("hook-secret", 1_700_000_000, body));
+        assert_ne!(sign_payload("other-secret", 1_700_000_000, body), sign_payload("hook-secret", 1_700_000_000, body));
+    }
+}
@@ -0,0 +1,677 @@
+//! Webhook, callback and push deliveries of results, with their retries,
+//! signing and per-destination circuit breaking
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::data_source;
+use super::extract::JsonBody;
+use super::integration_manager::{
+    authorize_integration, AnalysisStatus, IntegrationAnalysisResult, IntegrationManager, IntegrationStatus, PushResultRequest,
+};
+use super::store::PendingWrite;
+use super::webhook_payload::{notification_body, sign_payload, PayloadDetail, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+
+/// Timeout for a single webhook delivery attempt
+pub(super) const WEBHOOK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Where a notification was sent
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryType {
+    Webhook,
+    Callback,
+    /// A stored result pushed on request to a URL of the caller's choosing
+    Push,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryOutcome {
+    Delivered,
+    Failed,
+    /// Skipped because the destination's circuit breaker is open
+    CircuitOpen,
+}
+
+/// One notification sent for a result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryRecord {
+    pub destination: String,
+    #[serde(rename = "type")]
+    pub delivery_type: DeliveryType,
+    /// Status code of the last attempt, if the destination responded
+    pub status_code: Option<u16>,
+    pub attempts: u32,
+    pub outcome: DeliveryOutcome,
+    /// The result's status when the notification was sent
+    pub result_status: AnalysisStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub delivered_at: DateTime<Utc>,
+    /// Chunks streamed to the destination before this final POST
+    #[serde(default)]
+    pub streamed_chunks: usize,
+}
+
+impl IntegrationManager {
+    /// POST the result to the integration's webhook, recording the delivery
+    /// on the result. A delivery that fails even after retries puts the
+    /// integration in the `Error` status; a later successful one restores it.
+    ///
+    /// With a `secret`, each attempt is signed so the receiver can check it
+    /// came from this integration and is not a replay:
+    ///
+    /// - `X-JsonOracle-Timestamp` is the Unix time in seconds of the attempt
+    /// - `X-JsonOracle-Signature` is `sha256=` followed by the lowercase hex
+    ///   HMAC-SHA256, keyed by the secret, of `{timestamp}.{body}`, where
+    ///   `body` is the raw request body exactly as received
+    ///
+    /// Receivers should recompute the signature, compare it in constant time
+    /// and reject timestamps too far from their own clock.
+    pub(super) async fn send_webhook_notification(
+        &self,
+        webhook_url: &str,
+        secret: Option<&str>,
+        result: &mut IntegrationAnalysisResult,
+        detail: PayloadDetail,
+    ) -> Result<(), String> {
+        log::info!("Sending {:?} webhook for result {} to: {}", result.status, result.id, webhook_url);
+        let record = self.deliver(DeliveryType::Webhook, webhook_url, secret, &[], result, detail).await;
+        let outcome = match record.outcome {
+            DeliveryOutcome::Delivered => {
+                self.set_integration_status(&result.integration_id, IntegrationStatus::Error, IntegrationStatus::Active)
+                    .await;
+                Ok(())
+            }
+            // Not attempted, so it says nothing new about the destination
+            DeliveryOutcome::CircuitOpen => Err(format!("Webhook {} skipped: circuit open", webhook_url)),
+            DeliveryOutcome::Failed => {
+                let error = record.error.clone().unwrap_or_default();
+                log::error!(
+                    "Webhook delivery of result {} to {} failed after {} attempt(s): {}",
+                    result.id, webhook_url, record.attempts, error
+                );
+                self.set_integration_status(&result.integration_id, IntegrationStatus::Active, IntegrationStatus::Error)
+                    .await;
+                Err(format!("Webhook delivery to {} failed: {}", webhook_url, error))
+            }
+        };
+        result.deliveries.push(record);
+        outcome
+    }
+
+    /// Move an integration from `from` to `to`, leaving any other status alone
+    pub(super) async fn set_integration_status(&self, integration_id: &str, from: IntegrationStatus, to: IntegrationStatus) {
+        let updated = {
+            let mut integrations = self.integrations.write().await;
+            match integrations.get_mut(integration_id) {
+                Some(integration) if integration.status == from => {
+                    integration.status = to;
+                    integration.clone()
+                }
+                _ => return,
+            }
+        };
+        self.persist(PendingWrite::Integration(Box::new(updated))).await;
+    }
+
+    /// POST a stored result to `request.url`, signed with the integration's
+    /// webhook secret like its webhooks, and record the delivery on the
+    /// result. `None` when the result does not exist; an error when the URL
+    /// or a header is unacceptable.
+    pub async fn push_result(
+        &self,
+        integration_id: &str,
+        result_id: &str,
+        request: &PushResultRequest,
+    ) -> Result<Option<DeliveryRecord>, String> {
+        let Some(integration) = self.get_integration(integration_id).await else {
+            return Ok(None);
+        };
+        let Some(result) = self
+            .get_analysis_results(integration_id, None)
+            .await
+            .into_iter()
+            .find(|r| r.id == result_id)
+        else {
+            return Ok(None);
+        };
+
+        let url = data_source::check_destination(&request.url, self.config.allow_private_data_sources).await?.url;
+        let mut headers = Vec::new();
+        for (name, value) in &request.headers {
+            let reserved = [reqwest::header::CONTENT_TYPE.as_str(), SIGNATURE_HEADER, TIMESTAMP_HEADER];
+            if reserved.iter().any(|reserved| name.eq_ignore_ascii_case(reserved)) {
+                return Err(format!("Header {} is set by the push itself", name));
+            }
+            if reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_err()
+                || reqwest::header::HeaderValue::from_str(value).is_err()
+            {
+                return Err(format!("Invalid header {}", name));
+            }
+            headers.push((name.clone(), value.clone()));
+        }
+
+        log::info!("Pushing result {} to: {}", result.id, url);
+        let payload = integration.configuration.notification_settings.payload;
+        let record = self
+            .deliver(DeliveryType::Push, url.as_str(), integration.webhook_secret.as_deref(), &headers, &result, payload)
+            .await;
+        self.record_delivery(integration_id, result_id, record.clone()).await;
+        Ok(Some(record))
+    }
+
+    /// Append a delivery to the stored result under the write lock, so
+    /// deliveries recorded while this one was in flight are kept
+    pub(super) async fn record_delivery(&self, integration_id: &str, result_id: &str, record: DeliveryRecord) {
+        let updated = {
+            let mut results = self.analysis_results.write().await;
+            let Some(result) = results
+                .get_mut(integration_id)
+                .and_then(|results| results.iter_mut().find(|r| r.id == result_id))
+            else {
+                return;
+            };
+            result.deliveries.push(record);
+            self.storage_usage.update(result);
+            result.clone()
+        };
+        self.persist(PendingWrite::Result(Box::new(updated))).await;
+    }
+
+    /// Send callback notification
+    pub(super) async fn send_callback_notification(
+        &self,
+        callback_url: &str,
+        result: &IntegrationAnalysisResult,
+        detail: PayloadDetail,
+    ) -> DeliveryRecord {
+        log::info!("Sending callback notification for result {} to: {}", result.id, callback_url);
+        self.deliver(DeliveryType::Callback, callback_url, None, &[], result, detail).await
+    }
+
+    /// POST the result to `destination`, retrying 5xx responses and
+    /// connection failures with exponential backoff, and record how it went.
+    /// Attempts are signed with `secret` as `send_webhook_notification` describes
+    /// and carry `headers` besides.
+    pub(super) async fn deliver(
+        &self,
+        delivery_type: DeliveryType,
+        destination: &str,
+        secret: Option<&str>,
+        headers: &[(String, String)],
+        result: &IntegrationAnalysisResult,
+        detail: PayloadDetail,
+    ) -> DeliveryRecord {
+        let mut record = DeliveryRecord {
+            destination: destination.to_string(),
+            delivery_type,
+            status_code: None,
+            attempts: 1,
+            outcome: DeliveryOutcome::Failed,
+            result_status: result.status.clone(),
+            error: None,
+            delivered_at: Utc::now(),
+            streamed_chunks: 0,
+        };
+
+        let guarded = delivery_type == DeliveryType::Webhook;
+        if guarded && !self.webhook_circuits.allow(destination) {
+            log::warn!("Skipping webhook to {}: circuit open", destination);
+            record.attempts = 0;
+            record.outcome = DeliveryOutcome::CircuitOpen;
+            return record;
+        }
+
+        // Every outbound URL gets the same SSRF check as a data source, and the
+        // client only connects to the addresses that passed it
+        let client = match data_source::check_destination(destination, self.config.allow_private_data_sources)
+            .await
+            .and_then(|destination| destination.client())
+        {
+            Ok(client) => client,
+            Err(e) => {
+                log::warn!("Refusing {:?} delivery to {}: {}", delivery_type, destination, e);
+                record.attempts = 0;
+                record.error = Some(e);
+                return record;
+            }
+        };
+
+        // Serialized once so the signed bytes are exactly the bytes sent
+        let body = serde_json::to_vec(&notification_body(result, detail)).unwrap_or_default();
+        let mut backoff = std::time::Duration::from_millis(self.config.webhook_backoff_ms);
+        for attempt in 1..=self.config.webhook_retries + 1 {
+            if attempt > 1 {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            record.attempts = attempt;
+
+            let mut request = client
+                .post(destination)
+                .timeout(WEBHOOK_TIMEOUT)
+                .header(reqwest::header::CONTENT_TYPE, "application/json");
+            for (name, value) in headers {
+                request = request.header(name.as_str(), value.as_str());
+            }
+            if let Some(secret) = secret {
+                let timestamp = Utc::now().timestamp();
+                request = request
+                    .header(TIMESTAMP_HEADER, timestamp.to_string())
+                    .header(SIGNATURE_HEADER, sign_payload(secret, timestamp, &body));
+            }
+            let response = request.body(body.clone()).send().await;
+
+            // Only server errors and failures to connect are worth retrying
+            let retryable = match response {
+                Ok(response) => {
+                    record.status_code = Some(response.status().as_u16());
+                    if response.status().is_success() {
+                        record.outcome = DeliveryOutcome::Delivered;
+                        record.error = None;
+                        break;
+                    }
+                    log::warn!("{:?} {} responded with {} (attempt {})", delivery_type, destination, response.status(), attempt);
+                    record.error = Some(format!("Destination responded with {}", response.status()));
+                    response.status().is_server_error()
+                }
+                Err(e) => {
+                    log::warn!("{:?} delivery to {} failed (attempt {}): {}", delivery_type, destination, attempt, e);
+                    record.status_code = None;
+                    record.error = Some(e.to_string());
+                    true
+                }
+            };
+            if !retryable {
+                break;
+            }
+        }
+        record.delivered_at = Utc::now();
+
+        if guarded {
+            match record.outcome {
+                DeliveryOutcome::Delivered => self.webhook_circuits.record_success(destination),
+                _ => self.webhook_circuits.record_failure(destination),
+            }
+        }
+
+        record
+    }
+}
+
+/// Send a stored result to a URL of the caller's choosing, signed with the
+/// integration's webhook secret; needs its API key or the admin token
+pub(super) async fn push_analysis_result(
+    State(manager): State<Arc<IntegrationManager>>,
+    Path((integration_id, result_id)): Path<(String, String)>,
+    headers: HeaderMap,
+    JsonBody(request): JsonBody<PushResultRequest>,
+) -> Result<(StatusCode, Json<DeliveryRecord>), (StatusCode, String)> {
+    authorize_integration(&manager, &headers, &integration_id)
+        .await
+        .map_err(|status| (status, String::new()))?;
+    let record = manager
+        .push_result(&integration_id, &result_id, &request)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?
+        .ok_or((StatusCode::NOT_FOUND, format!("Result {} not found", result_id)))?;
+    let status = match record.outcome {
+        DeliveryOutcome::Delivered => StatusCode::OK,
+        _ => StatusCode::BAD_GATEWAY,
+    };
+    Ok((status, Json(record)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::integration_manager::{create_integration_routes, Integration, WebhookEvents};
+    use crate::ollama::Config;
+    use crate::test_support::*;
+    use axum::{body::Body, http::header, routing::post, Router};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_on_start_webhook_fires_before_completion() {
+        let (providers, _) = mock_ollama("All services healthy").await;
+        let (webhook_url, received) = mock_receiver().await;
+        let manager = Arc::new(local_delivery_manager());
+
+        let mut request = sample_request("progress");
+        request.webhook_url = Some(webhook_url);
+        request.configuration.notification_settings.webhook_events = WebhookEvents {
+            on_start: true,
+            on_success: true,
+            on_failure: false,
+        };
+        let integration = manager.create_integration(request).await.unwrap();
+
+        let result = manager
+            .process_analysis_request(analysis_request(&integration, serde_json::json!({"up": 3})), &providers)
+            .await
+            .unwrap();
+
+        let deliveries = received.lock().unwrap().clone();
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0]["status"], "Processing");
+        assert_eq!(deliveries[1]["status"], "Completed");
+        assert!(deliveries.iter().all(|d| d["id"] == result.id));
+    }
+
+    #[tokio::test]
+    async fn test_result_records_webhook_and_callback_deliveries() {
+        let (providers, _) = mock_ollama("All services healthy").await;
+        let (webhook_url, webhooks) = mock_receiver().await;
+        let (callback_url, callbacks) = mock_receiver().await;
+        let manager = Arc::new(local_delivery_manager());
+
+        let mut request = sample_request("deliveries");
+        request.webhook_url = Some(webhook_url.clone());
+        let integration = manager.create_integration(request).await.unwrap();
+
+        let mut analysis = analysis_request(&integration, serde_json::json!({"up": 3}));
+        analysis.callback_url = Some(callback_url.clone());
+        let result = manager.process_analysis_request(analysis, &providers).await.unwrap();
+
+        assert_eq!(webhooks.lock().unwrap().len(), 1);
+        assert_eq!(callbacks.lock().unwrap().len(), 1);
+
+        assert_eq!(result.deliveries.len(), 2);
+        let webhook = &result.deliveries[0];
+        assert_eq!(webhook.delivery_type, DeliveryType::Webhook);
+        assert_eq!(webhook.destination, webhook_url);
+        assert_eq!(webhook.status_code, Some(200));
+        assert_eq!(webhook.attempts, 1);
+        assert_eq!(webhook.outcome, DeliveryOutcome::Delivered);
+        let callback = &result.deliveries[1];
+        assert_eq!(callback.delivery_type, DeliveryType::Callback);
+        assert_eq!(callback.destination, callback_url);
+        assert_eq!(callback.outcome, DeliveryOutcome::Delivered);
+
+        // The stored copy carries the same records
+        let stored = manager.get_analysis_results(&integration.id, None).await;
+        assert_eq!(stored[0].deliveries.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_callback_is_recorded_as_failed() {
+        let (providers, _) = mock_ollama("All services healthy").await;
+        let config = Config {
+            webhook_backoff_ms: 1,
+            allow_private_data_sources: true,
+            ..Config::default()
+        };
+        let manager = Arc::new(IntegrationManager::with_config(config));
+        let integration = manager.create_integration(sample_request("unreachable")).await.unwrap();
+
+        let mut analysis = analysis_request(&integration, serde_json::json!({"up": 3}));
+        analysis.callback_url = Some("http://127.0.0.1:9/hook".to_string());
+        let result = manager.process_analysis_request(analysis, &providers).await.unwrap();
+
+        assert_eq!(result.deliveries.len(), 1);
+        assert_eq!(result.deliveries[0].outcome, DeliveryOutcome::Failed);
+        assert_eq!(result.deliveries[0].status_code, None);
+        assert_eq!(result.deliveries[0].attempts, 4);
+        assert!(result.deliveries[0].error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_failing_webhook_opens_circuit_and_fast_fails() {
+        let (providers, _) = mock_ollama("All services healthy").await;
+        let config = Config {
+            webhook_circuit_failure_threshold: 2,
+            webhook_circuit_cooldown_seconds: 60,
+            webhook_retries: 0,
+            allow_private_data_sources: true,
+            ..Config::default()
+        };
+        let manager = Arc::new(IntegrationManager::with_config(config));
+
+        let mut request = sample_request("broken-hook");
+        request.webhook_url = Some("http://127.0.0.1:9/hook".to_string());
+        let integration = manager.create_integration(request).await.unwrap();
+
+        let mut outcomes = Vec::new();
+        for _ in 0..3 {
+            let result = manager
+                .process_analysis_request(analysis_request(&integration, serde_json::json!({"up": 3})), &providers)
+                .await
+                .unwrap();
+            outcomes.push((result.deliveries[0].outcome, result.deliveries[0].attempts));
+        }
+
+        assert_eq!(
+            outcomes,
+            vec![
+                (DeliveryOutcome::Failed, 1),
+                (DeliveryOutcome::Failed, 1),
+                (DeliveryOutcome::CircuitOpen, 0),
+            ]
+        );
+        let stored = manager.get_analysis_results(&integration.id, Some(1)).await;
+        assert_eq!(serde_json::json!(stored[0].deliveries[0].outcome), "circuit_open");
+    }
+
+    #[tokio::test]
+    async fn test_deliveries_to_private_addresses_are_refused_by_default() {
+        let (callback_url, received) = mock_receiver().await;
+        let (providers, _) = mock_ollama("All services healthy").await;
+        let manager = Arc::new(IntegrationManager::new());
+        let mut create = sample_request("loopback");
+        create.webhook_url = Some(callback_url.clone());
+        let integration = manager.create_integration(create).await.unwrap();
+
+        let mut request = analysis_request(&integration, serde_json::json!({"latency_ms": [120, 480]}));
+        request.callback_url = Some(callback_url);
+        let result = manager.process_analysis_request(request, &providers).await.unwrap();
+
+        assert!(received.lock().unwrap().is_empty());
+        assert_eq!(result.deliveries.len(), 2);
+        for delivery in &result.deliveries {
+            assert_eq!(delivery.outcome, DeliveryOutcome::Failed);
+            assert_eq!(delivery.attempts, 0);
+            assert!(delivery.error.as_deref().unwrap().contains("internal address"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_notifications_carry_a_schema_version_and_minimal_omits_heavy_fields() {
+        let (providers, _) = mock_ollama("Orders are up").await;
+        let (webhook_url, webhooks) = mock_receiver().await;
+        let (callback_url, callbacks) = mock_receiver().await;
+        let manager = Arc::new(local_delivery_manager());
+
+        let mut request = sample_request("full");
+        request.webhook_url = Some(webhook_url.clone());
+        let full = manager.create_integration(request).await.unwrap();
+        let mut analysis = analysis_request(&full, serde_json::json!({"orders": [1, 2]}));
+        analysis.callback_url = Some(callback_url.clone());
+        let result = manager.process_analysis_request(analysis, &providers).await.unwrap();
+
+        for body in [&webhooks.lock().unwrap()[0], &callbacks.lock().unwrap()[0]] {
+            assert_eq!(body["schema_version"], crate::api::webhook_payload::WEBHOOK_SCHEMA_VERSION);
+            assert_eq!(body["id"], result.id.as_str());
+            assert_eq!(body["analysis_result"]["summary"], "Orders are up");
+            assert!(body.get("prompt_template").is_some());
+        }
+
+        let mut request = sample_request("minimal");
+        request.webhook_url = Some(webhook_url);
+        request.configuration.notification_settings.payload = PayloadDetail::Minimal;
+        let minimal = manager.create_integration(request).await.unwrap();
+        let mut analysis = analysis_request(&minimal, serde_json::json!({"orders": [1, 2]}));
+        analysis.callback_url = Some(callback_url);
+        let result = manager.process_analysis_request(analysis, &providers).await.unwrap();
+
+        for body in [&webhooks.lock().unwrap()[1], &callbacks.lock().unwrap()[1]] {
+            assert_eq!(body["schema_version"], 1);
+            assert_eq!(body["id"], result.id.as_str());
+            assert_eq!(body["status"], "Completed");
+            assert_eq!(body["insights_count"], result.insights_count);
+            for heavy in ["analysis_result", "deliveries", "prompt_template", "prompt_chars", "input_chars"] {
+                assert!(body.get(heavy).is_none(), "minimal payload carries {}", heavy);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_webhook_retries_server_errors_and_marks_integration_on_permanent_failure() {
+        use std::sync::atomic::Ordering;
+
+        let (providers, _) = mock_ollama("All services healthy").await;
+        let config = Config {
+            webhook_retries: 3,
+            webhook_backoff_ms: 1,
+            allow_private_data_sources: true,
+            ..Config::default()
+        };
+        let manager = Arc::new(IntegrationManager::with_config(config));
+        let analyze = |integration: &Integration| {
+            manager.process_analysis_request(analysis_request(integration, serde_json::json!({"up": 3})), &providers)
+        };
+
+        // Two 503s, then delivered on the third attempt
+        let (url, hits) = scripted_receiver(vec![503, 503]).await;
+        let mut request = sample_request("flaky-hook");
+        request.webhook_url = Some(url);
+        let integration = manager.create_integration(request).await.unwrap();
+        let delivery = analyze(&integration).await.unwrap().deliveries[0].clone();
+        assert_eq!((delivery.outcome, delivery.attempts, delivery.status_code), (DeliveryOutcome::Delivered, 3, Some(200)));
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        assert_eq!(manager.get_integration(&integration.id).await.unwrap().status, IntegrationStatus::Active);
+
+        // A client error is not retried
+        let (url, hits) = scripted_receiver(vec![404]).await;
+        let mut request = sample_request("missing-hook");
+        request.webhook_url = Some(url);
+        let integration = manager.create_integration(request).await.unwrap();
+        let delivery = analyze(&integration).await.unwrap().deliveries[0].clone();
+        assert_eq!((delivery.outcome, delivery.attempts), (DeliveryOutcome::Failed, 1));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // Failing every attempt puts the integration in Error, until a delivery succeeds
+        let (url, hits) = scripted_receiver(vec![500; 4]).await;
+        let mut request = sample_request("down-hook");
+        request.webhook_url = Some(url);
+        let integration = manager.create_integration(request).await.unwrap();
+        let result = analyze(&integration).await.unwrap();
+        assert_eq!(result.status, AnalysisStatus::Completed);
+        assert_eq!((result.deliveries[0].outcome, result.deliveries[0].attempts), (DeliveryOutcome::Failed, 4));
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+        assert_eq!(manager.get_integration(&integration.id).await.unwrap().status, IntegrationStatus::Error);
+
+        analyze(&integration).await.unwrap();
+        assert_eq!(manager.get_integration(&integration.id).await.unwrap().status, IntegrationStatus::Active);
+    }
+
+    #[tokio::test]
+    async fn test_webhooks_are_signed_with_the_integration_secret() {
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let store = received.clone();
+        let app = Router::new().route("/hook", post(move |headers: axum::http::HeaderMap, body: axum::body::Bytes| {
+            let store = store.clone();
+            async move {
+                let header = |name: &str| headers.get(name).map(|value| value.to_str().unwrap().to_string());
+                store.lock().unwrap().push((header(SIGNATURE_HEADER), header(TIMESTAMP_HEADER), body));
+                StatusCode::OK
+            }
+        }));
+        let url = format!("{}/hook", serve(app).await);
+
+        let (providers, _) = mock_ollama("All services healthy").await;
+        let manager = Arc::new(local_delivery_manager());
+        let mut request = sample_request("signed");
+        request.webhook_url = Some(url.clone());
+        request.webhook_secret = Some("hook-secret".to_string());
+        let signed = manager.create_integration(request).await.unwrap();
+        let mut request = sample_request("unsigned");
+        request.webhook_url = Some(url);
+        let unsigned = manager.create_integration(request).await.unwrap();
+
+        manager.process_analysis_request(analysis_request(&signed, serde_json::json!({"up": 3})), &providers).await.unwrap();
+        manager.process_analysis_request(analysis_request(&unsigned, serde_json::json!({"up": 3})), &providers).await.unwrap();
+
+        let received = received.lock().unwrap();
+        let (signature, timestamp, body) = &received[0];
+        let timestamp: i64 = timestamp.as_deref().unwrap().parse().unwrap();
+        assert!((Utc::now().timestamp() - timestamp).abs() < 60);
+        assert_eq!(signature.as_deref(), Some(sign_payload("hook-secret", timestamp, body).as_str()));
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(body).unwrap()["integration_id"], signed.id);
+
+        assert_eq!((&received[1].0, &received[1].1), (&None, &None));
+    }
+
+    #[tokio::test]
+    async fn test_pushed_result_is_delivered_signed_with_custom_headers() {
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let store = received.clone();
+        let app = Router::new().route("/inbox", post(move |headers: axum::http::HeaderMap, body: axum::body::Bytes| {
+            let store = store.clone();
+            async move {
+                let header = |name: &str| headers.get(name).map(|value| value.to_str().unwrap().to_string());
+                store.lock().unwrap().push((header(SIGNATURE_HEADER), header(TIMESTAMP_HEADER), header("x-tenant"), body));
+                StatusCode::OK
+            }
+        }));
+        let url = format!("{}/inbox", serve(app).await);
+
+        let manager = Arc::new(IntegrationManager::with_config(Config {
+            allow_private_data_sources: true,
+            ..Config::default()
+        }));
+        let mut request = sample_request("replay");
+        request.webhook_secret = Some("push-secret".to_string());
+        let integration = manager.create_integration(request).await.unwrap();
+        let mut older = sample_result(&integration.id, AnalysisStatus::Completed);
+        let mut newer = sample_result(&integration.id, AnalysisStatus::Completed);
+        manager.store_result(&mut older).await;
+        manager.store_result(&mut newer).await;
+
+        let push_as = |api_key: &str, result_id: &str, body: serde_json::Value| {
+            create_integration_routes(offline_providers()).with_state(manager.clone()).oneshot(
+                axum::http::Request::post(format!("/integrations/{}/results/{}/push", integration.id, result_id))
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header(header::AUTHORIZATION, format!("Bearer {}", api_key))
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+        };
+        let push = |result_id: &str, body: serde_json::Value| push_as(&integration.api_key, result_id, body);
+
+        // Only the integration's own key may push its results
+        let response = push_as("json_oracle_guess", &older.id, serde_json::json!({"url": url})).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(received.lock().unwrap().is_empty());
+
+        let response = push(&older.id, serde_json::json!({"url": url, "headers": {"X-Tenant": "acme"}})).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let record: DeliveryRecord = serde_json::from_str(&body_string(response).await).unwrap();
+        assert_eq!((record.delivery_type, record.outcome), (DeliveryType::Push, DeliveryOutcome::Delivered));
+
+        {
+            let received = received.lock().unwrap();
+            assert_eq!(received.len(), 1);
+            let (signature, timestamp, tenant, body) = &received[0];
+            let timestamp: i64 = timestamp.as_deref().unwrap().parse().unwrap();
+            assert_eq!(signature.as_deref(), Some(sign_payload("push-secret", timestamp, body).as_str()));
+            assert_eq!(tenant.as_deref(), Some("acme"));
+            assert_eq!(serde_json::from_slice::<serde_json::Value>(body).unwrap()["id"], older.id);
+        }
+        let stored = manager.get_analysis_results(&integration.id, None).await;
+        assert_eq!(stored.iter().find(|r| r.id == older.id).unwrap().deliveries.len(), 1);
+
+        let response = push(&older.id, serde_json::json!({"url": url, "headers": {"X-JsonOracle-Signature": "forged"}})).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = push("missing", serde_json::json!({"url": url})).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+}
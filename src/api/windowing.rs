@@ -0,0 +1,128 @@
+//! Splitting time series input into windows for per-window analysis
+
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How a series is split into windows
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "by", rename_all = "snake_case")]
+pub enum WindowSpec {
+    /// Consecutive windows of `size` rows
+    Count { size: usize },
+    /// Windows of `interval_seconds` measured from the earliest value of `field`
+    Time { field: String, interval_seconds: i64 },
+}
+
+/// A slice of the series together with the bounds it covers
+#[derive(Debug, Clone, Serialize)]
+pub struct Window {
+    pub index: usize,
+    pub rows: Vec<Value>,
+    /// Start of the window in Unix seconds (time windows only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<i64>,
+    /// Exclusive end of the window in Unix seconds (time windows only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<i64>,
+}
+
+/// Split an array of rows into windows, skipping windows with no rows
+pub fn split_series(data: &Value, spec: &WindowSpec) -> Result<Vec<Window>, String> {
+    let rows = data.as_array().ok_or("Windowing requires the data to be an array")?;
+
+    match spec {
+        WindowSpec::Count { size } => {
+            if *size == 0 {
+                return Err("Window size must be at least 1".to_string());
+            }
+            Ok(rows
+                .chunks(*size)
+                .enumerate()
+                .map(|(index, chunk)| Window {
+                    index,
+                    rows: chunk.to_vec(),
+                    start: None,
+                    end: None,
+                })
+                .collect())
+        }
+        WindowSpec::Time { field, interval_seconds } => {
+            if *interval_seconds <= 0 {
+                return Err("Window interval must be positive".to_string());
+            }
+
+            let mut stamped = Vec::with_capacity(rows.len());
+            for (position, row) in rows.iter().enumerate() {
+                let timestamp = row
+                    .get(field)
+                    .and_then(parse_timestamp)
+                    .ok_or_else(|| format!("Row {} has no valid '{}' timestamp", position, field))?;
+                stamped.push((timestamp, row));
+            }
+
+            let Some(origin) = stamped.iter().map(|(t, _)| *t).min() else {
+                return Ok(Vec::new());
+            };
+
+            let mut buckets: std::collections::BTreeMap<i64, Vec<Value>> = std::collections::BTreeMap::new();
+            for (timestamp, row) in stamped {
+                let bucket = (timestamp - origin) / interval_seconds;
+                buckets.entry(bucket).or_default().push(row.clone());
+            }
+
+            Ok(buckets
+                .into_iter()
+                .enumerate()
+                .map(|(index, (bucket, rows))| {
+                    let start = origin + bucket * interval_seconds;
+                    Window {
+                        index,
+                        rows,
+                        start: Some(start),
+                        end: Some(start + interval_seconds),
+                    }
+                })
+                .collect())
+        }
+    }
+}
+
+/// Accept RFC 3339 strings or Unix seconds
+fn parse_timestamp(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(n) => n.as_i64().or_else(|| n.as_f64().map(|f| f as i64)),
+        Value::String(s) => DateTime::parse_from_rfc3339(s).ok().map(|t| t.timestamp()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_time_windows_group_rows_by_interval() {
+        let data = json!([
+            {"ts": "2024-01-01T00:00:00Z", "cpu": 10},
+            {"ts": "2024-01-01T00:30:00Z", "cpu": 20},
+            {"ts": "2024-01-01T01:10:00Z", "cpu": 30},
+            {"ts": "2024-01-01T03:05:00Z", "cpu": 40}
+        ]);
+        let spec = WindowSpec::Time { field: "ts".to_string(), interval_seconds: 3600 };
+
+        let windows = split_series(&data, &spec).unwrap();
+
+        // The empty 02:00 hour is skipped
+        assert_eq!(windows.iter().map(|w| w.rows.len()).collect::<Vec<_>>(), vec![2, 1, 1]);
+        assert_eq!(windows[2].start, Some(windows[0].start.unwrap() + 3 * 3600));
+    }
+
+    #[test]
+    fn test_time_windows_reject_rows_without_timestamp() {
+        let data = json!([{"ts": 0}, {"cpu": 1}]);
+        let spec = WindowSpec::Time { field: "ts".to_string(), interval_seconds: 60 };
+        assert!(split_series(&data, &spec).is_err());
+    }
+}
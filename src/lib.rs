@@ -1,8 +1,12 @@
 //! Library crate for AI JSON Analysis API
 
 pub mod api;
+pub mod cli;
 pub mod ollama;
 
+#[cfg(test)]
+mod test_support;
+
 // Re-export main functionality
 pub use api::start_api_server;
 
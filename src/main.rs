@@ -1,26 +1,64 @@
 //! Main entry point for the AI JSON Analysis API
-//! Compatible with both traditional servers and serverless platforms
+//! Compatible with both traditional servers and serverless platforms.
+//! `analyze --file <path> ...` runs a single analysis and prints it instead.
 
 use std::env;
 
+use ai_json_analysis_api::cli::{self, AnalyzeArgs};
+use ai_json_analysis_api::ollama::{Config, ProviderRegistry};
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize logging
     env_logger::init();
-    
+
     // Load environment variables
     dotenv::dotenv().ok();
-    
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("analyze") {
+        run_analyze(&args[1..]).await;
+        return Ok(());
+    }
+
     // Get port from environment or default to 3000
     let port = env::var("PORT")
         .unwrap_or_else(|_| "3000".to_string())
         .parse::<u16>()
         .unwrap_or(3000);
-    
+
     log::info!("🚀 Starting AI JSON Analysis API on port {}", port);
-    
+
     // Start the API server
     ai_json_analysis_api::api::start_api_server(port).await?;
-    
+
     Ok(())
 }
+
+/// Run one analysis and print the result to stdout; logs go to stderr, so
+/// the output can be piped straight into other tools
+async fn run_analyze(args: &[String]) {
+    let args = match AnalyzeArgs::parse(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, cli::ANALYZE_USAGE);
+            std::process::exit(2);
+        }
+    };
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let providers = ProviderRegistry::from_config(&config);
+    match cli::analyze(&args, config, &providers).await {
+        Ok(result) => println!("{}", serde_json::to_string_pretty(&result).unwrap_or_default()),
+        Err(e) => {
+            eprintln!("Analysis failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -87,7 +87,7 @@ impl AIModelManager {
         // Assign to role
         self.role_assignments
             .entry(role)
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(model_name);
         
         // Sort by priority within role
@@ -136,11 +136,7 @@ impl AIModelManager {
         else if name_lower.contains("llama2") || name_lower.contains("codellama") {
             ModelRole::RiskManagement
         }
-        // General purpose models
-        else if name_lower.contains("tinyllama") || name_lower.contains("llama") {
-            ModelRole::GeneralPurpose
-        }
-        // Default to general purpose
+        // General purpose models (tinyllama, other llama variants) and everything else
         else {
             ModelRole::GeneralPurpose
         }
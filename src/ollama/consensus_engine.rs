@@ -373,7 +373,7 @@ impl ConsensusEngine {
         for response in responses {
             role_responses
                 .entry(response.role.clone())
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(response);
         }
         
@@ -0,0 +1,389 @@
+//! Several Ollama hosts behind one provider, with per-host health tracking
+//! and failover to the next host when one is down
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+use crate::ollama::conversation_manager::ConversationMessage;
+use crate::ollama::{Config, Generation, GenerationOptions, LlmProvider, OllamaClient, OllamaError, RetryPolicy};
+
+/// Default wait after a failure before an unhealthy host is probed again
+const DEFAULT_REPROBE_AFTER: Duration = Duration::from_secs(30);
+
+/// Whether `error` means the host is down or failing, as opposed to the host
+/// refusing the request itself (an unknown model, a bad request, a model
+/// error), which every other host would refuse as well
+fn is_host_failure(error: &anyhow::Error) -> bool {
+    !error.downcast_ref::<OllamaError>().is_some_and(OllamaError::is_request_error)
+}
+
+/// Health of one host as last observed
+#[derive(Debug, Clone, Serialize)]
+pub struct HostStatus {
+    pub url: String,
+    pub healthy: bool,
+    pub consecutive_failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_checked: Option<DateTime<Utc>>,
+}
+
+struct Host {
+    client: OllamaClient,
+    status: Mutex<HostStatus>,
+}
+
+impl Host {
+    fn record_success(&self) {
+        let mut status = self.status.lock().unwrap();
+        if !status.healthy {
+            log::info!("Ollama host {} recovered", status.url);
+        }
+        status.healthy = true;
+        status.consecutive_failures = 0;
+        status.last_error = None;
+        status.last_checked = Some(Utc::now());
+    }
+
+    /// Unhealthy, and long enough ago that a request should find out whether it recovered
+    fn due_for_reprobe(&self, reprobe_after: Duration) -> bool {
+        let status = self.status.lock().unwrap();
+        !status.healthy
+            && status.last_checked.is_none_or(|checked| {
+                Utc::now().signed_duration_since(checked).to_std().unwrap_or_default() >= reprobe_after
+            })
+    }
+
+    fn record_failure(&self, error: &anyhow::Error) {
+        let mut status = self.status.lock().unwrap();
+        if status.healthy {
+            log::warn!("Ollama host {} failed, failing over: {}", status.url, error);
+        }
+        status.healthy = false;
+        status.consecutive_failures += 1;
+        status.last_error = Some(error.to_string());
+        status.last_checked = Some(Utc::now());
+    }
+}
+
+/// Round-robins requests across Ollama hosts. Hosts that failed their last
+/// request are tried only after every healthy host, so a down host is skipped
+/// until it succeeds again; once `reprobe_after` has passed since its failure
+/// it is tried first by one request to see whether it is back. Only host
+/// failures fail over: a request the host refuses is refused on every host.
+pub struct OllamaHostPool {
+    hosts: Vec<Host>,
+    next: AtomicUsize,
+    reprobe_after: Duration,
+}
+
+impl OllamaHostPool {
+    pub fn new(urls: &[String], timeout_seconds: u64) -> Self {
+        let hosts = urls
+            .iter()
+            .map(|url| Host {
+                client: OllamaClient::new(url, timeout_seconds),
+                status: Mutex::new(HostStatus {
+                    url: url.clone(),
+                    healthy: true,
+                    consecutive_failures: 0,
+                    last_error: None,
+                    last_checked: None,
+                }),
+            })
+            .collect();
+
+        Self {
+            hosts,
+            next: AtomicUsize::new(0),
+            reprobe_after: DEFAULT_REPROBE_AFTER,
+        }
+    }
+
+    /// The hosts described by the configuration
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(&config.ollama_hosts(), config.max_timeout_seconds)
+            .with_retry_policy(config.ollama_retry_policy())
+            .with_reprobe_after(Duration::from_secs(config.ollama_host_reprobe_seconds))
+    }
+
+    /// Probe an unhealthy host again once `reprobe_after` has passed since it failed
+    pub fn with_reprobe_after(mut self, reprobe_after: Duration) -> Self {
+        self.reprobe_after = reprobe_after;
+        self
+    }
+
+    /// Have every host's client retry an unreachable or busy server per
+    /// `policy` before the pool fails over to the next host
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        for host in &mut self.hosts {
+            host.client = host.client.clone().with_retry_policy(policy);
+        }
+        self
+    }
+
+    /// Current health of every host, in configuration order
+    pub fn status(&self) -> Vec<HostStatus> {
+        self.hosts.iter().map(|host| host.status.lock().unwrap().clone()).collect()
+    }
+
+    /// Hosts to try for the next request: unhealthy hosts due for a probe,
+    /// healthy hosts in round-robin order, then the remaining unhealthy ones
+    fn attempt_order(&self) -> Vec<&Host> {
+        if self.hosts.is_empty() {
+            return Vec::new();
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % self.hosts.len();
+        let rotated = self.hosts[start..].iter().chain(&self.hosts[..start]);
+        let (healthy, unhealthy): (Vec<&Host>, Vec<&Host>) =
+            rotated.partition(|host| host.status.lock().unwrap().healthy);
+        let (reprobe, unhealthy): (Vec<&Host>, Vec<&Host>) =
+            unhealthy.into_iter().partition(|host| host.due_for_reprobe(self.reprobe_after));
+        reprobe.into_iter().chain(healthy).chain(unhealthy).collect()
+    }
+
+    /// Run `call` against each host in turn until one succeeds, failing over
+    /// only on host failures
+    async fn with_failover<T, F, Fut>(&self, call: F) -> Result<T>
+    where
+        F: Fn(OllamaClient) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.with_failover_while(call, is_host_failure).await
+    }
+
+    /// Run `call` against each host in turn until one succeeds or fails with
+    /// an error `fail_over` rejects. A host failure marks the host unhealthy;
+    /// any answer from it, even a refusal, shows it is up.
+    async fn with_failover_while<T, F, Fut>(&self, call: F, fail_over: impl Fn(&anyhow::Error) -> bool) -> Result<T>
+    where
+        F: Fn(OllamaClient) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut last_error = None;
+        for host in self.attempt_order() {
+            match call(host.client.clone()).await {
+                Ok(value) => {
+                    host.record_success();
+                    return Ok(value);
+                }
+                Err(e) => {
+                    if is_host_failure(&e) {
+                        host.record_failure(&e);
+                    } else {
+                        host.record_success();
+                    }
+                    if !fail_over(&e) {
+                        return Err(e);
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow!("No Ollama hosts configured")))
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaHostPool {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    async fn generate(&self, model: &str, prompt: &str) -> Result<String> {
+        self.with_failover(|client| async move { client.generate_optimized(model, prompt).await })
+            .await
+    }
+
+    async fn generate_with_options(&self, model: &str, prompt: &str, options: &GenerationOptions) -> Result<Generation> {
+        self.with_failover(|client| async move { client.generate_detailed(model, prompt, options).await })
+            .await
+    }
+
+    async fn generate_streaming(&self, model: &str, prompt: &str, chunks: &UnboundedSender<String>) -> Result<String> {
+        self.generate_streaming_with_options(model, prompt, &GenerationOptions::default(), chunks)
+            .await
+            .map(|generation| generation.text)
+    }
+
+    async fn generate_streaming_with_options(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &GenerationOptions,
+        chunks: &UnboundedSender<String>,
+    ) -> Result<Generation> {
+        // A host that fails partway through the reply is not failed over:
+        // the next host would stream the reply again after the part already sent
+        let streamed = AtomicBool::new(false);
+        let streamed = &streamed;
+        self.with_failover_while(
+            |client| async move {
+                let (sender, mut receiver) = mpsc::unbounded_channel();
+                let generation = async move { client.generate_chunked(model, prompt, options, &sender).await };
+                let relay = async {
+                    while let Some(chunk) = receiver.recv().await {
+                        streamed.store(true, Ordering::SeqCst);
+                        let _ = chunks.send(chunk);
+                    }
+                };
+                tokio::join!(generation, relay).0
+            },
+            |error| is_host_failure(error) && !streamed.load(Ordering::SeqCst),
+        )
+        .await
+    }
+
+    async fn chat(&self, model: &str, messages: &[ConversationMessage]) -> Result<String> {
+        self.with_failover(|client| async move { client.chat_with_model(model, messages.to_vec(), 0.7, 512).await })
+            .await
+    }
+
+    async fn embed(&self, model: &str, input: &str) -> Result<Vec<f32>> {
+        self.with_failover(|client| async move { client.embed(model, input).await })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{mock_ollama_server, serve};
+    use axum::{routing::{get, post}, Json, Router};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    /// A URL nothing is listening on
+    async fn down_host() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        url
+    }
+
+    #[tokio::test]
+    async fn test_requests_fail_over_to_the_healthy_host() {
+        let down = down_host().await;
+        let (healthy, calls) = mock_ollama_server("Inventory is balanced").await;
+        let pool = OllamaHostPool::new(&[down.clone(), healthy.clone()], 5);
+
+        for _ in 0..3 {
+            assert_eq!(pool.generate("llama3", "Summarize").await.unwrap(), "Inventory is balanced");
+        }
+        assert_eq!(calls.lock().unwrap().len(), 3);
+
+        let status = pool.status();
+        assert_eq!(status[0].url, down);
+        assert!(!status[0].healthy);
+        // Once marked down the host is skipped rather than retried on every request
+        assert_eq!(status[0].consecutive_failures, 1);
+        assert!(status[0].last_error.is_some());
+        assert!(status[1].healthy);
+    }
+
+    /// Serve a fake Ollama at `addr` answering every generate call with `status` and `body`
+    async fn serve_generate(addr: &str, status: u16, body: serde_json::Value) -> Arc<AtomicUsize> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let app = Router::new()
+            .route("/api/tags", get(|| async { Json(json!({"models": []})) }))
+            .route("/api/generate", post(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let status = axum::http::StatusCode::from_u16(status).unwrap();
+                let body = body.clone();
+                async move { (status, format!("{}\n", body)) }
+            }));
+        let listener = tokio::net::TcpListener::bind(addr.trim_start_matches("http://")).await.unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        calls
+    }
+
+    #[tokio::test]
+    async fn test_refused_requests_do_not_fail_over() {
+        let refusing = format!("http://{}", down_host().await.trim_start_matches("http://"));
+        let refused = serve_generate(&refusing, 404, json!({"error": "model 'llama9' not found"})).await;
+        let (healthy, calls) = mock_ollama_server("unused").await;
+        let pool = OllamaHostPool::new(&[refusing, healthy], 5).with_retry_policy(RetryPolicy::disabled());
+
+        let error = pool.generate("llama9", "Summarize").await.unwrap_err();
+        assert!(error.to_string().contains("404"), "{}", error);
+        assert!(refused.load(Ordering::SeqCst) >= 1);
+        assert_eq!(calls.lock().unwrap().len(), 0);
+        // The host answered, so it stays healthy
+        assert!(pool.status()[0].healthy);
+    }
+
+    #[tokio::test]
+    async fn test_unhealthy_host_is_probed_again_and_recovers() {
+        let down = down_host().await;
+        let (healthy, _) = mock_ollama_server("Inventory is balanced").await;
+        let pool = OllamaHostPool::new(&[down.clone(), healthy], 5)
+            .with_retry_policy(RetryPolicy::disabled())
+            .with_reprobe_after(Duration::ZERO);
+
+        pool.generate("llama3", "Summarize").await.unwrap();
+        assert!(!pool.status()[0].healthy);
+
+        // Once the host is back, the next request probes it ahead of the healthy host
+        let recovered = serve_generate(&down, 200, json!({"response": "Back up", "done": true})).await;
+        for _ in 0..2 {
+            pool.generate("llama3", "Summarize").await.unwrap();
+        }
+        assert!(recovered.load(Ordering::SeqCst) >= 1);
+        assert!(pool.status()[0].healthy);
+    }
+
+    #[tokio::test]
+    async fn test_stream_broken_partway_is_not_replayed_on_another_host() {
+        // Sends one token, then drops the connection
+        let app = Router::new().route("/api/generate", post(|| async {
+            use futures_util::StreamExt;
+            let lines = futures_util::stream::iter(vec![
+                Ok(format!("{}\n", json!({"response": "Half ", "done": false}))),
+                Err(std::io::Error::other("connection lost")),
+            ])
+            .then(|line| async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                line
+            });
+            axum::body::Body::from_stream(lines)
+        })).route("/api/tags", get(|| async { Json(json!({"models": []})) }));
+        let broken = serve(app).await;
+        let (healthy, calls) = mock_ollama_server("Whole reply").await;
+        let pool = OllamaHostPool::new(&[broken, healthy], 5).with_retry_policy(RetryPolicy::disabled());
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let outcome = pool.generate_streaming("llama3", "Summarize", &sender).await;
+        assert!(outcome.is_err(), "{:?}", outcome);
+        drop(sender);
+
+        let mut received = Vec::new();
+        while let Some(chunk) = receiver.recv().await {
+            received.push(chunk);
+        }
+        assert_eq!(received, vec!["Half ".to_string()]);
+        assert_eq!(calls.lock().unwrap().len(), 0);
+        assert!(!pool.status()[0].healthy);
+    }
+
+    #[tokio::test]
+    async fn test_healthy_hosts_share_requests_round_robin() {
+        let (first, first_calls) = mock_ollama_server("a").await;
+        let (second, second_calls) = mock_ollama_server("b").await;
+        let pool = OllamaHostPool::new(&[first, second], 5);
+
+        for _ in 0..4 {
+            pool.generate("llama3", "Summarize").await.unwrap();
+        }
+        assert_eq!(first_calls.lock().unwrap().len(), 2);
+        assert_eq!(second_calls.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,398 @@
+//! Model backends behind a common interface, so a model can be served by
+//! Ollama or by any OpenAI-compatible endpoint (Azure OpenAI, vLLM, ...)
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::ollama::conversation_manager::{ConversationMessage, MessageRole};
+use crate::ollama::{Config, Generation, GenerationOptions, OllamaClient, OllamaHostPool};
+
+/// A backend able to run completions, chats and embeddings
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Short name used in logs
+    fn name(&self) -> &'static str;
+
+    /// Complete a single prompt
+    async fn generate(&self, model: &str, prompt: &str) -> Result<String>;
+
+    /// Complete a single prompt with `options` (e.g. stop sequences) applied,
+    /// reporting why generation ended when the backend says. Providers
+    /// without such settings ignore them.
+    async fn generate_with_options(&self, model: &str, prompt: &str, _options: &GenerationOptions) -> Result<Generation> {
+        let text = self.generate(model, prompt).await?;
+        Ok(Generation { text, stop_reason: None })
+    }
+
+    /// Complete a single prompt, sending each piece of the reply to `chunks` as
+    /// it is produced. Providers that cannot stream send the reply as one chunk.
+    async fn generate_streaming(&self, model: &str, prompt: &str, chunks: &UnboundedSender<String>) -> Result<String> {
+        let reply = self.generate(model, prompt).await?;
+        let _ = chunks.send(reply.clone());
+        Ok(reply)
+    }
+
+    /// Stream like `generate_streaming` with `options` applied, reporting why
+    /// generation ended when the backend says. Providers without such
+    /// settings ignore them.
+    async fn generate_streaming_with_options(
+        &self,
+        model: &str,
+        prompt: &str,
+        _options: &GenerationOptions,
+        chunks: &UnboundedSender<String>,
+    ) -> Result<Generation> {
+        let text = self.generate_streaming(model, prompt, chunks).await?;
+        Ok(Generation { text, stop_reason: None })
+    }
+
+    /// Continue a conversation, returning the assistant's reply
+    async fn chat(&self, model: &str, messages: &[ConversationMessage]) -> Result<String>;
+
+    /// Embed `input` into a vector
+    async fn embed(&self, model: &str, input: &str) -> Result<Vec<f32>>;
+}
+
+/// The default provider, backed by the Ollama HTTP API
+pub struct OllamaProvider {
+    client: OllamaClient,
+}
+
+impl OllamaProvider {
+    pub fn new(client: OllamaClient) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    async fn generate(&self, model: &str, prompt: &str) -> Result<String> {
+        self.client.generate_optimized(model, prompt).await
+    }
+
+    async fn generate_with_options(&self, model: &str, prompt: &str, options: &GenerationOptions) -> Result<Generation> {
+        self.client.generate_detailed(model, prompt, options).await
+    }
+
+    async fn generate_streaming(&self, model: &str, prompt: &str, chunks: &UnboundedSender<String>) -> Result<String> {
+        self.generate_streaming_with_options(model, prompt, &GenerationOptions::default(), chunks)
+            .await
+            .map(|generation| generation.text)
+    }
+
+    async fn generate_streaming_with_options(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &GenerationOptions,
+        chunks: &UnboundedSender<String>,
+    ) -> Result<Generation> {
+        self.client.generate_chunked(model, prompt, options, chunks).await
+    }
+
+    async fn chat(&self, model: &str, messages: &[ConversationMessage]) -> Result<String> {
+        self.client.chat_with_model(model, messages.to_vec(), 0.7, 512).await
+    }
+
+    async fn embed(&self, model: &str, input: &str) -> Result<Vec<f32>> {
+        self.client.embed(model, input).await
+    }
+}
+
+/// A provider speaking the OpenAI `/v1/chat/completions` and `/v1/embeddings` API
+pub struct OpenAiCompatProvider {
+    client: Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl OpenAiCompatProvider {
+    pub fn new(base_url: &str, api_key: Option<String>, timeout_seconds: u64) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(timeout_seconds))
+            .build()
+            .expect("Failed to create HTTP client");
+
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+        }
+    }
+
+    async fn post(&self, path: &str, body: Value) -> Result<Value> {
+        let mut request = self.client.post(format!("{}{}", self.base_url, path)).json(&body);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(anyhow!("OpenAI-compatible API error ({}): {}", status, error_text));
+        }
+        Ok(response.json().await?)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiCompatProvider {
+    fn name(&self) -> &'static str {
+        "openai_compat"
+    }
+
+    async fn generate(&self, model: &str, prompt: &str) -> Result<String> {
+        let messages = [ConversationMessage {
+            role: MessageRole::User,
+            content: prompt.to_string(),
+        }];
+        self.chat(model, &messages).await
+    }
+
+    async fn chat(&self, model: &str, messages: &[ConversationMessage]) -> Result<String> {
+        let body = json!({
+            "model": model,
+            "messages": messages,
+        });
+        let response = self.post("/v1/chat/completions", body).await?;
+
+        response["choices"][0]["message"]["content"]
+            .as_str()
+            .map(|content| content.to_string())
+            .ok_or_else(|| anyhow!("Invalid response format from OpenAI-compatible chat API"))
+    }
+
+    async fn embed(&self, model: &str, input: &str) -> Result<Vec<f32>> {
+        let body = json!({
+            "model": model,
+            "input": input,
+        });
+        let response = self.post("/v1/embeddings", body).await?;
+
+        response["data"][0]["embedding"]
+            .as_array()
+            .map(|values| values.iter().filter_map(|v| v.as_f64()).map(|v| v as f32).collect())
+            .ok_or_else(|| anyhow!("Invalid response format from OpenAI-compatible embeddings API"))
+    }
+}
+
+/// Picks the provider serving each model: models matching a registered
+/// prefix go to that provider, everything else to the default
+#[derive(Clone)]
+pub struct ProviderRegistry {
+    default: Arc<dyn LlmProvider>,
+    routes: Vec<(String, Arc<dyn LlmProvider>)>,
+}
+
+impl ProviderRegistry {
+    pub fn new(default: Arc<dyn LlmProvider>) -> Self {
+        Self {
+            default,
+            routes: Vec::new(),
+        }
+    }
+
+    /// Build the registry described by the configuration
+    pub fn from_config(config: &Config) -> Self {
+        Self::with_ollama_hosts(config, Arc::new(OllamaHostPool::from_config(config)))
+    }
+
+    /// Build the registry described by the configuration, serving Ollama
+    /// models from an existing host pool so its health tracking carries over
+    pub fn with_ollama_hosts(config: &Config, hosts: Arc<OllamaHostPool>) -> Self {
+        let mut registry = Self::new(hosts);
+
+        if let Some(base_url) = &config.openai_compat_base_url {
+            let provider: Arc<dyn LlmProvider> = Arc::new(OpenAiCompatProvider::new(
+                base_url,
+                config.openai_compat_api_key.clone(),
+                config.max_timeout_seconds,
+            ));
+            for prefix in &config.openai_compat_models {
+                registry = registry.route(prefix, provider.clone());
+            }
+        }
+
+        registry
+    }
+
+    /// Serve models whose name starts with `prefix` from `provider`
+    pub fn route(mut self, prefix: &str, provider: Arc<dyn LlmProvider>) -> Self {
+        self.routes.push((prefix.to_string(), provider));
+        self
+    }
+
+    /// The provider for a model, preferring the longest matching prefix
+    pub fn for_model(&self, model: &str) -> Arc<dyn LlmProvider> {
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, provider)| provider.clone())
+            .unwrap_or_else(|| self.default.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama::StopReason;
+    use crate::test_support::{serve, ReceivedRequests};
+    use axum::{http::HeaderMap, routing::post, Json, Router};
+    use std::sync::Mutex;
+
+    /// Serve a fake OpenAI-compatible API, recording each request's auth header and body
+    async fn mock_openai() -> (String, Arc<Mutex<Vec<(Option<String>, Value)>>>) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let store = seen.clone();
+        let app = Router::new().route(
+            "/v1/chat/completions",
+            post(move |headers: HeaderMap, Json(body): Json<Value>| {
+                let store = store.clone();
+                async move {
+                    let auth = headers
+                        .get("authorization")
+                        .and_then(|v| v.to_str().ok())
+                        .map(|v| v.to_string());
+                    store.lock().unwrap().push((auth, body));
+                    Json(json!({
+                        "id": "chatcmpl-1",
+                        "object": "chat.completion",
+                        "choices": [{
+                            "index": 0,
+                            "message": {"role": "assistant", "content": "Revenue is trending up."},
+                            "finish_reason": "stop"
+                        }]
+                    }))
+                }
+            }),
+        );
+
+
+        (serve(app).await, seen)
+    }
+
+    #[tokio::test]
+    async fn test_openai_compat_request_shape_and_response() {
+        let (base_url, seen) = mock_openai().await;
+        let provider = OpenAiCompatProvider::new(&base_url, Some("test-key".to_string()), 5);
+
+        let reply = provider.generate("gpt-4o-mini", "Summarize the data").await.unwrap();
+        assert_eq!(reply, "Revenue is trending up.");
+
+        let seen = seen.lock().unwrap();
+        let (auth, body) = &seen[0];
+        assert_eq!(auth.as_deref(), Some("Bearer test-key"));
+        assert_eq!(body["model"], "gpt-4o-mini");
+        assert_eq!(body["messages"], json!([{"role": "user", "content": "Summarize the data"}]));
+    }
+
+    #[test]
+    fn test_registry_routes_models_by_prefix() {
+        let ollama: Arc<dyn LlmProvider> =
+            Arc::new(OllamaProvider::new(OllamaClient::new("http://localhost:11434", 5)));
+        let openai: Arc<dyn LlmProvider> = Arc::new(OpenAiCompatProvider::new("http://localhost:8000", None, 5));
+        let registry = ProviderRegistry::new(ollama).route("gpt-", openai);
+
+        assert_eq!(registry.for_model("gpt-4o").name(), "openai_compat");
+        assert_eq!(registry.for_model("llama3").name(), "ollama");
+    }
+
+    #[tokio::test]
+    async fn test_ollama_streaming_forwards_each_chunk() {
+        let app = Router::new()
+            .route("/api/tags", axum::routing::get(|| async { Json(json!({"models": []})) }))
+            .route("/api/generate", post(|| async {
+                ["Revenue ", "is ", "up."]
+                    .iter()
+                    .map(|chunk| format!("{}\n", json!({"response": chunk, "done": false})))
+                    .chain(std::iter::once(format!("{}\n", json!({"response": "", "done": true}))))
+                    .collect::<String>()
+            }));
+
+        let provider = OllamaProvider::new(OllamaClient::new(&serve(app).await, 5));
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let reply = provider.generate_streaming("llama3", "Summarize", &sender).await.unwrap();
+        drop(sender);
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = receiver.recv().await {
+            chunks.push(chunk);
+        }
+        assert_eq!(chunks, ["Revenue ", "is ", "up."]);
+        assert_eq!(reply, "Revenue is up.");
+    }
+
+    #[tokio::test]
+    async fn test_ollama_streaming_applies_options_and_reports_the_stop_reason() {
+        let seen: ReceivedRequests = Arc::default();
+        let store = seen.clone();
+        let app = Router::new()
+            .route("/api/tags", axum::routing::get(|| async { Json(json!({"models": []})) }))
+            .route("/api/generate", post(move |Json(body): Json<Value>| {
+                store.lock().unwrap().push(body);
+                async {
+                    format!(
+                        "{}\n{}\n",
+                        json!({"response": "Revenue is up", "done": false}),
+                        json!({"response": "", "done": true, "done_reason": "stop"})
+                    )
+                }
+            }));
+
+        let provider = OllamaProvider::new(OllamaClient::new(&serve(app).await, 5));
+        let options = GenerationOptions::default().with_stop(&["END OF REPORT".to_string()]);
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let generation = provider
+            .generate_streaming_with_options("llama3", "Summarize", &options, &sender)
+            .await
+            .unwrap();
+        drop(sender);
+
+        assert_eq!(receiver.recv().await.as_deref(), Some("Revenue is up"));
+        assert_eq!(generation.text, "Revenue is up");
+        assert_eq!(generation.stop_reason, Some(StopReason::Stop));
+        let body = seen.lock().unwrap()[0].clone();
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["options"]["stop"], json!(["END OF REPORT"]));
+    }
+
+    #[tokio::test]
+    async fn test_ollama_html_error_page_is_a_clean_server_error() {
+        let page = format!("<html><head><title>502 Bad Gateway</title></head><body>{}</body></html>", "nginx ".repeat(100));
+        let app = Router::new()
+            .route("/api/tags", axum::routing::get(|| async { Json(json!({"models": []})) }))
+            .route("/api/generate", post(move || {
+                let page = page.clone();
+                async move { (axum::http::StatusCode::BAD_GATEWAY, axum::response::Html(page)) }
+            }));
+        let provider = OllamaProvider::new(OllamaClient::new(&serve(app).await, 5));
+
+        let (sender, _receiver) = tokio::sync::mpsc::unbounded_channel();
+        for error in [
+            provider.generate("llama3", "Summarize").await.unwrap_err(),
+            provider.generate_streaming("llama3", "Summarize", &sender).await.unwrap_err(),
+        ] {
+            match error.downcast_ref::<crate::ollama::OllamaError>() {
+                Some(crate::ollama::OllamaError::Server { status, content_type, snippet }) => {
+                    assert_eq!(*status, 502);
+                    assert!(content_type.as_deref().unwrap().starts_with("text/html"));
+                    assert!(snippet.starts_with("<html><head><title>502 Bad Gateway</title>"));
+                    assert!(snippet.ends_with('…') && snippet.chars().count() == 201);
+                }
+                _ => panic!("expected a Server error, got: {}", error),
+            }
+            assert!(error.to_string().contains("non-JSON response (HTTP 502"));
+        }
+    }
+}
@@ -4,11 +4,17 @@ pub mod ollama_receipt;
 pub mod ai_model_manager;
 pub mod consensus_engine;
 pub mod conversation_manager;
+pub mod model_metadata;
+pub mod llm_provider;
+pub mod host_pool;
 
 
 // Re-export the main types for easier importing
-pub use ollama_client::OllamaClient;
+pub use ollama_client::{Generation, GenerationOptions, OllamaClient, OllamaError, RetryPolicy, StopReason};
 pub use ollama_config::Config;
 pub use ai_model_manager::{AIModelManager, ModelConfig, ModelRole, ConsensusResult};
 pub use consensus_engine::{ConsensusEngine, ConsensusRequest, AnalysisType, UrgencyLevel};
-pub use ollama_receipt::OllamaReceipt;
\ No newline at end of file
+pub use ollama_receipt::OllamaReceipt;
+pub use model_metadata::ModelMetadataTable;
+pub use llm_provider::{LlmProvider, OllamaProvider, OpenAiCompatProvider, ProviderRegistry};
+pub use host_pool::{HostStatus, OllamaHostPool};
\ No newline at end of file
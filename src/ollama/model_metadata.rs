@@ -0,0 +1,265 @@
+//! Per-model metadata used for prompt budgeting and capability checks
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+
+/// Context window assumed for models that match no known family
+const DEFAULT_CONTEXT_WINDOW: usize = 4096;
+
+/// Features a model family supports beyond plain text generation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ModelCapabilities {
+    /// Reliably answers with a single JSON document when asked to
+    pub json_mode: bool,
+    /// Supports tool (function) calling
+    pub tools: bool,
+}
+
+impl ModelCapabilities {
+    /// Capabilities assumed for models that match no known family, so an
+    /// unlisted model is never flagged
+    pub const ALL: Self = Self { json_mode: true, tools: true };
+
+    /// Parse `json_mode+tools`, or `none` for a family supporting neither
+    fn parse(features: &str) -> Option<Self> {
+        let mut capabilities = Self { json_mode: false, tools: false };
+        for feature in features.split('+').map(str::trim) {
+            match feature {
+                "json_mode" => capabilities.json_mode = true,
+                "tools" => capabilities.tools = true,
+                "none" | "" => {}
+                _ => return None,
+            }
+        }
+        Some(capabilities)
+    }
+}
+
+/// Capabilities an analysis needs from its model
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ModelRequirements {
+    pub json_mode: bool,
+    pub tools: bool,
+}
+
+/// What to do when the requested model lacks a capability the analysis needs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CapabilityMismatch {
+    /// Run on the requested model and flag the result
+    #[default]
+    Warn,
+    /// Run on the first fallback model that has the capabilities, flagging
+    /// the result when none does
+    Substitute,
+    /// Refuse the analysis
+    Reject,
+}
+
+/// Metadata for a family of models sharing a name prefix
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelMetadata {
+    pub family: String,
+    pub context_window: usize,
+    #[serde(flatten)]
+    pub capabilities: ModelCapabilities,
+}
+
+/// Table mapping model families to their context window sizes and capabilities
+#[derive(Debug, Clone)]
+pub struct ModelMetadataTable {
+    families: BTreeMap<String, (usize, ModelCapabilities)>,
+    default_context_window: usize,
+}
+
+impl ModelMetadataTable {
+    /// Create a table with defaults for the model families we commonly run
+    pub fn new() -> Self {
+        // (family, context window, JSON mode, tool calling)
+        let families = [
+            ("llama2", 4096, false, false),
+            ("llama3", 8192, true, false),
+            ("llama3.1", 131072, true, true),
+            ("llama3.2", 131072, true, true),
+            ("codellama", 16384, false, false),
+            ("mistral", 32768, true, true),
+            ("mixtral", 32768, true, true),
+            ("qwen2.5", 32768, true, true),
+            ("gemma2", 8192, true, false),
+            ("phi", 2048, false, false),
+            ("phi3", 4096, true, false),
+            ("tinyllama", 2048, false, false),
+        ]
+        .into_iter()
+        .map(|(family, window, json_mode, tools)| {
+            (family.to_string(), (window, ModelCapabilities { json_mode, tools }))
+        })
+        .collect();
+
+        Self {
+            families,
+            default_context_window: DEFAULT_CONTEXT_WINDOW,
+        }
+    }
+
+    /// Load the defaults, applying `MODEL_CONTEXT_WINDOWS` overrides
+    /// (e.g. `llama3:8192,mistral:32768`), `MODEL_CAPABILITIES` overrides
+    /// (e.g. `llama3:json_mode+tools,phi3:none`) and `DEFAULT_CONTEXT_WINDOW`
+    pub fn from_env() -> Self {
+        let mut table = Self::new();
+
+        if let Ok(overrides) = env::var("MODEL_CONTEXT_WINDOWS") {
+            for entry in overrides.split(',') {
+                match entry.trim().rsplit_once(':').map(|(f, w)| (f.trim(), w.trim().parse::<usize>())) {
+                    Some((family, Ok(window))) if !family.is_empty() => {
+                        table = table.with_context_window(family, window);
+                    }
+                    _ => log::warn!("Ignoring invalid MODEL_CONTEXT_WINDOWS entry: {}", entry),
+                }
+            }
+        }
+
+        if let Ok(overrides) = env::var("MODEL_CAPABILITIES") {
+            for entry in overrides.split(',').filter(|entry| !entry.trim().is_empty()) {
+                match entry.trim().split_once(':').map(|(f, c)| (f.trim(), ModelCapabilities::parse(c))) {
+                    Some((family, Some(capabilities))) if !family.is_empty() => {
+                        table = table.with_capabilities(family, capabilities);
+                    }
+                    _ => log::warn!("Ignoring invalid MODEL_CAPABILITIES entry: {}", entry),
+                }
+            }
+        }
+
+        if let Some(window) = env::var("DEFAULT_CONTEXT_WINDOW").ok().and_then(|w| w.parse().ok()) {
+            table.default_context_window = window;
+        }
+
+        table
+    }
+
+    /// Set the context window for a model family
+    pub fn with_context_window(mut self, family: &str, context_window: usize) -> Self {
+        self.families
+            .entry(family.to_lowercase())
+            .and_modify(|(window, _)| *window = context_window)
+            .or_insert((context_window, ModelCapabilities::ALL));
+        self
+    }
+
+    /// Set the capabilities of a model family, adding it with the default
+    /// context window if unknown
+    pub fn with_capabilities(mut self, family: &str, capabilities: ModelCapabilities) -> Self {
+        let default_context_window = self.default_context_window;
+        self.families
+            .entry(family.to_lowercase())
+            .and_modify(|(_, known)| *known = capabilities)
+            .or_insert((default_context_window, capabilities));
+        self
+    }
+
+    /// Entry of the longest family prefix of a model's name
+    fn family(&self, model: &str) -> Option<&(usize, ModelCapabilities)> {
+        let name = model.to_lowercase();
+        let name = name.rsplit('/').next().unwrap_or(&name);
+
+        self.families
+            .iter()
+            .filter(|(family, _)| name.starts_with(family.as_str()))
+            .max_by_key(|(family, _)| family.len())
+            .map(|(_, entry)| entry)
+    }
+
+    /// Context window for a model, matched by the longest family prefix of its name
+    pub fn context_window(&self, model: &str) -> usize {
+        self.family(model).map(|(window, _)| *window).unwrap_or(self.default_context_window)
+    }
+
+    /// Capabilities of a model, matched like `context_window`
+    pub fn capabilities(&self, model: &str) -> ModelCapabilities {
+        self.family(model).map(|(_, capabilities)| *capabilities).unwrap_or(ModelCapabilities::ALL)
+    }
+
+    /// Capabilities in `requirements` the model lacks, by name
+    pub fn unmet_requirements(&self, model: &str, requirements: ModelRequirements) -> Vec<&'static str> {
+        let capabilities = self.capabilities(model);
+        let mut unmet = Vec::new();
+        if requirements.json_mode && !capabilities.json_mode {
+            unmet.push("json_mode");
+        }
+        if requirements.tools && !capabilities.tools {
+            unmet.push("tools");
+        }
+        unmet
+    }
+
+    /// Context window used for unknown models
+    pub fn default_context_window(&self) -> usize {
+        self.default_context_window
+    }
+
+    /// All known model families
+    pub fn entries(&self) -> Vec<ModelMetadata> {
+        self.families
+            .iter()
+            .map(|(family, (window, capabilities))| ModelMetadata {
+                family: family.clone(),
+                context_window: *window,
+                capabilities: *capabilities,
+            })
+            .collect()
+    }
+}
+
+impl Default for ModelMetadataTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Rough token estimate (about four characters per token)
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_window_prefers_longest_family() {
+        let table = ModelMetadataTable::new();
+        assert_eq!(table.context_window("llama3.1:8b"), 131072);
+        assert_eq!(table.context_window("llama3:latest"), 8192);
+        assert_eq!(table.context_window("tinyllama"), 2048);
+        assert_eq!(table.context_window("unknown-model"), DEFAULT_CONTEXT_WINDOW);
+    }
+
+    #[test]
+    fn test_override_context_window() {
+        let table = ModelMetadataTable::new().with_context_window("llama2", 8192);
+        assert_eq!(table.context_window("llama2:13b"), 8192);
+        assert!(!table.capabilities("llama2:13b").json_mode);
+    }
+
+    #[test]
+    fn test_json_requirement_flags_model_without_json_mode() {
+        let table = ModelMetadataTable::new();
+        let json = ModelRequirements { json_mode: true, ..ModelRequirements::default() };
+
+        assert_eq!(table.unmet_requirements("llama2:7b", json), vec!["json_mode"]);
+        assert!(table.unmet_requirements("llama3.1:8b", json).is_empty());
+        assert!(table.unmet_requirements("llama2:7b", ModelRequirements::default()).is_empty());
+        // Unknown models are given the benefit of the doubt
+        assert!(table.unmet_requirements("unknown-model", json).is_empty());
+
+        let table = table.with_capabilities("llama2", ModelCapabilities { json_mode: true, tools: false });
+        assert!(table.unmet_requirements("llama2:7b", json).is_empty());
+        assert_eq!(
+            ModelCapabilities::parse("json_mode+tools"),
+            Some(ModelCapabilities { json_mode: true, tools: true })
+        );
+        assert_eq!(ModelCapabilities::parse("none"), Some(ModelCapabilities { json_mode: false, tools: false }));
+        assert_eq!(ModelCapabilities::parse("vision"), None);
+    }
+}
@@ -1,5 +1,6 @@
 use anyhow::{anyhow, Result};
 use reqwest::Client;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
@@ -15,6 +16,211 @@ const REQUEST_TIMEOUT: u64 = 180;  // Reduced to prevent long timeouts
 const KEEP_ALIVE_DURATION: u64 = 60;  // Reduced for better connection management
 const MAX_IDLE_PER_HOST: usize = 5;  // Reduced to prevent memory issues
 
+/// Most characters of a non-JSON body quoted in an error
+const ERROR_SNIPPET_CHARS: usize = 200;
+
+/// How generation calls are retried while Ollama is unreachable or busy
+/// (e.g. answering 503 as it loads a model). Other failures, such as a 404
+/// for an unknown model, are never retried.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Calls made in total, counting the first; 1 disables retrying
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubling before each one after
+    pub base_delay: Duration,
+    /// Longest wait between attempts
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Make every call exactly once
+    pub fn disabled() -> Self {
+        Self { max_attempts: 1, ..Self::default() }
+    }
+
+    /// Wait before retry number `retry` (from 1): the doubled base delay,
+    /// capped, with up to half of it taken off at random so clients that
+    /// failed together do not retry together
+    fn delay(&self, retry: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_delay);
+        let jitter = (uuid::Uuid::new_v4().as_u128() % 1000) as u32;
+        backoff - backoff / 2 * jitter / 1000
+    }
+}
+
+/// Failures talking to Ollama that callers may want to tell apart from
+/// ordinary API errors
+#[derive(Debug, Clone, PartialEq)]
+pub enum OllamaError {
+    /// The server, or a proxy in front of it, answered with something other
+    /// than JSON, such as an HTML error page
+    Server {
+        status: u16,
+        content_type: Option<String>,
+        /// Start of the body, whitespace collapsed
+        snippet: String,
+    },
+    /// The request could not be sent or the response could not be read
+    Request(String),
+    /// A streamed line was not a generate chunk
+    Decode(String),
+    /// Ollama reported an error partway through a stream
+    Generation(String),
+    /// Ollama could not be reached, or answered 503 while busy; worth retrying
+    Unavailable(String),
+    /// Ollama answered an API call with an error status and message, such as
+    /// a 404 for an unknown model
+    Api { api: String, status: u16, message: String },
+}
+
+impl std::fmt::Display for OllamaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OllamaError::Server { status, content_type, snippet } => write!(
+                f,
+                "Ollama server returned a non-JSON response (HTTP {}, {}): {}",
+                status,
+                content_type.as_deref().unwrap_or("no content type"),
+                snippet
+            ),
+            OllamaError::Request(message) => write!(f, "Ollama request failed: {}", message),
+            OllamaError::Decode(message) => write!(f, "Failed to parse Ollama stream: {}", message),
+            OllamaError::Generation(message) => write!(f, "Ollama returned error: {}", message),
+            OllamaError::Unavailable(message) => write!(f, "Ollama is unavailable: {}", message),
+            OllamaError::Api { api, status, message } => {
+                // Shown with its reason phrase, e.g. "404 Not Found"
+                let status = reqwest::StatusCode::from_u16(*status).map_or_else(|_| status.to_string(), |s| s.to_string());
+                write!(f, "Ollama {} API error ({}): {}", api, status, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OllamaError {}
+
+impl OllamaError {
+    /// A `Server` error when `body` is not JSON: it is declared HTML, or does
+    /// not open with `{` or `[` (which also covers newline-delimited streams)
+    fn non_json(status: u16, content_type: Option<&str>, body: &str) -> Option<Self> {
+        let declared_html = content_type.is_some_and(|t| t.to_ascii_lowercase().contains("html"));
+        if !declared_html && body.trim_start().starts_with(['{', '[']) {
+            return None;
+        }
+
+        let collapsed = body.split_whitespace().collect::<Vec<_>>().join(" ");
+        let mut snippet: String = collapsed.chars().take(ERROR_SNIPPET_CHARS).collect();
+        if snippet.len() < collapsed.len() {
+            snippet.push('…');
+        }
+        Some(OllamaError::Server {
+            status,
+            content_type: content_type.map(str::to_string),
+            snippet,
+        })
+    }
+
+    /// Whether the same call may succeed if made again shortly
+    fn is_transient(&self) -> bool {
+        matches!(self, OllamaError::Unavailable(_) | OllamaError::Server { status: 503, .. })
+    }
+
+    /// Whether the server answered and refused the call itself (an API error
+    /// status or a model error), so another host would refuse it too
+    pub fn is_request_error(&self) -> bool {
+        matches!(self, OllamaError::Api { .. } | OllamaError::Generation(_))
+    }
+
+    /// Keep an `OllamaError` raised under `anyhow` as is; anything else was
+    /// a failure of the request itself
+    fn from_anyhow(error: anyhow::Error) -> Self {
+        error
+            .downcast::<OllamaError>()
+            .unwrap_or_else(|error| OllamaError::Request(error.to_string()))
+    }
+}
+
+/// Status, content type and body of a response, read in full
+async fn read_body(response: reqwest::Response) -> Result<(reqwest::StatusCode, Option<String>, String)> {
+    let status = response.status();
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let body = response.text().await.map_err(|e| anyhow!("Failed to read Ollama response: {}", e))?;
+    Ok((status, content_type, body))
+}
+
+/// Decode a JSON API response. Non-JSON bodies become `OllamaError::Server`
+/// and error statuses report Ollama's `error` message, so neither surfaces
+/// as a confusing decode error.
+async fn read_json<T: DeserializeOwned>(response: reqwest::Response, api: &str) -> Result<T> {
+    let (status, content_type, body) = read_body(response).await?;
+    if !status.is_success() || OllamaError::non_json(status.as_u16(), content_type.as_deref(), &body).is_some() {
+        return Err(response_error(api, status, content_type.as_deref(), body));
+    }
+    serde_json::from_str(&body).map_err(|e| anyhow!("Failed to parse Ollama {} response: {}", api, e))
+}
+
+/// The error for an error status or non-JSON body
+fn response_error(api: &str, status: reqwest::StatusCode, content_type: Option<&str>, body: String) -> anyhow::Error {
+    if let Some(error) = OllamaError::non_json(status.as_u16(), content_type, &body) {
+        return error.into();
+    }
+    let message = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|value| value["error"].as_str().map(str::to_string))
+        .unwrap_or(body);
+    if status == reqwest::StatusCode::SERVICE_UNAVAILABLE {
+        return OllamaError::Unavailable(format!("{} API returned {}: {}", api, status, message)).into();
+    }
+    OllamaError::Api { api: api.to_string(), status: status.as_u16(), message }.into()
+}
+
+/// The error for a request that could not be sent; refused connections are
+/// `OllamaError::Unavailable`
+fn send_error(error: reqwest::Error) -> anyhow::Error {
+    if error.is_connect() {
+        OllamaError::Unavailable(format!("request failed: {}", error)).into()
+    } else {
+        anyhow!("Request failed: {}", error)
+    }
+}
+
+/// Whether an error is a transient `OllamaError`
+fn is_transient(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<OllamaError>().is_some_and(OllamaError::is_transient)
+}
+
+/// Let a streaming response through only if it is a successful stream of
+/// JSON lines, judged by status and content type since the body is read
+/// incrementally
+async fn check_stream(response: reqwest::Response) -> Result<reqwest::Response> {
+    let declared_html = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|t| t.to_ascii_lowercase().contains("html"));
+    if response.status().is_success() && !declared_html {
+        return Ok(response);
+    }
+    let (status, content_type, body) = read_body(response).await?;
+    Err(response_error("generate", status, content_type.as_deref(), body))
+}
+
 #[derive(Debug, Serialize)]
 struct GenerateRequest {
     model: String,
@@ -41,6 +247,114 @@ struct GenerateOptions {
     mirostat: i32,            // Use mirostat for consistent quality
     mirostat_eta: f32,        // Learning rate for mirostat
     mirostat_tau: f32,        // Target entropy for mirostat
+
+    // Caller-requested sampling
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+}
+
+impl GenerateOptions {
+    /// These options with every setting `overrides` specifies replacing ours
+    fn with_overrides(mut self, overrides: &GenerationOptions) -> Self {
+        if let Some(temperature) = overrides.temperature {
+            self.temperature = temperature;
+        }
+        if let Some(top_p) = overrides.top_p {
+            self.top_p = top_p;
+        }
+        if let Some(top_k) = overrides.top_k {
+            self.top_k = top_k;
+        }
+        if let Some(num_predict) = overrides.num_predict {
+            self.num_predict = num_predict;
+        }
+        self.seed = overrides.seed.or(self.seed);
+        if !overrides.stop.is_empty() {
+            self.stop = overrides.stop.clone();
+        }
+        self
+    }
+}
+
+/// Sampling settings a caller can pass to `generate_with_options`; anything
+/// left unset keeps the client's own tuning
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<i32>,
+    /// Most tokens to generate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<i32>,
+    /// Fixed sampling seed, making runs with the same prompt repeatable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    /// Sequences that end generation when produced
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+}
+
+impl GenerationOptions {
+    /// Greedy sampling with a fixed seed, for repeatable output such as risk
+    /// assessments
+    pub fn deterministic(seed: i64) -> Self {
+        Self {
+            temperature: Some(0.0),
+            top_k: Some(1),
+            seed: Some(seed),
+            ..Self::default()
+        }
+    }
+
+    /// These options with `stop` added to their stop sequences
+    pub fn with_stop(mut self, stop: &[String]) -> Self {
+        for sequence in stop {
+            if !self.stop.contains(sequence) {
+                self.stop.push(sequence.clone());
+            }
+        }
+        self
+    }
+}
+
+/// Why a generation ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// The model either ended its reply by itself or produced one of the
+    /// request's stop sequences; Ollama reports both alike
+    Stop,
+    /// The token limit (`num_predict`) was reached
+    Length,
+    /// The model ended its reply by itself, no stop sequences being set
+    Natural,
+}
+
+impl StopReason {
+    /// Classify Ollama's `done_reason`. Ollama reports a matched stop
+    /// sequence and the model's own end alike as `stop`, so when stop
+    /// sequences were set, which one it was is unknown.
+    fn from_done_reason(done_reason: Option<&str>, stop: &[String]) -> Option<Self> {
+        match done_reason? {
+            "length" => Some(StopReason::Length),
+            "stop" if !stop.is_empty() => Some(StopReason::Stop),
+            "stop" => Some(StopReason::Natural),
+            _ => None,
+        }
+    }
+}
+
+/// A completed generation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generation {
+    pub text: String,
+    /// Why generation ended, when the backend said
+    pub stop_reason: Option<StopReason>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -48,13 +362,134 @@ struct GenerateResponse {
     response: String,
     #[serde(default)]
     error: Option<String>,
+    #[serde(default)]
+    done_reason: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct StreamResponse {
+    #[serde(default)]
     response: String,
     #[serde(default)]
     error: Option<String>,
+    #[serde(default)]
+    done: bool,
+    /// Set on the final chunk: `stop` or `length`
+    #[serde(default)]
+    done_reason: Option<String>,
+}
+
+/// Longest NDJSON line a stream may buffer before it is treated as broken
+const MAX_STREAM_LINE_BYTES: usize = 1024 * 1024;
+
+/// Where a `generate_stream` call has got to
+struct TokenStream {
+    client: OllamaClient,
+    request: GenerateRequest,
+    response: Option<reqwest::Response>,
+    _permit: Option<tokio::sync::OwnedSemaphorePermit>,
+    /// Bytes of a line not yet terminated by a newline
+    pending: Vec<u8>,
+    /// Tokens parsed but not yet yielded
+    tokens: std::collections::VecDeque<String>,
+    /// An error to yield once the tokens before it are out
+    error: Option<OllamaError>,
+    /// Ollama's `done_reason` from the final chunk
+    done_reason: Option<String>,
+    first_chunk: bool,
+    done: bool,
+}
+
+impl TokenStream {
+    /// The next token, `None` once Ollama has sent `done: true` or closed
+    /// the body
+    async fn next_token(&mut self) -> Option<Result<String, OllamaError>> {
+        loop {
+            if let Some(token) = self.tokens.pop_front() {
+                return Some(Ok(token));
+            }
+            if let Some(error) = self.error.take() {
+                return Some(Err(error));
+            }
+            if self.done {
+                return None;
+            }
+            // Any error ends the stream after it is yielded
+            if let Err(error) = self.advance().await {
+                self.error = Some(error);
+                self.done = true;
+            }
+        }
+    }
+
+    /// Take a concurrency permit and send the request
+    async fn start(&mut self) -> Result<()> {
+        let permit = self.client.semaphore.clone().acquire_owned().await
+            .map_err(|e| anyhow!("Semaphore error: {}", e))?;
+        self._permit = Some(permit);
+        let response = self.client.client
+            .post(format!("{}/api/generate", self.client.base_url))
+            .json(&self.request)
+            .send()
+            .await
+            .map_err(send_error)?;
+        self.response = Some(check_stream(response).await?);
+        Ok(())
+    }
+
+    /// Send the request, or read the next network chunk into `tokens`
+    async fn advance(&mut self) -> Result<(), OllamaError> {
+        let Some(response) = self.response.as_mut() else {
+            return self.start().await.map_err(OllamaError::from_anyhow);
+        };
+
+        let status = response.status().as_u16();
+        match response.chunk().await.map_err(|e| OllamaError::Request(e.to_string()))? {
+            Some(bytes) => {
+                if std::mem::take(&mut self.first_chunk) {
+                    if let Some(error) = OllamaError::non_json(status, None, &String::from_utf8_lossy(&bytes)) {
+                        return Err(error);
+                    }
+                }
+                self.pending.extend_from_slice(&bytes);
+                while let Some(newline) = self.pending.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = self.pending.drain(..=newline).collect();
+                    self.push_line(&line)?;
+                    if self.done {
+                        return Ok(());
+                    }
+                }
+                if self.pending.len() > MAX_STREAM_LINE_BYTES {
+                    return Err(OllamaError::Decode(format!(
+                        "stream line longer than {} bytes",
+                        MAX_STREAM_LINE_BYTES
+                    )));
+                }
+            }
+            None => {
+                let line = std::mem::take(&mut self.pending);
+                self.push_line(&line)?;
+                self.done = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn push_line(&mut self, line: &[u8]) -> Result<(), OllamaError> {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            return Ok(());
+        }
+        let chunk: StreamResponse = serde_json::from_slice(line).map_err(|e| OllamaError::Decode(e.to_string()))?;
+        if let Some(error) = chunk.error {
+            return Err(OllamaError::Generation(error));
+        }
+        if !chunk.response.is_empty() {
+            self.tokens.push_back(chunk.response);
+        }
+        self.done_reason = chunk.done_reason.or(self.done_reason.take());
+        self.done |= chunk.done;
+        Ok(())
+    }
 }
 
 #[derive(Clone)]
@@ -62,6 +497,7 @@ pub struct OllamaClient {
     client: Client,
     base_url: String,
     semaphore: Arc<Semaphore>,
+    retry: RetryPolicy,
 }
 
 impl OllamaClient {
@@ -81,6 +517,45 @@ impl OllamaClient {
             client,
             base_url: base_url.to_string(),
             semaphore: Arc::new(Semaphore::new(MAX_CONCURRENT_REQUESTS)),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Retry generation calls per `policy`; `RetryPolicy::disabled()` turns
+    /// retrying off
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Make `call` until it succeeds, fails with anything but a transient
+    /// error, or runs out of attempts. The last error is returned as is.
+    async fn with_retry<T, F, Fut>(&self, mut call: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match call().await {
+                Err(e) if attempt < self.retry.max_attempts && is_transient(&e) => {
+                    let delay = self.retry.delay(attempt);
+                    log::warn!(
+                        "{} (attempt {} of {}); retrying in {}ms",
+                        e,
+                        attempt,
+                        self.retry.max_attempts,
+                        delay.as_millis()
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) if attempt > 1 => {
+                    log::warn!("Giving up on Ollama after {} attempts: {}", attempt, e);
+                    return Err(e);
+                }
+                result => return result,
+            }
         }
     }
     
@@ -103,30 +578,117 @@ impl OllamaClient {
             mirostat: 2,              // Better quality control
             mirostat_eta: 0.1,        
             mirostat_tau: 5.0,
+            seed: None,
+            stop: Vec::new(),
         }
     }
 
     // High-performance generate with connection pooling and concurrency control
     pub async fn generate_optimized(&self, model: &str, prompt: &str) -> Result<String> {
-        // Check if Ollama is running first
-        if let Err(e) = self.check_ollama_status().await {
-            return Err(anyhow!("Ollama server is not running or not accessible: {}", e));
-        }
-        
-        // Acquire semaphore permit for concurrency control
-        let _permit = self.semaphore.acquire().await.map_err(|e| anyhow!("Semaphore error: {}", e))?;
-        
-        // Try streaming first, fallback to non-streaming if needed
-        match self.generate_with_streaming(model, prompt).await {
-            Ok(response) => Ok(response),
-            Err(stream_error) => {
-                println!("⚠️ Streaming failed, trying non-streaming mode: {}", stream_error);
-                self.generate_without_streaming(model, prompt).await
+        self.generate_with_options(model, prompt, &GenerationOptions::default()).await
+    }
+
+    /// Generate like `generate_optimized`, with `options` overriding the
+    /// client's sampling settings in the request's `options` object
+    pub async fn generate_with_options(&self, model: &str, prompt: &str, options: &GenerationOptions) -> Result<String> {
+        self.generate_detailed(model, prompt, options).await.map(|generation| generation.text)
+    }
+
+    /// Generate like `generate_with_options`, also reporting why generation ended
+    pub async fn generate_detailed(&self, model: &str, prompt: &str, options: &GenerationOptions) -> Result<Generation> {
+        self.with_retry(|| async {
+            // Check if Ollama is running first
+            self.check_ollama_status().await?;
+
+            // Acquire semaphore permit for concurrency control
+            let _permit = self.semaphore.acquire().await.map_err(|e| anyhow!("Semaphore error: {}", e))?;
+
+            // Try streaming first, fallback to non-streaming if needed
+            match self.generate_with_streaming(model, prompt, options).await {
+                Ok(response) => Ok(response),
+                Err(stream_error) => {
+                    println!("⚠️ Streaming failed, trying non-streaming mode: {}", stream_error);
+                    self.generate_without_streaming(model, prompt, options).await
+                }
             }
-        }
+        })
+        .await
     }
     
-    // Check if Ollama server is running
+    /// Generate with `"stream": true` and `options` applied, forwarding each
+    /// token chunk to `chunks` as it arrives and returning the full response
+    pub async fn generate_chunked(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &GenerationOptions,
+        chunks: &tokio::sync::mpsc::UnboundedSender<String>,
+    ) -> Result<Generation> {
+        // Only getting the stream started is retried; once tokens have been
+        // forwarded a failure is final
+        let mut stream = self
+            .with_retry(|| async {
+                self.check_ollama_status().await?;
+                let mut stream = self.token_stream(model, prompt, options);
+                stream.start().await?;
+                Ok(stream)
+            })
+            .await?;
+
+        let mut full_response = String::new();
+        while let Some(token) = stream.next_token().await {
+            let token = token?;
+            full_response.push_str(&token);
+            // The receiver going away only means nobody is listening any more
+            let _ = chunks.send(token);
+        }
+
+        if full_response.is_empty() {
+            Err(anyhow!("Empty response from Ollama streaming"))
+        } else {
+            Ok(Generation {
+                text: full_response,
+                stop_reason: StopReason::from_done_reason(stream.done_reason.as_deref(), &options.stop),
+            })
+        }
+    }
+
+    /// Generate with `"stream": true`, yielding each token chunk as Ollama
+    /// sends it. The stream ends after the chunk marked `done: true`; any
+    /// error is yielded once and ends it too.
+    pub fn generate_stream(
+        &self,
+        model: &str,
+        prompt: &str,
+    ) -> impl futures_util::Stream<Item = std::result::Result<String, OllamaError>> + Send + 'static {
+        futures_util::stream::unfold(self.token_stream(model, prompt, &GenerationOptions::default()), |mut state| async move {
+            let item = state.next_token().await?;
+            Some((item, state))
+        })
+    }
+
+    /// A token stream for `prompt` that sends nothing until first polled
+    fn token_stream(&self, model: &str, prompt: &str, options: &GenerationOptions) -> TokenStream {
+        TokenStream {
+            client: self.clone(),
+            request: GenerateRequest {
+                model: model.to_string(),
+                prompt: prompt.to_string(),
+                stream: true,
+                options: OllamaClient::create_balanced_options().with_overrides(options),
+            },
+            response: None,
+            _permit: None,
+            pending: Vec::new(),
+            tokens: std::collections::VecDeque::new(),
+            error: None,
+            done_reason: None,
+            first_chunk: true,
+            done: false,
+        }
+    }
+
+    // Check if Ollama server is running; unreachable or busy is `OllamaError::Unavailable`
     async fn check_ollama_status(&self) -> Result<()> {
         let status_url = format!("{}/api/tags", self.base_url);
         
@@ -134,22 +696,24 @@ impl OllamaClient {
             Ok(Ok(response)) => {
                 if response.status().is_success() {
                     Ok(())
+                } else if response.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE {
+                    Err(OllamaError::Unavailable(format!("server returned status: {}", response.status())).into())
                 } else {
-                    Err(anyhow!("Ollama server returned status: {}", response.status()))
+                    Err(anyhow!("Ollama server is not accessible, it returned status: {}", response.status()))
                 }
             }
-            Ok(Err(e)) => Err(anyhow!("Failed to connect to Ollama: {}", e)),
-            Err(_) => Err(anyhow!("Timeout connecting to Ollama server")),
+            Ok(Err(e)) => Err(OllamaError::Unavailable(format!("failed to connect: {}", e)).into()),
+            Err(_) => Err(OllamaError::Unavailable("timeout connecting to the server".to_string()).into()),
         }
     }
     
     // Generate with streaming for better performance and timeout handling
-    async fn generate_with_streaming(&self, model: &str, prompt: &str) -> Result<String> {
+    async fn generate_with_streaming(&self, model: &str, prompt: &str, options: &GenerationOptions) -> Result<Generation> {
         let request = GenerateRequest {
             model: model.to_string(),
             prompt: prompt.to_string(),
             stream: true, // Enable streaming for better timeout handling
-            options: OllamaClient::create_ultra_fast_options(), // Use faster options for streaming
+            options: OllamaClient::create_ultra_fast_options().with_overrides(options), // Use faster options for streaming
         };
         
         println!("🧠 Using model: {} (streaming mode)", model);
@@ -164,10 +728,13 @@ impl OllamaClient {
         
         match timeout(Duration::from_secs(REQUEST_TIMEOUT), response_future).await {
             Ok(Ok(response)) => {
-                if response.status().is_success() {
-                    // For streaming, we need to read the response as text and parse it
-                    let response_text = response.text().await?;
+                let (status, content_type, response_text) = read_body(response).await?;
+                if let Some(error) = OllamaError::non_json(status.as_u16(), content_type.as_deref(), &response_text) {
+                    return Err(error.into());
+                }
+                if status.is_success() {
                     let mut full_response = String::new();
+                    let mut done_reason = None;
                     
                     // Parse streaming response (each line is a JSON object)
                     for line in response_text.lines() {
@@ -176,22 +743,29 @@ impl OllamaClient {
                         }
                         
                         if let Ok(stream_response) = serde_json::from_str::<StreamResponse>(line) {
+                            if let Some(error) = stream_response.error {
+                                return Err(OllamaError::Generation(error).into());
+                            }
                             full_response.push_str(&stream_response.response);
+                            done_reason = stream_response.done_reason.or(done_reason);
                         }
                     }
                     
                     if full_response.is_empty() {
                         Err(anyhow!("Empty response from Ollama streaming"))
                     } else {
-                        Ok(full_response)
+                        Ok(Generation {
+                            text: full_response,
+                            stop_reason: StopReason::from_done_reason(done_reason.as_deref(), &options.stop),
+                        })
                     }
                 } else {
-                    Err(anyhow!("HTTP error: {}", response.status()))
+                    Err(response_error("generate", status, content_type.as_deref(), response_text))
                 }
             }
             Ok(Err(e)) => {
                 println!("❌ HTTP request failed: {}", e);
-                Err(anyhow!("Request failed: {}", e))
+                Err(send_error(e))
             }
             Err(_) => {
                 println!("⏰ Request timeout after {} seconds (REQUEST_TIMEOUT: {}s). Consider increasing REQUEST_TIMEOUT or checking Ollama server performance.", REQUEST_TIMEOUT, REQUEST_TIMEOUT);
@@ -201,12 +775,12 @@ impl OllamaClient {
     }
     
     // Fallback to non-streaming mode
-    async fn generate_without_streaming(&self, model: &str, prompt: &str) -> Result<String> {
+    async fn generate_without_streaming(&self, model: &str, prompt: &str, options: &GenerationOptions) -> Result<Generation> {
         let request = GenerateRequest {
             model: model.to_string(),
             prompt: prompt.to_string(),
             stream: false,
-            options: OllamaClient::create_default_options(),
+            options: OllamaClient::create_default_options().with_overrides(options),
         };
         
         println!("🧠 Using model: {} (non-streaming mode)", model);
@@ -219,16 +793,15 @@ impl OllamaClient {
         
         match timeout(Duration::from_secs(REQUEST_TIMEOUT), response_future).await {
             Ok(Ok(response)) => {
-                if response.status().is_success() {
-                    let generate_response: GenerateResponse = response.json().await?;
-                    Ok(generate_response.response)
-                } else {
-                    Err(anyhow!("HTTP error: {}", response.status()))
-                }
+                let generate_response: GenerateResponse = read_json(response, "generate").await?;
+                Ok(Generation {
+                    stop_reason: StopReason::from_done_reason(generate_response.done_reason.as_deref(), &options.stop),
+                    text: generate_response.response,
+                })
             }
             Ok(Err(e)) => {
                 println!("❌ HTTP request failed: {}", e);
-                Err(anyhow!("Request failed: {}", e))
+                Err(send_error(e))
             }
             Err(_) => {
                 println!("⏰ Request timeout after {} seconds (REQUEST_TIMEOUT: {}s). Consider increasing REQUEST_TIMEOUT or checking Ollama server performance.", REQUEST_TIMEOUT, REQUEST_TIMEOUT);
@@ -256,6 +829,8 @@ impl OllamaClient {
             mirostat: 0,              // Disable for speed
             mirostat_eta: 0.0,        
             mirostat_tau: 0.0,
+            seed: None,
+            stop: Vec::new(),
         }
     }
     
@@ -278,6 +853,8 @@ impl OllamaClient {
             mirostat: 0,              // Disabled by default
             mirostat_eta: 0.0,        
             mirostat_tau: 0.0,
+            seed: None,
+            stop: Vec::new(),
         }
     }
 
@@ -305,22 +882,19 @@ impl OllamaClient {
                 mirostat: 0,
                 mirostat_eta: 0.0,
                 mirostat_tau: 0.0,
+                seed: None,
+                stop: Vec::new(),
             },
         };
         
         let response = self.client
-            .post(&format!("{}/api/generate", self.base_url))
+            .post(format!("{}/api/generate", self.base_url))
             .json(&request)
             .send()
             .await?;
         
-        if response.status().is_success() {
-            let generate_response: GenerateResponse = response.json().await?;
-            Ok(generate_response.response)
-        } else {
-            let error_text = response.text().await?;
-            Err(anyhow::anyhow!("Ollama API error: {}", error_text))
-        }
+        let generate_response: GenerateResponse = read_json(response, "generate").await?;
+        Ok(generate_response.response)
     }
 
     /// Chat with a model using the conversations endpoint
@@ -345,26 +919,41 @@ impl OllamaClient {
         });
         
         let response = self.client
-            .post(&format!("{}/api/chat", self.base_url))
+            .post(format!("{}/api/chat", self.base_url))
             .json(&request)
             .send()
             .await?;
         
-        if response.status().is_success() {
-            let chat_response: serde_json::Value = response.json().await?;
-            if let Some(message) = chat_response["message"]["content"].as_str() {
-                Ok(message.to_string())
-            } else {
-                Err(anyhow::anyhow!("Invalid response format from Ollama chat API"))
-            }
+        let chat_response: serde_json::Value = read_json(response, "chat").await?;
+        if let Some(message) = chat_response["message"]["content"].as_str() {
+            Ok(message.to_string())
         } else {
-            let error_text = response.text().await?;
-            Err(anyhow::anyhow!("Ollama chat API error: {}", error_text))
+            Err(anyhow::anyhow!("Invalid response format from Ollama chat API"))
         }
     }
 
 
 
+    /// Embed text using the embeddings endpoint
+    pub async fn embed(&self, model: &str, input: &str) -> Result<Vec<f32>> {
+        let request = serde_json::json!({
+            "model": model,
+            "prompt": input
+        });
+
+        let response = self.client
+            .post(format!("{}/api/embeddings", self.base_url))
+            .json(&request)
+            .send()
+            .await?;
+
+        let embed_response: serde_json::Value = read_json(response, "embeddings").await?;
+        embed_response["embedding"]
+            .as_array()
+            .map(|values| values.iter().filter_map(|v| v.as_f64()).map(|v| v as f32).collect())
+            .ok_or_else(|| anyhow::anyhow!("Invalid response format from Ollama embeddings API"))
+    }
+
     pub async fn generate_with_timing(&self, model: &str, prompt: &str) -> Result<(String, OllamaReceipt)> {
         let (mut receipt, start_instant) = OllamaReceipt::new(
             "Generate".to_string(),
@@ -392,21 +981,10 @@ impl OllamaClient {
                 .await
                 .map_err(|e| anyhow!("Failed to send request to Ollama: {}", e))?;
 
-            if !response.status().is_success() {
-                return Err(anyhow!(
-                    "Ollama API returned error status: {} - {}",
-                    response.status(),
-                    response.text().await.unwrap_or_default()
-                ));
-            }
-
-            let generate_response: GenerateResponse = response
-                .json()
-                .await
-                .map_err(|e| anyhow!("Failed to parse Ollama response: {}", e))?;
+            let generate_response: GenerateResponse = read_json(response, "generate").await?;
 
             if let Some(error) = generate_response.error {
-                return Err(anyhow!("Ollama returned error: {}", error));
+                return Err(anyhow::Error::from(OllamaError::Generation(error)));
             }
 
             Ok(generate_response.response)
@@ -449,19 +1027,14 @@ impl OllamaClient {
             .await
             .map_err(|e| anyhow!("Failed to send request to Ollama: {}", e))?;
 
-        if !response.status().is_success() {
-            return Err(anyhow!(
-                "Ollama API returned error status: {} - {}",
-                response.status(),
-                response.text().await.unwrap_or_default()
-            ));
-        }
-
+        let response = check_stream(response).await?;
         let mut text_chunks = Vec::new();
         
         // For now, use the non-streaming approach since bytes_stream is not available
-        let response_text = response.text().await
-            .map_err(|e| anyhow!("Failed to read response text: {}", e))?;
+        let (status, content_type, response_text) = read_body(response).await?;
+        if let Some(error) = OllamaError::non_json(status.as_u16(), content_type.as_deref(), &response_text) {
+            return Err(error.into());
+        }
         
         // Split the response into chunks (simulating streaming)
         let chunks: Vec<&str> = response_text.split('\n').collect();
@@ -496,4 +1069,140 @@ impl OllamaClient {
         let (chunks, _receipt) = self.generate_stream_with_timing(model, &prompt).await?;
         Ok(chunks)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{flaky_generate, serve};
+    use axum::{routing::post, Router};
+    use futures_util::StreamExt;
+
+    /// Serve a fake Ollama whose generate endpoint answers with `body`
+    async fn mock_generate(body: impl Into<String>) -> OllamaClient {
+        let body = body.into();
+        let app = Router::new().route("/api/generate", post(move || async move { body }));
+        OllamaClient::new(&serve(app).await, 5)
+    }
+
+    #[tokio::test]
+    async fn test_generation_options_override_the_request_options() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let store = seen.clone();
+        let app = Router::new()
+            .route("/api/tags", axum::routing::get(|| async { axum::Json(serde_json::json!({"models": []})) }))
+            .route("/api/generate", post(move |axum::Json(body): axum::Json<serde_json::Value>| {
+                store.lock().unwrap().push(body);
+                async { "{\"response\":\"Low risk\",\"done\":true}\n" }
+            }));
+        let client = OllamaClient::new(&serve(app).await, 5);
+
+        let options = GenerationOptions {
+            num_predict: Some(64),
+            stop: vec!["\n\n".to_string()],
+            ..GenerationOptions::deterministic(7)
+        };
+        assert_eq!(client.generate_with_options("llama3", "Assess", &options).await.unwrap(), "Low risk");
+        assert_eq!(client.generate_optimized("llama3", "Assess").await.unwrap(), "Low risk");
+
+        let seen = seen.lock().unwrap();
+        let overridden = &seen[0]["options"];
+        assert_eq!(overridden["temperature"], 0.0);
+        assert_eq!(overridden["top_k"], 1);
+        assert_eq!(overridden["num_predict"], 64);
+        assert_eq!(overridden["seed"], 7);
+        assert_eq!(overridden["stop"], serde_json::json!(["\n\n"]));
+        // Settings the caller left unset keep the client's tuning
+        assert_eq!(overridden["top_p"], seen[1]["options"]["top_p"]);
+        assert!(seen[1]["options"].get("seed").is_none() && seen[1]["options"].get("stop").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_generation_retries_a_busy_server_but_not_an_unknown_model() {
+        use std::sync::atomic::Ordering;
+
+        // Each attempt tries streaming, then non-streaming: two 503s per attempt
+        let (client, calls) = flaky_generate(503, 2).await;
+        assert_eq!(client.generate_optimized("llama3", "Assess").await.unwrap(), "Low risk");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let (client, calls) = flaky_generate(503, usize::MAX).await;
+        let error = client.generate_optimized("llama3", "Assess").await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<OllamaError>(), Some(OllamaError::Unavailable(m)) if m.contains("model is loading")));
+        assert_eq!(calls.load(Ordering::SeqCst), 6);
+
+        let (client, calls) = flaky_generate(404, usize::MAX).await;
+        let error = client.generate_optimized("missing", "Assess").await.unwrap_err();
+        assert!(error.to_string().contains("404"), "{}", error);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let (client, calls) = flaky_generate(503, usize::MAX).await;
+        let client = client.with_retry_policy(RetryPolicy::disabled());
+        assert!(client.generate_optimized("llama3", "Assess").await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_retry_delay_doubles_with_jitter_up_to_the_cap() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+        };
+        for (retry, full) in [(1, 100), (2, 200), (3, 300), (4, 300)] {
+            let delay = policy.delay(retry).as_millis();
+            assert!(delay >= full / 2 && delay <= full, "retry {} waited {}ms", retry, delay);
+        }
+    }
+
+    #[test]
+    fn test_done_reason_stop_is_ambiguous_once_stop_sequences_are_set() {
+        let stop = ["END OF REPORT".to_string()];
+        assert_eq!(StopReason::from_done_reason(Some("length"), &stop), Some(StopReason::Length));
+        assert_eq!(StopReason::from_done_reason(Some("stop"), &stop), Some(StopReason::Stop));
+        assert_eq!(StopReason::from_done_reason(Some("stop"), &[]), Some(StopReason::Natural));
+        assert_eq!(StopReason::from_done_reason(None, &stop), None);
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream_yields_tokens_until_done() {
+        let client = mock_generate(concat!(
+            "{\"response\":\"Revenue\",\"done\":false}\n",
+            "{\"response\":\" is up\",\"done\":false}\n",
+            "{\"response\":\"\",\"done\":true}\n",
+            "{\"response\":\"ignored\",\"done\":false}\n",
+        ))
+        .await;
+
+        let tokens: Vec<_> = client.generate_stream("llama3", "Summarize").collect().await;
+        assert_eq!(tokens, vec![Ok("Revenue".to_string()), Ok(" is up".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream_ends_with_a_mid_stream_error() {
+        let client = mock_generate(concat!(
+            "{\"response\":\"Revenue\",\"done\":false}\n",
+            "{\"error\":\"model ran out of memory\"}\n",
+            "{\"response\":\" is up\",\"done\":true}\n",
+        ))
+        .await;
+
+        let tokens: Vec<_> = client.generate_stream("llama3", "Summarize").collect().await;
+        assert_eq!(
+            tokens,
+            vec![
+                Ok("Revenue".to_string()),
+                Err(OllamaError::Generation("model ran out of memory".to_string())),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream_rejects_an_unterminated_oversized_line() {
+        let body = format!("{{\"response\":\"{}", "x".repeat(MAX_STREAM_LINE_BYTES + 1));
+        let client = mock_generate(body).await;
+
+        let tokens: Vec<_> = client.generate_stream("llama3", "Summarize").collect().await;
+        assert!(matches!(tokens.as_slice(), [Err(OllamaError::Decode(_))]));
+    }
+}
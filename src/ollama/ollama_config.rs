@@ -1,14 +1,224 @@
 use anyhow::{anyhow, Result};
 use std::env;
+use serde_json::{json, Value};
 use url::Url;
 
+use crate::api::domains::{AnalysisType, Domain, OutputFormat};
+use crate::ollama::model_metadata::{CapabilityMismatch, ModelMetadataTable};
+use crate::ollama::ollama_client::RetryPolicy;
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub ollama_base_url: String,
+    /// Further Ollama hosts to fail over to (`OLLAMA_BASE_URLS=http://a:11434,http://b:11434`);
+    /// when set it replaces `ollama_base_url`
+    pub ollama_base_urls: Vec<String>,
     pub ollama_model: String,
     pub max_timeout_seconds: u64,
+    /// Upper bound on the `timeout_seconds` an analysis request may ask for
+    pub max_request_timeout_seconds: u64,
+    /// How long before an integration's API key expires to start warning about it
+    pub key_expiry_warning_hours: u64,
     pub log_directory: String,
     pub max_prompt_length: usize,
+    /// Hard cap on the characters of any prompt sent to the model; a longer
+    /// prompt fails the analysis instead of being trimmed. Unset is unlimited.
+    pub max_prompt_chars: Option<usize>,
+    /// Minimum confidence `detect_domain` needs before committing to a specific domain
+    pub domain_detection_threshold: f64,
+    /// Word-overlap (Jaccard) similarity at which two insights or
+    /// recommendations count as duplicates; 0 (the default) disables deduplication
+    pub insight_dedup_threshold: f64,
+    /// Context window sizes per model family, used for prompt budgeting
+    pub model_metadata: ModelMetadataTable,
+    /// What to do when an analysis needs a capability (e.g. JSON mode) its
+    /// model lacks: `warn`, `substitute` a capable fallback model, or `reject`
+    pub capability_mismatch: CapabilityMismatch,
+    /// Maximum analyses a single user may run at once; further requests queue
+    pub max_concurrent_analyses_per_user: usize,
+    /// Most items of one batch analyzed at the same time, whatever the batch asks for
+    pub max_batch_concurrency: usize,
+    /// Queued analysis jobs above which new requests get `503`
+    pub queue_high_water_mark: usize,
+    /// Keep the exact prompt and raw model response for each result (off by default for privacy)
+    pub store_transcripts: bool,
+    /// Include a store sentinel round-trip in `/readyz`
+    pub readiness_check_store: bool,
+    /// Largest input file accepted, measured after decompression
+    pub max_input_bytes: u64,
+    /// Records read from the start of a Parquet input and analyzed as JSON
+    pub parquet_sample_rows: usize,
+    /// Most results kept per integration; unset keeps every result
+    pub max_results_per_integration: Option<usize>,
+    /// Most serialized result bytes kept per integration; unset is unlimited
+    pub max_result_bytes_per_integration: Option<u64>,
+    /// Evict an integration's oldest results when it exceeds its quota;
+    /// when off the quota only produces warnings
+    pub evict_over_quota: bool,
+    /// Reject a new integration whose name (ignoring case and surrounding
+    /// whitespace) one of the user's integrations already has
+    pub unique_integration_names: bool,
+    /// Reject input lacking any of its domain's required fields; when off
+    /// the missing fields are only reported on the result
+    pub reject_missing_fields: bool,
+    /// Reject request bodies carrying fields the endpoint does not know
+    /// (e.g. a misspelt `domian`) with a `400`; when off they are ignored
+    pub strict_request_fields: bool,
+    /// Serialized analysis output size above which the output's prose is
+    /// replaced by a model-written summary, keeping its structured fields;
+    /// unset keeps every output verbatim
+    pub max_output_chars: Option<usize>,
+    /// Directory the full text of a summarized output is written to, and
+    /// served from by the result's `full-output` route; when unset the full
+    /// text is dropped
+    pub output_archive_dir: Option<String>,
+    /// Directory integrations and results are persisted to as JSON files;
+    /// when unset they live only in memory and are lost on restart
+    pub store_directory: Option<String>,
+    /// Directory export jobs write their gzipped NDJSON files to
+    pub export_directory: String,
+    /// Key export download URLs are signed with (`EXPORT_SIGNING_KEY`); when
+    /// unset a random key is used, so URLs stop working on restart
+    pub export_signing_key: Option<String>,
+    /// How long an export download URL stays valid
+    pub export_url_ttl_seconds: u64,
+    /// Strip control characters and cut overlong tokens from model output
+    /// before storing it; off keeps the output exactly as generated
+    pub sanitize_output: bool,
+    /// Longest whitespace-separated token kept whole when sanitizing output
+    pub max_output_token_chars: usize,
+    /// Model completed results are embedded with (`EMBEDDING_MODEL`); unset
+    /// stores no embeddings
+    pub embedding_model: Option<String>,
+    /// Domains this deployment exposes (`ENABLED_DOMAINS=healthcare,generic`); empty means all
+    pub enabled_domains: Vec<String>,
+    /// Domain used when a request names none (`DEFAULT_DOMAIN`), instead of detecting it
+    pub default_domain: Option<String>,
+    /// Analysis type used when a request names none (`DEFAULT_ANALYSIS_TYPE`)
+    pub default_analysis_type: Option<AnalysisType>,
+    /// Analysis types this deployment refuses (`DISABLED_ANALYSIS_TYPES=prediction`)
+    pub disabled_analysis_types: Vec<AnalysisType>,
+    /// Output format used when a request asks for none (`DEFAULT_OUTPUT_FORMAT`)
+    pub default_output_format: Option<OutputFormat>,
+    pub clerk_secret_key: Option<String>,
+    /// Bearer token admin-only endpoints require (`ADMIN_TOKEN`); unset
+    /// closes them
+    pub admin_token: Option<String>,
+    pub clerk_publishable_key: Option<String>,
+    /// How long Clerk's JWKS signing keys are cached before being fetched again
+    pub clerk_jwks_ttl_seconds: u64,
+    /// Audience Clerk tokens must be issued for; unset skips the `aud` check
+    pub clerk_audience: Option<String>,
+    /// Clock skew allowed when checking a Clerk token's `exp` and `nbf`
+    pub clerk_clock_skew_seconds: u64,
+    /// Extra headers sent with outgoing webhooks (`WEBHOOK_HEADERS=Name=value,...`)
+    pub webhook_headers: Vec<(String, String)>,
+    /// Consecutive failures after which deliveries to a webhook destination pause
+    pub webhook_circuit_failure_threshold: u32,
+    /// How long a webhook destination's circuit stays open before a trial delivery
+    pub webhook_circuit_cooldown_seconds: u64,
+    /// Extra attempts at a webhook or callback after a 5xx or connection failure
+    pub webhook_retries: u32,
+    /// Wait before the first retry, doubling before each one after
+    pub webhook_backoff_ms: u64,
+    /// Let data sources, webhooks, callbacks and pushes point at
+    /// loopback/private addresses (off to prevent SSRF)
+    pub allow_private_data_sources: bool,
+    pub s3_access_key_id: Option<String>,
+    pub s3_secret_access_key: Option<String>,
+    /// OpenAI-compatible endpoint serving the models in `openai_compat_models`
+    pub openai_compat_base_url: Option<String>,
+    pub openai_compat_api_key: Option<String>,
+    /// Model name prefixes routed to the OpenAI-compatible endpoint
+    pub openai_compat_models: Vec<String>,
+    /// Models tried in order when an analysis's model keeps failing
+    pub fallback_models: Vec<String>,
+    /// Extra attempts on the same model after a failed generation
+    pub generation_retries: u32,
+    /// Retries and fallback attempts one analysis may spend in total, across
+    /// every model, beyond the first attempt of each prompt
+    pub retry_budget: u32,
+    /// Calls made to an unreachable or busy (503) Ollama before giving up,
+    /// counting the first; 1 disables retrying
+    pub ollama_retry_attempts: u32,
+    /// Wait before the first Ollama retry, doubling (with jitter) before each one after
+    pub ollama_retry_base_delay_ms: u64,
+    /// Seconds after a failure before an unhealthy Ollama host is tried
+    /// again ahead of the healthy ones, to find out whether it recovered
+    pub ollama_host_reprobe_seconds: u64,
+}
+
+/// Placeholder shown instead of secret values
+const MASKED: &str = "********";
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            ollama_base_url: "http://localhost:11434".to_string(),
+            ollama_base_urls: Vec::new(),
+            ollama_model: "auto".to_string(),
+            max_timeout_seconds: 300,
+            max_request_timeout_seconds: 900,
+            key_expiry_warning_hours: 168,
+            log_directory: "ollama_logs".to_string(),
+            max_prompt_length: 8192,
+            max_prompt_chars: None,
+            domain_detection_threshold: 0.5,
+            insight_dedup_threshold: 0.0,
+            model_metadata: ModelMetadataTable::new(),
+            capability_mismatch: CapabilityMismatch::Warn,
+            max_concurrent_analyses_per_user: 2,
+            max_batch_concurrency: 8,
+            queue_high_water_mark: 64,
+            store_transcripts: false,
+            readiness_check_store: true,
+            max_input_bytes: crate::api::input::DEFAULT_MAX_INPUT_BYTES,
+            parquet_sample_rows: crate::api::input::DEFAULT_PARQUET_SAMPLE_ROWS,
+            max_results_per_integration: None,
+            max_result_bytes_per_integration: None,
+            evict_over_quota: true,
+            unique_integration_names: false,
+            reject_missing_fields: false,
+            strict_request_fields: false,
+            max_output_chars: None,
+            output_archive_dir: None,
+            store_directory: None,
+            export_directory: "exports".to_string(),
+            export_signing_key: None,
+            export_url_ttl_seconds: 3600,
+            sanitize_output: false,
+            max_output_token_chars: 256,
+            embedding_model: None,
+            enabled_domains: Vec::new(),
+            default_domain: None,
+            default_analysis_type: None,
+            disabled_analysis_types: Vec::new(),
+            default_output_format: None,
+            clerk_secret_key: None,
+            admin_token: None,
+            clerk_publishable_key: None,
+            clerk_jwks_ttl_seconds: 3600,
+            clerk_audience: None,
+            clerk_clock_skew_seconds: 60,
+            webhook_headers: Vec::new(),
+            webhook_circuit_failure_threshold: 5,
+            webhook_circuit_cooldown_seconds: 60,
+            webhook_retries: 3,
+            webhook_backoff_ms: 500,
+            allow_private_data_sources: false,
+            s3_access_key_id: None,
+            s3_secret_access_key: None,
+            openai_compat_base_url: None,
+            openai_compat_api_key: None,
+            openai_compat_models: Vec::new(),
+            fallback_models: Vec::new(),
+            generation_retries: 0,
+            ollama_retry_attempts: 3,
+            ollama_retry_base_delay_ms: 500,
+            ollama_host_reprobe_seconds: 30,
+            retry_budget: 3,
+        }
+    }
 }
 
 impl Config {
@@ -44,6 +254,70 @@ impl Config {
         
         println!("🔧 Loaded MAX_TIMEOUT_SECONDS: {}", max_timeout_seconds);
 
+        let max_request_timeout_seconds = env::var("MAX_REQUEST_TIMEOUT_SECONDS")
+            .unwrap_or_else(|_| "900".to_string())
+            .parse::<u64>()
+            .map_err(|_| anyhow!("MAX_REQUEST_TIMEOUT_SECONDS must be a valid number"))?;
+
+        if max_request_timeout_seconds == 0 {
+            return Err(anyhow!("MAX_REQUEST_TIMEOUT_SECONDS must be at least 1"));
+        }
+
+        let key_expiry_warning_hours = env::var("KEY_EXPIRY_WARNING_HOURS")
+            .unwrap_or_else(|_| "168".to_string())
+            .parse::<u64>()
+            .map_err(|_| anyhow!("KEY_EXPIRY_WARNING_HOURS must be a valid number"))?;
+
+        let default_domain = match env::var("DEFAULT_DOMAIN") {
+            Ok(value) if !value.trim().is_empty() => Some(
+                Domain::from_str(value.trim())
+                    .ok_or_else(|| anyhow!("DEFAULT_DOMAIN must name a known domain, got '{}'", value))?
+                    .as_str()
+                    .to_string(),
+            ),
+            _ => None,
+        };
+
+        let default_analysis_type = match env::var("DEFAULT_ANALYSIS_TYPE") {
+            Ok(value) if !value.trim().is_empty() => Some(
+                AnalysisType::parse(&value)
+                    .ok_or_else(|| anyhow!("DEFAULT_ANALYSIS_TYPE must name a known analysis type, got '{}'", value))?,
+            ),
+            _ => None,
+        };
+
+        let disabled_analysis_types = env::var("DISABLED_ANALYSIS_TYPES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| {
+                AnalysisType::parse(value)
+                    .ok_or_else(|| anyhow!("DISABLED_ANALYSIS_TYPES must name known analysis types, got '{}'", value))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(default) = default_analysis_type.as_ref().filter(|t| disabled_analysis_types.contains(t)) {
+            return Err(anyhow!("DEFAULT_ANALYSIS_TYPE '{}' is listed in DISABLED_ANALYSIS_TYPES", default.as_str()));
+        }
+
+        let default_output_format = match env::var("DEFAULT_OUTPUT_FORMAT") {
+            Ok(value) if !value.trim().is_empty() => Some(
+                serde_json::from_value::<OutputFormat>(json!(value.trim().to_lowercase())).map_err(|_| {
+                    anyhow!(
+                        "DEFAULT_OUTPUT_FORMAT must be structured, narrative, bulletpoints, table or json, got '{}'",
+                        value
+                    )
+                })?,
+            ),
+            _ => None,
+        };
+
+        let capability_mismatch = match env::var("CAPABILITY_MISMATCH") {
+            Ok(value) if !value.trim().is_empty() => serde_json::from_value::<CapabilityMismatch>(json!(value.trim().to_lowercase()))
+                .map_err(|_| anyhow!("CAPABILITY_MISMATCH must be warn, substitute or reject, got '{}'", value))?,
+            _ => CapabilityMismatch::Warn,
+        };
+
         let log_directory = env::var("LOG_DIRECTORY")
             .unwrap_or_else(|_| "ollama_logs".to_string());
 
@@ -52,19 +326,426 @@ impl Config {
             .parse::<usize>()
             .map_err(|_| anyhow!("MAX_PROMPT_LENGTH must be a valid number"))?;
 
+        let max_prompt_chars = match env::var("MAX_PROMPT_CHARS") {
+            Ok(value) if !value.trim().is_empty() => Some(
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| anyhow!("MAX_PROMPT_CHARS must be a valid number"))?,
+            ),
+            _ => None,
+        };
+
+        let domain_detection_threshold = env::var("DOMAIN_DETECTION_THRESHOLD")
+            .unwrap_or_else(|_| "0.5".to_string())
+            .parse::<f64>()
+            .map_err(|_| anyhow!("DOMAIN_DETECTION_THRESHOLD must be a valid number"))?;
+
+        if !(0.0..=1.0).contains(&domain_detection_threshold) {
+            return Err(anyhow!("DOMAIN_DETECTION_THRESHOLD must be between 0.0 and 1.0"));
+        }
+
+        let insight_dedup_threshold = env::var("INSIGHT_DEDUP_THRESHOLD")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse::<f64>()
+            .map_err(|_| anyhow!("INSIGHT_DEDUP_THRESHOLD must be a valid number"))?;
+
+        if !(0.0..=1.0).contains(&insight_dedup_threshold) {
+            return Err(anyhow!("INSIGHT_DEDUP_THRESHOLD must be between 0.0 and 1.0"));
+        }
+
+        let max_concurrent_analyses_per_user = env::var("MAX_CONCURRENT_ANALYSES_PER_USER")
+            .unwrap_or_else(|_| "2".to_string())
+            .parse::<usize>()
+            .map_err(|_| anyhow!("MAX_CONCURRENT_ANALYSES_PER_USER must be a valid number"))?;
+
+        if max_concurrent_analyses_per_user == 0 {
+            return Err(anyhow!("MAX_CONCURRENT_ANALYSES_PER_USER must be at least 1"));
+        }
+
+        let max_batch_concurrency = env::var("MAX_BATCH_CONCURRENCY")
+            .unwrap_or_else(|_| "8".to_string())
+            .parse::<usize>()
+            .map_err(|_| anyhow!("MAX_BATCH_CONCURRENCY must be a valid number"))?;
+
+        if max_batch_concurrency == 0 {
+            return Err(anyhow!("MAX_BATCH_CONCURRENCY must be at least 1"));
+        }
+
+        let queue_high_water_mark = env::var("QUEUE_HIGH_WATER_MARK")
+            .unwrap_or_else(|_| "64".to_string())
+            .parse::<usize>()
+            .map_err(|_| anyhow!("QUEUE_HIGH_WATER_MARK must be a valid number"))?;
+
+        let max_input_bytes = match env::var("MAX_INPUT_BYTES") {
+            Ok(value) => value
+                .parse::<u64>()
+                .map_err(|_| anyhow!("MAX_INPUT_BYTES must be a valid number"))?,
+            Err(_) => crate::api::input::DEFAULT_MAX_INPUT_BYTES,
+        };
+
+        let parquet_sample_rows = match env::var("PARQUET_SAMPLE_ROWS") {
+            Ok(value) => value
+                .parse::<usize>()
+                .map_err(|_| anyhow!("PARQUET_SAMPLE_ROWS must be a valid number"))?,
+            Err(_) => crate::api::input::DEFAULT_PARQUET_SAMPLE_ROWS,
+        };
+        if parquet_sample_rows == 0 {
+            return Err(anyhow!("PARQUET_SAMPLE_ROWS must be at least 1"));
+        }
+
+        let max_results_per_integration = match env::var("MAX_RESULTS_PER_INTEGRATION") {
+            Ok(value) if !value.trim().is_empty() => Some(
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| anyhow!("MAX_RESULTS_PER_INTEGRATION must be a valid number"))?,
+            ),
+            _ => None,
+        };
+
+        let max_result_bytes_per_integration = match env::var("MAX_RESULT_BYTES_PER_INTEGRATION") {
+            Ok(value) if !value.trim().is_empty() => Some(
+                value
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|_| anyhow!("MAX_RESULT_BYTES_PER_INTEGRATION must be a valid number"))?,
+            ),
+            _ => None,
+        };
+
+        let max_output_chars = match env::var("MAX_OUTPUT_CHARS") {
+            Ok(value) if !value.trim().is_empty() => Some(
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| anyhow!("MAX_OUTPUT_CHARS must be a valid number"))?,
+            ),
+            _ => None,
+        };
+
+        let max_output_token_chars = env::var("MAX_OUTPUT_TOKEN_CHARS")
+            .unwrap_or_else(|_| "256".to_string())
+            .parse::<usize>()
+            .map_err(|_| anyhow!("MAX_OUTPUT_TOKEN_CHARS must be a valid number"))?;
+        if max_output_token_chars == 0 {
+            return Err(anyhow!("MAX_OUTPUT_TOKEN_CHARS must be at least 1"));
+        }
+
+        let generation_retries = env::var("GENERATION_RETRIES")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse::<u32>()
+            .map_err(|_| anyhow!("GENERATION_RETRIES must be a valid number"))?;
+
+        let ollama_retry_attempts = env::var("OLLAMA_RETRY_ATTEMPTS")
+            .unwrap_or_else(|_| "3".to_string())
+            .parse::<u32>()
+            .map_err(|_| anyhow!("OLLAMA_RETRY_ATTEMPTS must be a valid number"))?;
+        if ollama_retry_attempts == 0 {
+            return Err(anyhow!("OLLAMA_RETRY_ATTEMPTS must be at least 1"));
+        }
+
+        let ollama_retry_base_delay_ms = env::var("OLLAMA_RETRY_BASE_DELAY_MS")
+            .unwrap_or_else(|_| "500".to_string())
+            .parse::<u64>()
+            .map_err(|_| anyhow!("OLLAMA_RETRY_BASE_DELAY_MS must be a valid number"))?;
+
+        let ollama_host_reprobe_seconds = env::var("OLLAMA_HOST_REPROBE_SECONDS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse::<u64>()
+            .map_err(|_| anyhow!("OLLAMA_HOST_REPROBE_SECONDS must be a valid number"))?;
+
+        let clerk_jwks_ttl_seconds = env::var("CLERK_JWKS_TTL_SECONDS")
+            .unwrap_or_else(|_| "3600".to_string())
+            .parse::<u64>()
+            .map_err(|_| anyhow!("CLERK_JWKS_TTL_SECONDS must be a valid number"))?;
+
+        let clerk_clock_skew_seconds = env::var("CLERK_CLOCK_SKEW_SECONDS")
+            .unwrap_or_else(|_| "60".to_string())
+            .parse::<u64>()
+            .map_err(|_| anyhow!("CLERK_CLOCK_SKEW_SECONDS must be a valid number"))?;
+
+        let retry_budget = env::var("RETRY_BUDGET")
+            .unwrap_or_else(|_| "3".to_string())
+            .parse::<u32>()
+            .map_err(|_| anyhow!("RETRY_BUDGET must be a valid number"))?;
+
+        let webhook_headers = env::var("WEBHOOK_HEADERS")
+            .map(|headers| Self::parse_webhook_headers(&headers))
+            .unwrap_or_default();
+
+        let webhook_circuit_failure_threshold = env::var("WEBHOOK_CIRCUIT_FAILURE_THRESHOLD")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse::<u32>()
+            .map_err(|_| anyhow!("WEBHOOK_CIRCUIT_FAILURE_THRESHOLD must be a valid number"))?;
+
+        if webhook_circuit_failure_threshold == 0 {
+            return Err(anyhow!("WEBHOOK_CIRCUIT_FAILURE_THRESHOLD must be at least 1"));
+        }
+
+        let webhook_circuit_cooldown_seconds = env::var("WEBHOOK_CIRCUIT_COOLDOWN_SECONDS")
+            .unwrap_or_else(|_| "60".to_string())
+            .parse::<u64>()
+            .map_err(|_| anyhow!("WEBHOOK_CIRCUIT_COOLDOWN_SECONDS must be a valid number"))?;
+
+        let export_url_ttl_seconds = env::var("EXPORT_URL_TTL_SECONDS")
+            .unwrap_or_else(|_| "3600".to_string())
+            .parse::<u64>()
+            .map_err(|_| anyhow!("EXPORT_URL_TTL_SECONDS must be a valid number"))?;
+
+        let webhook_retries = env::var("WEBHOOK_RETRIES")
+            .unwrap_or_else(|_| "3".to_string())
+            .parse::<u32>()
+            .map_err(|_| anyhow!("WEBHOOK_RETRIES must be a valid number"))?;
+
+        let webhook_backoff_ms = env::var("WEBHOOK_BACKOFF_MS")
+            .unwrap_or_else(|_| "500".to_string())
+            .parse::<u64>()
+            .map_err(|_| anyhow!("WEBHOOK_BACKOFF_MS must be a valid number"))?;
+
+        let ollama_base_urls: Vec<String> = env::var("OLLAMA_BASE_URLS")
+            .map(|urls| {
+                urls.split(',')
+                    .map(|url| url.trim().to_string())
+                    .filter(|url| !url.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
         // Validate and secure the configuration
         Self::validate_config(&ollama_base_url, &ollama_model, 
                              max_timeout_seconds, max_prompt_length)?;
+        for url in &ollama_base_urls {
+            Self::validate_config(url, &ollama_model, max_timeout_seconds, max_prompt_length)?;
+        }
 
         Ok(Config {
             ollama_base_url,
+            ollama_base_urls,
             ollama_model,
             max_timeout_seconds,
+            max_request_timeout_seconds,
+            key_expiry_warning_hours,
             log_directory,
             max_prompt_length,
+            max_prompt_chars,
+            domain_detection_threshold,
+            insight_dedup_threshold,
+            model_metadata: ModelMetadataTable::from_env(),
+            capability_mismatch,
+            max_concurrent_analyses_per_user,
+            max_batch_concurrency,
+            queue_high_water_mark,
+            max_input_bytes,
+            parquet_sample_rows,
+            max_results_per_integration,
+            max_result_bytes_per_integration,
+            evict_over_quota: env::var("STORAGE_QUOTA_EVICT")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            unique_integration_names: env::var("UNIQUE_INTEGRATION_NAMES")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            reject_missing_fields: env::var("REJECT_MISSING_FIELDS")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            strict_request_fields: env::var("STRICT_REQUEST_FIELDS")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            max_output_chars,
+            output_archive_dir: env::var("OUTPUT_ARCHIVE_DIR").ok().filter(|v| !v.trim().is_empty()),
+            store_directory: env::var("STORE_DIRECTORY").ok().filter(|v| !v.trim().is_empty()),
+            export_directory: env::var("EXPORT_DIRECTORY")
+                .ok()
+                .filter(|v| !v.trim().is_empty())
+                .unwrap_or_else(|| "exports".to_string()),
+            export_signing_key: env::var("EXPORT_SIGNING_KEY").ok().filter(|v| !v.trim().is_empty()),
+            export_url_ttl_seconds,
+            sanitize_output: env::var("SANITIZE_OUTPUT")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            max_output_token_chars,
+            embedding_model: env::var("EMBEDDING_MODEL").ok().filter(|v| !v.trim().is_empty()),
+            readiness_check_store: env::var("READINESS_CHECK_STORE")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            store_transcripts: env::var("STORE_TRANSCRIPTS")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            default_domain,
+            default_analysis_type,
+            disabled_analysis_types,
+            default_output_format,
+            enabled_domains: env::var("ENABLED_DOMAINS")
+                .map(|domains| {
+                    domains
+                        .split(',')
+                        .map(|d| d.trim().to_lowercase())
+                        .filter(|d| !d.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            clerk_secret_key: env::var("CLERK_SECRET_KEY").ok(),
+            admin_token: env::var("ADMIN_TOKEN").ok().filter(|v| !v.trim().is_empty()),
+            clerk_publishable_key: env::var("CLERK_PUBLISHABLE_KEY").ok(),
+            clerk_jwks_ttl_seconds,
+            clerk_audience: env::var("CLERK_AUDIENCE").ok().filter(|v| !v.trim().is_empty()),
+            clerk_clock_skew_seconds,
+            webhook_headers,
+            webhook_circuit_failure_threshold,
+            webhook_circuit_cooldown_seconds,
+            webhook_retries,
+            webhook_backoff_ms,
+            allow_private_data_sources: env::var("ALLOW_PRIVATE_DATA_SOURCES")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            s3_access_key_id: env::var("AWS_ACCESS_KEY_ID").ok(),
+            s3_secret_access_key: env::var("AWS_SECRET_ACCESS_KEY").ok(),
+            openai_compat_base_url: env::var("OPENAI_COMPAT_BASE_URL").ok(),
+            openai_compat_api_key: env::var("OPENAI_COMPAT_API_KEY").ok(),
+            openai_compat_models: env::var("OPENAI_COMPAT_MODELS")
+                .map(|models| {
+                    models
+                        .split(',')
+                        .map(|m| m.trim().to_string())
+                        .filter(|m| !m.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            fallback_models: env::var("FALLBACK_MODELS")
+                .map(|models| {
+                    models
+                        .split(',')
+                        .map(|m| m.trim().to_string())
+                        .filter(|m| !m.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            generation_retries,
+            ollama_retry_attempts,
+            ollama_retry_base_delay_ms,
+            ollama_host_reprobe_seconds,
+            retry_budget,
         })
     }
 
+    fn parse_webhook_headers(headers: &str) -> Vec<(String, String)> {
+        headers
+            .split(',')
+            .filter_map(|entry| entry.split_once('='))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .filter(|(name, _)| !name.is_empty())
+            .collect()
+    }
+
+    /// Every Ollama host to use, in failover order
+    pub fn ollama_hosts(&self) -> Vec<String> {
+        if self.ollama_base_urls.is_empty() {
+            vec![self.ollama_base_url.clone()]
+        } else {
+            self.ollama_base_urls.clone()
+        }
+    }
+
+    /// How each Ollama client retries an unreachable or busy server
+    pub fn ollama_retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.ollama_retry_attempts,
+            base_delay: std::time::Duration::from_millis(self.ollama_retry_base_delay_ms),
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// The effective configuration as JSON, with secret values masked
+    pub fn masked(&self) -> Value {
+        let mask = |secret: &Option<String>| secret.as_ref().map(|_| MASKED);
+        let webhook_headers: serde_json::Map<String, Value> = self
+            .webhook_headers
+            .iter()
+            .map(|(name, _)| (name.clone(), json!(MASKED)))
+            .collect();
+
+        // Split into groups, as one literal this size outgrows the macro recursion limit
+        let model = json!({
+            "ollama_base_url": self.ollama_base_url,
+            "ollama_base_urls": self.ollama_base_urls,
+            "ollama_model": self.ollama_model,
+            "max_timeout_seconds": self.max_timeout_seconds,
+            "max_request_timeout_seconds": self.max_request_timeout_seconds,
+            "key_expiry_warning_hours": self.key_expiry_warning_hours,
+            "log_directory": self.log_directory,
+            "max_prompt_length": self.max_prompt_length,
+            "max_prompt_chars": self.max_prompt_chars,
+            "domain_detection_threshold": self.domain_detection_threshold,
+            "insight_dedup_threshold": self.insight_dedup_threshold,
+            "model_context_windows": self.model_metadata.entries(),
+            "default_context_window": self.model_metadata.default_context_window(),
+            "capability_mismatch": self.capability_mismatch,
+            "max_concurrent_analyses_per_user": self.max_concurrent_analyses_per_user,
+            "max_batch_concurrency": self.max_batch_concurrency,
+            "queue_high_water_mark": self.queue_high_water_mark,
+            "store_transcripts": self.store_transcripts,
+        });
+        let storage = json!({
+            "readiness_check_store": self.readiness_check_store,
+            "max_input_bytes": self.max_input_bytes,
+            "parquet_sample_rows": self.parquet_sample_rows,
+            "max_results_per_integration": self.max_results_per_integration,
+            "max_result_bytes_per_integration": self.max_result_bytes_per_integration,
+            "evict_over_quota": self.evict_over_quota,
+            "unique_integration_names": self.unique_integration_names,
+            "reject_missing_fields": self.reject_missing_fields,
+            "strict_request_fields": self.strict_request_fields,
+            "max_output_chars": self.max_output_chars,
+            "output_archive_dir": self.output_archive_dir,
+            "store_directory": self.store_directory,
+            "export_directory": self.export_directory,
+            "export_signing_key": mask(&self.export_signing_key),
+            "export_url_ttl_seconds": self.export_url_ttl_seconds,
+            "sanitize_output": self.sanitize_output,
+            "max_output_token_chars": self.max_output_token_chars,
+            "embedding_model": self.embedding_model,
+            "enabled_domains": self.enabled_domains,
+            "default_domain": self.default_domain,
+            "default_analysis_type": self.default_analysis_type.as_ref().map(|t| t.as_str()),
+            "disabled_analysis_types": self.disabled_analysis_types.iter().map(|t| t.as_str()).collect::<Vec<_>>(),
+            "default_output_format": self.default_output_format.as_ref().map(|f| f.as_str()),
+        });
+        let delivery = json!({
+            "clerk_secret_key": mask(&self.clerk_secret_key),
+            "admin_token": mask(&self.admin_token),
+            "clerk_publishable_key": mask(&self.clerk_publishable_key),
+            "clerk_jwks_ttl_seconds": self.clerk_jwks_ttl_seconds,
+            "clerk_audience": self.clerk_audience,
+            "clerk_clock_skew_seconds": self.clerk_clock_skew_seconds,
+            "webhook_headers": webhook_headers,
+            "webhook_circuit_failure_threshold": self.webhook_circuit_failure_threshold,
+            "webhook_circuit_cooldown_seconds": self.webhook_circuit_cooldown_seconds,
+            "webhook_retries": self.webhook_retries,
+            "webhook_backoff_ms": self.webhook_backoff_ms,
+            "allow_private_data_sources": self.allow_private_data_sources,
+            "s3_access_key_id": mask(&self.s3_access_key_id),
+            "s3_secret_access_key": mask(&self.s3_secret_access_key),
+            "openai_compat_base_url": self.openai_compat_base_url,
+            "openai_compat_api_key": mask(&self.openai_compat_api_key),
+            "openai_compat_models": self.openai_compat_models,
+            "fallback_models": self.fallback_models,
+            "generation_retries": self.generation_retries,
+            "ollama_retry_attempts": self.ollama_retry_attempts,
+            "ollama_retry_base_delay_ms": self.ollama_retry_base_delay_ms,
+            "ollama_host_reprobe_seconds": self.ollama_host_reprobe_seconds,
+            "retry_budget": self.retry_budget,
+        });
+
+        let mut masked = serde_json::Map::new();
+        for group in [model, storage, delivery] {
+            if let Value::Object(settings) = group {
+                masked.extend(settings);
+            }
+        }
+        Value::Object(masked)
+    }
+
     /// Automatically detect and select the best available Ollama model
     pub async fn auto_detect_model(&mut self) -> Result<()> {
         // If model is not set to auto, keep the current value
@@ -211,8 +892,7 @@ impl Config {
 
         // Remove potentially dangerous characters/sequences
         let sanitized = input
-            .replace('\0', "") // Remove null bytes
-            .replace('\x1b', "") // Remove escape sequences
+            .replace(['\0', '\x1b'], "") // Remove null bytes and escape sequences
             .trim()
             .to_string();
 
@@ -224,4 +904,29 @@ impl Config {
     }
 
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_masked_config_hides_secrets() {
+        let config = Config {
+            ollama_model: "llama3".to_string(),
+            clerk_secret_key: Some("sk_live_abc123".to_string()),
+            webhook_headers: vec![("Authorization".to_string(), "Bearer hook-token".to_string())],
+            ..Config::default()
+        };
+
+        let masked = config.masked();
+        let rendered = masked.to_string();
+
+        assert_eq!(masked["ollama_model"], "llama3");
+        assert_eq!(masked["clerk_secret_key"], MASKED);
+        assert_eq!(masked["webhook_headers"]["Authorization"], MASKED);
+        assert!(masked["s3_secret_access_key"].is_null());
+        assert!(!rendered.contains("sk_live_abc123"));
+        assert!(!rendered.contains("hook-token"));
+    }
+}
@@ -127,6 +127,7 @@ impl OllamaReceipt {
         if !log_file.exists() {
             let _file = OpenOptions::new()
                 .create(true)
+                .truncate(false)
                 .write(true)
                 .open(&log_file)?;
             
@@ -207,7 +208,7 @@ impl OllamaReceipt {
             
             // When we have a complete JSON object
             if brace_count == 0 && current_json.trim().starts_with('{') {
-                match serde_json::from_str::<OllamaReceipt>(&current_json.trim()) {
+                match serde_json::from_str::<OllamaReceipt>(current_json.trim()) {
                     Ok(receipt) => receipts.push(receipt),
                     Err(e) => {
                         log::warn!("Failed to parse receipt: {}", e);
@@ -0,0 +1,767 @@
+//! Fixtures shared by the unit tests: stub model providers, in-memory
+//! stores and local mock servers
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    http::{header, StatusCode},
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use serde_json::{json, Value};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::api::attachments::Attachment;
+use crate::api::data_profiles::DataProfile;
+use crate::api::presets::AnalysisPreset;
+use crate::api::input::InputFormat;
+use crate::api::integration_manager::{
+    AnalysisRequest, AnalysisSession, AnalysisStatus, CreateIntegrationRequest, ExchangeSizes, Integration,
+    IntegrationAnalysisResult, IntegrationConfig, IntegrationManager, NotificationSettings, SizeCounts, SystemType,
+    WebhookEvents, OUTPUT_SUMMARY_PROMPT,
+};
+use crate::api::prompt_templates::PromptTemplate;
+use crate::api::store::IntegrationStore;
+use crate::api::webhook_payload::PayloadDetail;
+use crate::ollama::conversation_manager::ConversationMessage;
+use crate::ollama::{Config, LlmProvider, OllamaClient, OllamaProvider, ProviderRegistry, RetryPolicy};
+
+/// Serve `app` on a local port, returning its base URL
+pub async fn serve(app: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{}", addr)
+}
+
+/// JSON bodies received by a mock server
+pub type ReceivedRequests = Arc<Mutex<Vec<Value>>>;
+
+/// Serve a fake Ollama answering every generate call with `reply`,
+/// returning its base URL and the request bodies it received
+pub async fn mock_ollama_server(reply: &'static str) -> (String, ReceivedRequests) {
+    let calls: ReceivedRequests = Arc::default();
+    let recorded = calls.clone();
+    let app = Router::new()
+        .route("/api/tags", get(|| async { Json(json!({"models": []})) }))
+        .route("/api/generate", post(move |Json(body): Json<Value>| {
+            recorded.lock().unwrap().push(body);
+            async move { format!("{}\n", json!({"response": reply, "done": true})) }
+        }));
+    (serve(app).await, calls)
+}
+
+/// Providers backed by a fake Ollama answering every generate call with `reply`
+pub async fn mock_ollama(reply: &'static str) -> (ProviderRegistry, ReceivedRequests) {
+    let (url, calls) = mock_ollama_server(reply).await;
+    let client = OllamaClient::new(&url, 5);
+    (ProviderRegistry::new(Arc::new(OllamaProvider::new(client))), calls)
+}
+
+/// Serve a fake Ollama whose generate endpoint answers `status` to the
+/// first `failures` calls and a completed response after, counting calls
+pub async fn flaky_generate(status: u16, failures: usize) -> (OllamaClient, Arc<AtomicUsize>) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let app = Router::new()
+        .route("/api/tags", get(|| async { Json(json!({"models": []})) }))
+        .route("/api/generate", post(move || {
+            let call = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if call < failures {
+                    (StatusCode::from_u16(status).unwrap(), Json(json!({"error": "model is loading"})))
+                } else {
+                    (StatusCode::OK, Json(json!({"response": "Low risk", "done": true})))
+                }
+            }
+        }));
+    let client = OllamaClient::new(&serve(app).await, 5).with_retry_policy(RetryPolicy {
+        base_delay: Duration::from_millis(1),
+        ..RetryPolicy::default()
+    });
+    (client, calls)
+}
+
+/// Serve a webhook receiver that records every JSON body posted to its `/hook` URL
+pub async fn mock_receiver() -> (String, ReceivedRequests) {
+    let received: ReceivedRequests = Arc::default();
+    let store = received.clone();
+    let app = Router::new().route("/hook", post(move |Json(body): Json<Value>| {
+        store.lock().unwrap().push(body);
+        async { StatusCode::OK }
+    }));
+    (format!("{}/hook", serve(app).await), received)
+}
+
+/// Serve a webhook answering with each of `statuses` in turn, then 200
+pub async fn scripted_receiver(statuses: Vec<u16>) -> (String, Arc<AtomicUsize>) {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = hits.clone();
+    let app = Router::new().route("/hook", post(move || {
+        let hit = counter.fetch_add(1, Ordering::SeqCst);
+        let status = statuses.get(hit).copied().unwrap_or(200);
+        async move { StatusCode::from_u16(status).unwrap() }
+    }));
+    (format!("{}/hook", serve(app).await), hits)
+}
+
+/// Answers every prompt with `reply` and records the prompts
+pub struct RecordingProvider {
+    pub reply: &'static str,
+    pub prompts: Mutex<Vec<String>>,
+}
+
+impl RecordingProvider {
+    pub fn new(reply: &'static str) -> Self {
+        Self { reply, prompts: Mutex::default() }
+    }
+}
+
+#[async_trait::async_trait]
+impl LlmProvider for RecordingProvider {
+    fn name(&self) -> &'static str {
+        "recording"
+    }
+
+    async fn generate(&self, _model: &str, prompt: &str) -> anyhow::Result<String> {
+        self.prompts.lock().unwrap().push(prompt.to_string());
+        Ok(self.reply.to_string())
+    }
+
+    async fn chat(&self, model: &str, _messages: &[ConversationMessage]) -> anyhow::Result<String> {
+        self.generate(model, "").await
+    }
+
+    async fn embed(&self, _model: &str, _input: &str) -> anyhow::Result<Vec<f32>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Replies with the prompt it was given, so a test can measure it
+pub struct EchoProvider;
+
+#[async_trait::async_trait]
+impl LlmProvider for EchoProvider {
+    fn name(&self) -> &'static str {
+        "echo"
+    }
+
+    async fn generate(&self, _model: &str, prompt: &str) -> anyhow::Result<String> {
+        Ok(prompt.to_string())
+    }
+
+    async fn chat(&self, model: &str, _messages: &[ConversationMessage]) -> anyhow::Result<String> {
+        self.generate(model, "").await
+    }
+
+    async fn embed(&self, _model: &str, _input: &str) -> anyhow::Result<Vec<f32>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Provider that answers after a fixed delay
+pub struct SlowProvider(pub Duration);
+
+#[async_trait::async_trait]
+impl LlmProvider for SlowProvider {
+    fn name(&self) -> &'static str {
+        "slow"
+    }
+
+    async fn generate(&self, _model: &str, _prompt: &str) -> anyhow::Result<String> {
+        tokio::time::sleep(self.0).await;
+        Ok("Finished late".to_string())
+    }
+
+    async fn chat(&self, model: &str, _messages: &[ConversationMessage]) -> anyhow::Result<String> {
+        self.generate(model, "").await
+    }
+
+    async fn embed(&self, _model: &str, _input: &str) -> anyhow::Result<Vec<f32>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Replies with a fixed sequence of chunks
+pub struct ChunkedProvider(pub &'static [&'static str]);
+
+#[async_trait::async_trait]
+impl LlmProvider for ChunkedProvider {
+    fn name(&self) -> &'static str {
+        "chunked"
+    }
+
+    async fn generate(&self, _model: &str, _prompt: &str) -> anyhow::Result<String> {
+        Ok(self.0.concat())
+    }
+
+    async fn generate_streaming(&self, _model: &str, _prompt: &str, chunks: &UnboundedSender<String>) -> anyhow::Result<String> {
+        for chunk in self.0 {
+            chunks.send(chunk.to_string()).unwrap();
+        }
+        Ok(self.0.concat())
+    }
+
+    async fn chat(&self, model: &str, _messages: &[ConversationMessage]) -> anyhow::Result<String> {
+        self.generate(model, "").await
+    }
+
+    async fn embed(&self, _model: &str, _input: &str) -> anyhow::Result<Vec<f32>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Streams `partial`, stalls for `stall` and then breaks on its first call,
+/// and streams `chunks` whole on every later one
+pub struct BrokenStreamProvider {
+    pub partial: &'static str,
+    pub chunks: &'static [&'static str],
+    pub stall: Duration,
+    pub calls: AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl LlmProvider for BrokenStreamProvider {
+    fn name(&self) -> &'static str {
+        "broken-stream"
+    }
+
+    async fn generate(&self, _model: &str, _prompt: &str) -> anyhow::Result<String> {
+        Ok(self.chunks.concat())
+    }
+
+    async fn generate_streaming(&self, _model: &str, _prompt: &str, chunks: &UnboundedSender<String>) -> anyhow::Result<String> {
+        if self.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            chunks.send(self.partial.to_string()).unwrap();
+            tokio::time::sleep(self.stall).await;
+            anyhow::bail!("connection reset mid-stream");
+        }
+        for chunk in self.chunks {
+            chunks.send(chunk.to_string()).unwrap();
+        }
+        Ok(self.chunks.concat())
+    }
+
+    async fn chat(&self, model: &str, _messages: &[ConversationMessage]) -> anyhow::Result<String> {
+        self.generate(model, "").await
+    }
+
+    async fn embed(&self, _model: &str, _input: &str) -> anyhow::Result<Vec<f32>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Provider that records the most generate calls it saw in flight at once
+#[derive(Default)]
+pub struct ConcurrencyProbe {
+    pub in_flight: AtomicUsize,
+    pub peak: AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl LlmProvider for ConcurrencyProbe {
+    fn name(&self) -> &'static str {
+        "probe"
+    }
+
+    async fn generate(&self, _model: &str, _prompt: &str) -> anyhow::Result<String> {
+        let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        Ok(r#"{"summary": "ok"}"#.to_string())
+    }
+
+    async fn chat(&self, model: &str, _messages: &[ConversationMessage]) -> anyhow::Result<String> {
+        self.generate(model, "").await
+    }
+
+    async fn embed(&self, _model: &str, _input: &str) -> anyhow::Result<Vec<f32>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Provider that fails for every model except `healthy`, counting calls per model
+pub struct FlakyProvider {
+    pub healthy: &'static str,
+    pub calls: Mutex<Vec<String>>,
+}
+
+impl FlakyProvider {
+    pub fn new(healthy: &'static str) -> Self {
+        Self { healthy, calls: Mutex::default() }
+    }
+}
+
+#[async_trait::async_trait]
+impl LlmProvider for FlakyProvider {
+    fn name(&self) -> &'static str {
+        "flaky"
+    }
+
+    async fn generate(&self, model: &str, _prompt: &str) -> anyhow::Result<String> {
+        self.calls.lock().unwrap().push(model.to_string());
+        if model == self.healthy {
+            Ok("Recovered on a fallback".to_string())
+        } else {
+            Err(anyhow::anyhow!("{} is unavailable", model))
+        }
+    }
+
+    async fn chat(&self, model: &str, _messages: &[ConversationMessage]) -> anyhow::Result<String> {
+        self.generate(model, "").await
+    }
+
+    async fn embed(&self, _model: &str, _input: &str) -> anyhow::Result<Vec<f32>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Replies with `long` to analysis prompts and a short summary to summary prompts
+pub struct VerboseProvider {
+    pub long: String,
+}
+
+#[async_trait::async_trait]
+impl LlmProvider for VerboseProvider {
+    fn name(&self) -> &'static str {
+        "verbose"
+    }
+
+    async fn generate(&self, _model: &str, prompt: &str) -> anyhow::Result<String> {
+        if prompt.starts_with(OUTPUT_SUMMARY_PROMPT) {
+            Ok("Churn rose in EMEA; retention offers are advised.".to_string())
+        } else {
+            Ok(self.long.clone())
+        }
+    }
+
+    async fn chat(&self, model: &str, _messages: &[ConversationMessage]) -> anyhow::Result<String> {
+        self.generate(model, "").await
+    }
+
+    async fn embed(&self, _model: &str, _input: &str) -> anyhow::Result<Vec<f32>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Embeds every input as its length, recording the inputs
+#[derive(Default)]
+pub struct EmbeddingProvider {
+    pub embedded: Mutex<Vec<String>>,
+}
+
+#[async_trait::async_trait]
+impl LlmProvider for EmbeddingProvider {
+    fn name(&self) -> &'static str {
+        "embedding"
+    }
+
+    async fn generate(&self, _model: &str, _prompt: &str) -> anyhow::Result<String> {
+        Ok(r#"{"summary": "Stock is low"}"#.to_string())
+    }
+
+    async fn chat(&self, model: &str, _messages: &[ConversationMessage]) -> anyhow::Result<String> {
+        self.generate(model, "").await
+    }
+
+    async fn embed(&self, _model: &str, input: &str) -> anyhow::Result<Vec<f32>> {
+        self.embedded.lock().unwrap().push(input.to_string());
+        Ok(vec![input.len() as f32])
+    }
+}
+
+/// Store whose writes fail while `failing` is set
+#[derive(Debug, Default)]
+pub struct FlakyStore {
+    pub failing: AtomicBool,
+    pub saved_results: Mutex<Vec<String>>,
+    pub sentinels: Mutex<HashMap<String, String>>,
+}
+
+#[async_trait::async_trait]
+impl IntegrationStore for FlakyStore {
+    async fn save_integration(&self, _integration: &Integration) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn load_all(&self) -> Result<Vec<Integration>, String> {
+        Ok(Vec::new())
+    }
+
+    async fn delete_integration(&self, _integration_id: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn save_result(&self, result: &IntegrationAnalysisResult) -> Result<(), String> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err("disk unavailable".to_string());
+        }
+        self.saved_results.lock().unwrap().push(result.id.clone());
+        Ok(())
+    }
+
+    async fn load_results(&self, _integration_id: &str) -> Result<Vec<IntegrationAnalysisResult>, String> {
+        Ok(Vec::new())
+    }
+
+    async fn delete_result(&self, _integration_id: &str, _result_id: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn save_attachment(&self, _result_id: &str, _attachment: &Attachment) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn load_attachment(&self, _result_id: &str, _name: &str) -> Result<Option<Attachment>, String> {
+        Ok(None)
+    }
+
+    async fn save_prompt_template(&self, _template: &PromptTemplate) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn load_prompt_templates(&self) -> Result<Vec<PromptTemplate>, String> {
+        Ok(Vec::new())
+    }
+
+    async fn save_data_profile(&self, _profile: &DataProfile) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn load_data_profiles(&self) -> Result<Vec<DataProfile>, String> {
+        Ok(Vec::new())
+    }
+
+    async fn delete_data_profile(&self, _name: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn save_preset(&self, _preset: &AnalysisPreset) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn load_presets(&self) -> Result<Vec<AnalysisPreset>, String> {
+        Ok(Vec::new())
+    }
+
+    async fn delete_preset(&self, _name: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn save_session(&self, _session: &AnalysisSession) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn load_sessions(&self) -> Result<Vec<AnalysisSession>, String> {
+        Ok(Vec::new())
+    }
+
+    async fn delete_session(&self, _integration_id: &str, _session_id: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn write_sentinel(&self, key: &str, value: &str) -> Result<(), String> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err("disk unavailable".to_string());
+        }
+        self.sentinels.lock().unwrap().insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    async fn read_sentinel(&self, key: &str) -> Result<Option<String>, String> {
+        Ok(self.sentinels.lock().unwrap().get(key).cloned())
+    }
+
+    async fn remove_sentinel(&self, key: &str) -> Result<(), String> {
+        self.sentinels.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+/// A store that keeps everything saved to it, for restart tests
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    pub integrations: Mutex<Vec<Integration>>,
+    pub results: Mutex<Vec<IntegrationAnalysisResult>>,
+    pub attachments: Mutex<HashMap<(String, String), Attachment>>,
+    pub prompt_templates: Mutex<Vec<PromptTemplate>>,
+    pub data_profiles: Mutex<Vec<DataProfile>>,
+    pub presets: Mutex<Vec<AnalysisPreset>>,
+    pub sessions: Mutex<Vec<AnalysisSession>>,
+}
+
+#[async_trait::async_trait]
+impl IntegrationStore for MemoryStore {
+    async fn save_integration(&self, integration: &Integration) -> Result<(), String> {
+        let mut integrations = self.integrations.lock().unwrap();
+        integrations.retain(|i| i.id != integration.id);
+        integrations.push(integration.clone());
+        Ok(())
+    }
+
+    async fn load_all(&self) -> Result<Vec<Integration>, String> {
+        Ok(self.integrations.lock().unwrap().clone())
+    }
+
+    async fn delete_integration(&self, integration_id: &str) -> Result<(), String> {
+        self.integrations.lock().unwrap().retain(|i| i.id != integration_id);
+        let mut results = self.results.lock().unwrap();
+        let removed: Vec<String> =
+            results.iter().filter(|r| r.integration_id == integration_id).map(|r| r.id.clone()).collect();
+        results.retain(|r| r.integration_id != integration_id);
+        self.attachments.lock().unwrap().retain(|(result_id, _), _| !removed.contains(result_id));
+        self.sessions.lock().unwrap().retain(|s| s.integration_id != integration_id);
+        Ok(())
+    }
+
+    async fn save_result(&self, result: &IntegrationAnalysisResult) -> Result<(), String> {
+        let mut results = self.results.lock().unwrap();
+        results.retain(|r| r.id != result.id);
+        results.push(result.clone());
+        Ok(())
+    }
+
+    async fn load_results(&self, integration_id: &str) -> Result<Vec<IntegrationAnalysisResult>, String> {
+        Ok(self.results.lock().unwrap().iter().filter(|r| r.integration_id == integration_id).cloned().collect())
+    }
+
+    async fn delete_result(&self, _integration_id: &str, result_id: &str) -> Result<(), String> {
+        self.results.lock().unwrap().retain(|r| r.id != result_id);
+        self.attachments.lock().unwrap().retain(|(stored_for, _), _| stored_for != result_id);
+        Ok(())
+    }
+
+    async fn save_attachment(&self, result_id: &str, attachment: &Attachment) -> Result<(), String> {
+        let key = (result_id.to_string(), attachment.info.name.clone());
+        self.attachments.lock().unwrap().insert(key, attachment.clone());
+        Ok(())
+    }
+
+    async fn load_attachment(&self, result_id: &str, name: &str) -> Result<Option<Attachment>, String> {
+        Ok(self.attachments.lock().unwrap().get(&(result_id.to_string(), name.to_string())).cloned())
+    }
+
+    async fn save_prompt_template(&self, template: &PromptTemplate) -> Result<(), String> {
+        let mut templates = self.prompt_templates.lock().unwrap();
+        templates.retain(|t| t.domain != template.domain);
+        templates.push(template.clone());
+        Ok(())
+    }
+
+    async fn load_prompt_templates(&self) -> Result<Vec<PromptTemplate>, String> {
+        Ok(self.prompt_templates.lock().unwrap().clone())
+    }
+
+    async fn save_data_profile(&self, profile: &DataProfile) -> Result<(), String> {
+        let mut profiles = self.data_profiles.lock().unwrap();
+        profiles.retain(|p| p.name != profile.name);
+        profiles.push(profile.clone());
+        Ok(())
+    }
+
+    async fn load_data_profiles(&self) -> Result<Vec<DataProfile>, String> {
+        Ok(self.data_profiles.lock().unwrap().clone())
+    }
+
+    async fn delete_data_profile(&self, name: &str) -> Result<(), String> {
+        self.data_profiles.lock().unwrap().retain(|p| p.name != name);
+        Ok(())
+    }
+
+    async fn save_preset(&self, preset: &AnalysisPreset) -> Result<(), String> {
+        let mut presets = self.presets.lock().unwrap();
+        presets.retain(|p| p.name != preset.name);
+        presets.push(preset.clone());
+        Ok(())
+    }
+
+    async fn load_presets(&self) -> Result<Vec<AnalysisPreset>, String> {
+        Ok(self.presets.lock().unwrap().clone())
+    }
+
+    async fn delete_preset(&self, name: &str) -> Result<(), String> {
+        self.presets.lock().unwrap().retain(|p| p.name != name);
+        Ok(())
+    }
+
+    async fn save_session(&self, session: &AnalysisSession) -> Result<(), String> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|s| (&s.integration_id, &s.id) != (&session.integration_id, &session.id));
+        sessions.push(session.clone());
+        Ok(())
+    }
+
+    async fn load_sessions(&self) -> Result<Vec<AnalysisSession>, String> {
+        Ok(self.sessions.lock().unwrap().clone())
+    }
+
+    async fn delete_session(&self, integration_id: &str, session_id: &str) -> Result<(), String> {
+        self.sessions.lock().unwrap().retain(|s| (s.integration_id.as_str(), s.id.as_str()) != (integration_id, session_id));
+        Ok(())
+    }
+
+    async fn write_sentinel(&self, _key: &str, _value: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn read_sentinel(&self, _key: &str) -> Result<Option<String>, String> {
+        Ok(None)
+    }
+
+    async fn remove_sentinel(&self, _key: &str) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Providers for route tests that never reach a model
+pub fn offline_providers() -> ProviderRegistry {
+    ProviderRegistry::from_config(&Config::default())
+}
+
+/// An integration request with every notification and option off
+pub fn sample_request(name: &str) -> CreateIntegrationRequest {
+    CreateIntegrationRequest {
+        name: name.to_string(),
+        system_type: SystemType::RestApi,
+        webhook_url: None,
+        webhook_secret: None,
+        configuration: IntegrationConfig {
+            auto_analyze: false,
+            analysis_domain: None,
+            ai_model: None,
+            allowed_models: Vec::new(),
+            notification_settings: NotificationSettings {
+                email_notifications: false,
+                webhook_notifications: false,
+                dashboard_alerts: false,
+                real_time_updates: false,
+                webhook_events: WebhookEvents::default(),
+                payload: PayloadDetail::Full,
+            },
+            data_filters: Vec::new(),
+            redact_fields: Vec::new(),
+            insight_paths: Vec::new(),
+            recommendation_paths: Vec::new(),
+            sampling: None,
+            rest_source: None,
+            correlation_key: None,
+            data_profile: None,
+            attachments: Vec::new(),
+        },
+        expires_at: None,
+        tags: Vec::new(),
+    }
+}
+
+/// A stored-looking result with no deliveries, sizes or metadata
+pub fn sample_result(integration_id: &str, status: AnalysisStatus) -> IntegrationAnalysisResult {
+    IntegrationAnalysisResult {
+        id: uuid::Uuid::new_v4().to_string(),
+        integration_id: integration_id.to_string(),
+        system_name: "test".to_string(),
+        data_source: "external_system".to_string(),
+        analysis_result: serde_json::json!({"summary": "ok"}),
+        status,
+        created_at: Utc::now(),
+        processing_time: 0.1,
+        insights_count: 0,
+        recommendations_count: 0,
+        sequence: 0,
+        input_truncated: false,
+        output_truncated: false,
+        input_chars: SizeCounts::default(),
+        output_items: SizeCounts::default(),
+        deliveries: Vec::new(),
+        exchange_sizes: ExchangeSizes::default(),
+        low_confidence: false,
+        storage_warning: None,
+        model_attempts: 0,
+        stop_reason: None,
+        prompt_template: None,
+        missing_fields: Vec::new(),
+        capability_warnings: Vec::new(),
+        domain: None,
+        metadata: serde_json::Map::new(),
+        correlation_id: None,
+        embedding: None,
+        embedding_model: None,
+        detected_language: None,
+        attachments: Vec::new(),
+    }
+}
+
+/// Append `results` as if stored, without running any analysis
+pub async fn seed_results(manager: &IntegrationManager, integration_id: &str, results: Vec<IntegrationAnalysisResult>) {
+    let mut stored = manager.analysis_results.write().await;
+    let stored = stored.entry(integration_id.to_string()).or_default();
+    // Numbered after the stored ones, as store_result would
+    let mut sequence = stored.last().map_or(0, |r| r.sequence);
+    for mut result in results {
+        sequence += 1;
+        result.sequence = sequence;
+        manager.counters.replace(None, Some(&result));
+        manager.storage_usage.update(&result);
+        stored.push(result);
+    }
+}
+
+/// A manager allowed to deliver to the loopback receivers the tests start
+pub fn local_delivery_manager() -> IntegrationManager {
+    IntegrationManager::with_config(Config { allow_private_data_sources: true, ..Config::default() })
+}
+
+/// A monitoring request for `data` carrying the integration's key
+pub fn analysis_request(integration: &Integration, data: serde_json::Value) -> AnalysisRequest {
+    AnalysisRequest {
+        integration_id: integration.id.clone(),
+        api_key: integration.api_key.clone(),
+        data,
+        domain: Some("monitoring".to_string()),
+        model: Some("llama3".to_string()),
+        callback_url: None,
+        sampling: None,
+        windowing: None,
+        reasoning: None,
+        fields: None,
+        timeout_seconds: None,
+        analysis_type: None,
+        output_format: None,
+        preset: None,
+        prompt: None,
+        raw_prompt: false,
+        allow_empty_input: false,
+        stream_callback: false,
+        locale: None,
+        currency: None,
+        compute_stats: false,
+        focus: None,
+        input_format: InputFormat::default(),
+        baseline_result_id: None,
+        min_confidence: None,
+        fail_on_low_confidence: false,
+        fallback_models: None,
+        output_formats: Vec::new(),
+        stop: Vec::new(),
+        generation_options: None,
+        session_id: None,
+        language: None,
+        metadata: serde_json::Map::new(),
+    }
+}
+
+/// The whole body of `response` as text
+pub async fn body_string(response: Response) -> String {
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+/// `request` carrying the integration's API key
+pub fn with_key(request: axum::http::request::Builder, integration: &Integration) -> axum::http::request::Builder {
+    request.header(header::AUTHORIZATION, format!("Bearer {}", integration.api_key))
+}